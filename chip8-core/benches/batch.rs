@@ -0,0 +1,68 @@
+//! Frames per second across many machines: a `Vec<Chip8>` looped over one by
+//! one against `Chip8Batch` on one thread and on all of them.
+//!
+//! Run with `cargo bench -p chip8-core --bench batch`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use chip8_core::batch::Chip8Batch;
+use chip8_core::emu::Chip8;
+use chip8_core::platform::Platform;
+
+const ROM: &[u8] = include_bytes!("../../roms/ghosts.ch8");
+
+// Frames each machine runs before the measurement starts, to get past the title
+const WARM_UP_FRAMES: usize = 60;
+
+fn machines(count: usize) -> Vec<Chip8> {
+    (0..count)
+        .map(|n| {
+            let mut chip8 = Chip8::new();
+            chip8.set_seed(n as u64);
+            chip8.load_rom_bytes(ROM);
+            for _ in 0..WARM_UP_FRAMES {
+                chip8.run_frame();
+            }
+            chip8
+        })
+        .collect()
+}
+
+fn batch(count: usize) -> Chip8Batch {
+    let mut batch = Chip8Batch::new(count, Platform::default(), 0);
+    batch.load_rom(ROM);
+    for _ in 0..WARM_UP_FRAMES {
+        batch.run_frame();
+    }
+    batch
+}
+
+fn frames(c: &mut Criterion) {
+    let single_thread = rayon::ThreadPoolBuilder::new()
+        .num_threads(1)
+        .build()
+        .unwrap();
+    let mut group = c.benchmark_group("frame");
+    for count in [64, 1024, 8192] {
+        group.throughput(Throughput::Elements(count as u64));
+
+        let mut chip8s = machines(count);
+        group.bench_function(BenchmarkId::new("chip8-loop", count), |b| {
+            b.iter(|| chip8s.iter_mut().for_each(Chip8::run_frame))
+        });
+
+        let mut one_thread = batch(count);
+        group.bench_function(BenchmarkId::new("batch-1-thread", count), |b| {
+            b.iter(|| single_thread.install(|| one_thread.run_frame()))
+        });
+
+        let mut parallel = batch(count);
+        group.bench_function(BenchmarkId::new("batch-parallel", count), |b| {
+            b.iter(|| parallel.run_frame())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, frames);
+criterion_main!(benches);
@@ -0,0 +1,49 @@
+//! Instructions per second on each bundled ROM, with the decoded-instruction
+//! cache and without it (decoding every fetch, as before the cache).
+//!
+//! Run with `cargo bench -p chip8-core --bench interpreter`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use chip8_core::emu::Chip8;
+
+const ROMS: [(&str, &[u8]); 7] = [
+    (
+        "1-chip8-logo",
+        include_bytes!("../../roms/1-chip8-logo.ch8"),
+    ),
+    ("3-corax+", include_bytes!("../../roms/3-corax+.ch8")),
+    ("4-flags", include_bytes!("../../roms/4-flags.ch8")),
+    ("5-quirks", include_bytes!("../../roms/5-quirks.ch8")),
+    ("6-keypad", include_bytes!("../../roms/6-keypad.ch8")),
+    ("IBM_Logo", include_bytes!("../../roms/IBM_Logo.ch8")),
+    ("ghosts", include_bytes!("../../roms/ghosts.ch8")),
+];
+
+// Enough instructions per frame that the frame overhead hardly shows
+const CYCLES_PER_FRAME: usize = 10_000;
+
+fn instructions(c: &mut Criterion) {
+    let mut group = c.benchmark_group("instructions");
+    group.throughput(Throughput::Elements(CYCLES_PER_FRAME as u64));
+    for (name, rom) in ROMS {
+        for cached in [true, false] {
+            let mut chip8 = Chip8::new();
+            // Frames would otherwise end at the first sprite drawn
+            let mut quirks = chip8.quirks();
+            quirks.display_wait = false;
+            chip8.set_quirks(quirks);
+            chip8.set_cycles_per_frame(CYCLES_PER_FRAME);
+            chip8.memory_mut().set_decode_cache(cached);
+            chip8.load_rom_bytes(rom);
+            let variant = if cached { "cached" } else { "uncached" };
+            group.bench_function(BenchmarkId::new(variant, name), |b| {
+                b.iter(|| chip8.run_frame())
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, instructions);
+criterion_main!(benches);
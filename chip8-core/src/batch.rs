@@ -0,0 +1,754 @@
+//! Many machines run side by side, for reinforcement learning and fuzzing.
+//!
+//! `Chip8Batch` keeps each part of the machine state in an array of its own
+//! (structure of arrays) instead of holding a `Chip8` per instance, so a
+//! frame works through densely packed state, and with the `parallel` feature
+//! the instances are split across threads. They run the same instructions as
+//! `Chip8` does, and `machine` turns any one back into a `Chip8`.
+
+use crate::display::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use crate::emu::{
+    Chip8, RomTooLarge, DEFAULT_CYCLES_PER_FRAME, KEY_WAIT_BEEP, MAX_ROM_SIZE, ROM_START,
+    STACK_SIZE, STACK_START,
+};
+use crate::keyboard::{KeyWait, Keyboard};
+use crate::memory::MEMORY_SIZE;
+use crate::platform::{Platform, Quirks};
+use crate::rng::Rng;
+use crate::timing::{vip_cycles, Timing, VIP_INTERPRETER_CYCLES_PER_FRAME};
+
+// Instances one thread takes on at a time
+#[cfg(feature = "parallel")]
+const CHUNK: usize = 64;
+
+/// One bit per pixel, a row per `u64` with x = 0 in the top bit
+type Screen = [u64; DISPLAY_HEIGHT];
+
+#[derive(Clone, Copy)]
+struct Stack {
+    entries: [u16; STACK_SIZE],
+    len: usize,
+}
+
+impl Stack {
+    fn new() -> Self {
+        Stack {
+            entries: [0; STACK_SIZE],
+            len: STACK_START,
+        }
+    }
+
+    fn push(&mut self, address: u16) {
+        if self.len < STACK_SIZE {
+            self.entries[self.len] = address;
+            self.len += 1;
+        }
+    }
+
+    fn pop(&mut self) -> u16 {
+        if self.len == 0 {
+            return 0;
+        }
+        self.len -= 1;
+        self.entries[self.len]
+    }
+}
+
+/// What every instance in a batch runs with
+#[derive(Debug, Clone, Copy)]
+struct Settings {
+    quirks: Quirks,
+    timing: Timing,
+    cycles_per_frame: usize,
+}
+
+/// A batch of machines running the same ROM with the same settings
+pub struct Chip8Batch {
+    platform: Platform,
+    settings: Settings,
+    rom: Vec<u8>,
+    seeds: Vec<u64>,
+    memory: Vec<u8>,
+    registers: Vec<[u8; 16]>,
+    pc: Vec<u16>,
+    i: Vec<u16>,
+    stacks: Vec<Stack>,
+    delay_timers: Vec<u8>,
+    sound_timers: Vec<u8>,
+    keyboards: Vec<Keyboard>,
+    rngs: Vec<Rng>,
+    screens: Vec<Screen>,
+    frames: Vec<u64>,
+    cycle_debts: Vec<u32>,
+}
+
+impl Chip8Batch {
+    /// `count` machines with no ROM loaded, instance n seeded with `seed + n`
+    pub fn new(count: usize, platform: Platform, seed: u64) -> Self {
+        let mut batch = Chip8Batch {
+            platform,
+            settings: Settings {
+                quirks: platform.quirks(),
+                timing: Timing::default(),
+                cycles_per_frame: DEFAULT_CYCLES_PER_FRAME,
+            },
+            rom: Vec::new(),
+            seeds: (0..count as u64).map(|n| seed.wrapping_add(n)).collect(),
+            memory: vec![0; count * MEMORY_SIZE],
+            registers: vec![[0; 16]; count],
+            pc: vec![0; count],
+            i: vec![0; count],
+            stacks: vec![Stack::new(); count],
+            delay_timers: vec![0; count],
+            sound_timers: vec![0; count],
+            keyboards: vec![Keyboard::new(); count],
+            rngs: vec![Rng::new(0); count],
+            screens: vec![[0; DISPLAY_HEIGHT]; count],
+            frames: vec![0; count],
+            cycle_debts: vec![0; count],
+        };
+        for n in 0..count {
+            batch.power_on(n);
+        }
+        batch
+    }
+
+    pub fn len(&self) -> usize {
+        self.pc.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.settings.quirks
+    }
+
+    /// Override individual behaviours of the platform, for every instance
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.settings.quirks = quirks;
+    }
+
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.settings.timing = timing;
+        self.cycle_debts.fill(0);
+    }
+
+    pub fn set_cycles_per_frame(&mut self, cycles: usize) {
+        self.settings.cycles_per_frame = cycles;
+    }
+
+    /// Power every instance on afresh with `rom` loaded. Panics if it is
+    /// longer than `MAX_ROM_SIZE`; `try_load_rom` reports that instead.
+    pub fn load_rom(&mut self, rom: &[u8]) {
+        if let Err(e) = self.try_load_rom(rom) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Like `load_rom`, but a ROM that does not fit is an error and leaves
+    /// every instance untouched
+    pub fn try_load_rom(&mut self, rom: &[u8]) -> Result<(), RomTooLarge> {
+        if rom.len() > MAX_ROM_SIZE {
+            return Err(RomTooLarge { len: rom.len() });
+        }
+        self.rom = rom.to_vec();
+        for n in 0..self.len() {
+            self.power_on(n);
+        }
+        Ok(())
+    }
+
+    /// Power instance `n` on afresh with the ROM loaded and a new seed
+    pub fn reset(&mut self, n: usize, seed: u64) {
+        self.seeds[n] = seed;
+        self.power_on(n);
+    }
+
+    fn power_on(&mut self, n: usize) {
+        let memory = &mut self.memory[n * MEMORY_SIZE..(n + 1) * MEMORY_SIZE];
+        memory.fill(0);
+        memory[ROM_START..ROM_START + self.rom.len()].copy_from_slice(&self.rom);
+        self.registers[n] = [0; 16];
+        self.pc[n] = ROM_START as u16;
+        self.i[n] = 0;
+        self.stacks[n] = Stack::new();
+        self.delay_timers[n] = 0;
+        self.sound_timers[n] = 0;
+        self.keyboards[n] = Keyboard::new();
+        self.rngs[n] = Rng::new(self.seeds[n]);
+        self.screens[n] = [0; DISPLAY_HEIGHT];
+        self.frames[n] = 0;
+        self.cycle_debts[n] = 0;
+    }
+
+    /// Hold down the keys of instance `n` set in `keys`, bit k standing for
+    /// key k, and let go of the rest
+    pub fn set_keys(&mut self, n: usize, keys: u16) {
+        let keyboard = &mut self.keyboards[n];
+        for key in 0..16 {
+            keyboard.set_key(key, keys & 1 << key != 0);
+        }
+    }
+
+    /// Run one 60 Hz frame on every instance
+    pub fn run_frame(&mut self) {
+        let settings = self.settings;
+        run_frames(self.columns(), &settings);
+    }
+
+    pub fn pc(&self, n: usize) -> u16 {
+        self.pc[n]
+    }
+
+    pub fn i(&self, n: usize) -> u16 {
+        self.i[n]
+    }
+
+    pub fn registers(&self, n: usize) -> &[u8; 16] {
+        &self.registers[n]
+    }
+
+    pub fn memory(&self, n: usize) -> &[u8] {
+        &self.memory[n * MEMORY_SIZE..(n + 1) * MEMORY_SIZE]
+    }
+
+    /// Number of frames instance `n` has run since power on
+    pub fn frame(&self, n: usize) -> u64 {
+        self.frames[n]
+    }
+
+    pub fn sound_active(&self, n: usize) -> bool {
+        self.sound_timers[n] > 0
+    }
+
+    pub fn get_pixel(&self, n: usize, x: usize, y: usize) -> u8 {
+        (self.screens[n][y] >> (DISPLAY_WIDTH - 1 - x) & 1) as u8
+    }
+
+    /// Instance `n` as a machine of its own
+    pub fn machine(&self, n: usize) -> Chip8 {
+        let mut chip8 = Chip8::new();
+        chip8.set_platform(self.platform);
+        chip8.set_quirks(self.settings.quirks);
+        chip8.set_timing(self.settings.timing);
+        chip8.set_cycles_per_frame(self.settings.cycles_per_frame);
+        chip8.set_seed(self.seeds[n]);
+        chip8.registers = self.registers[n];
+        let stack = &self.stacks[n];
+        chip8.stack = stack.entries[..stack.len].to_vec();
+        chip8.memory.write_slice_at(0, self.memory(n));
+        for y in 0..DISPLAY_HEIGHT {
+            for x in 0..DISPLAY_WIDTH {
+                chip8.display.update_pixel(x, y, self.get_pixel(n, x, y));
+            }
+        }
+        chip8.pc = self.pc[n];
+        chip8.i = self.i[n];
+        chip8.delay_timer = self.delay_timers[n];
+        chip8.sound_timer = self.sound_timers[n];
+        chip8.key = self.keyboards[n].clone();
+        chip8.rng = self.rngs[n];
+        chip8.frame = self.frames[n];
+        chip8.cycle_debt = self.cycle_debts[n];
+        chip8
+    }
+
+    /// Replace instance `n` with the state of `chip8`. The batch keeps its
+    /// own platform, quirks and timing.
+    pub fn set_machine(&mut self, n: usize, chip8: &Chip8) {
+        self.seeds[n] = chip8.seed();
+        self.registers[n] = chip8.registers;
+        let stack = &chip8.stack[chip8.stack.len().saturating_sub(STACK_SIZE)..];
+        self.stacks[n].entries[..stack.len()].copy_from_slice(stack);
+        self.stacks[n].len = stack.len();
+        self.memory[n * MEMORY_SIZE..(n + 1) * MEMORY_SIZE]
+            .copy_from_slice(chip8.memory.read_slice_at(0, MEMORY_SIZE));
+        let display = chip8.display();
+        for (y, row) in self.screens[n].iter_mut().enumerate() {
+            *row = (0..DISPLAY_WIDTH).fold(0, |row, x| row << 1 | display.get_pixel(x, y) as u64);
+        }
+        self.pc[n] = chip8.pc;
+        self.i[n] = chip8.i;
+        self.delay_timers[n] = chip8.delay_timer;
+        self.sound_timers[n] = chip8.sound_timer;
+        self.keyboards[n] = chip8.key.clone();
+        self.rngs[n] = chip8.rng;
+        self.frames[n] = chip8.frame;
+        self.cycle_debts[n] = chip8.cycle_debt;
+    }
+
+    fn columns(&mut self) -> Columns<'_> {
+        Columns {
+            memory: &mut self.memory,
+            registers: &mut self.registers,
+            pc: &mut self.pc,
+            i: &mut self.i,
+            stacks: &mut self.stacks,
+            delay_timers: &mut self.delay_timers,
+            sound_timers: &mut self.sound_timers,
+            keyboards: &mut self.keyboards,
+            rngs: &mut self.rngs,
+            screens: &mut self.screens,
+            frames: &mut self.frames,
+            cycle_debts: &mut self.cycle_debts,
+        }
+    }
+}
+
+/// The state of a run of instances, to be split between threads
+struct Columns<'a> {
+    memory: &'a mut [u8],
+    registers: &'a mut [[u8; 16]],
+    pc: &'a mut [u16],
+    i: &'a mut [u16],
+    stacks: &'a mut [Stack],
+    delay_timers: &'a mut [u8],
+    sound_timers: &'a mut [u8],
+    keyboards: &'a mut [Keyboard],
+    rngs: &'a mut [Rng],
+    screens: &'a mut [Screen],
+    frames: &'a mut [u64],
+    cycle_debts: &'a mut [u32],
+}
+
+impl<'a> Columns<'a> {
+    fn len(&self) -> usize {
+        self.pc.len()
+    }
+
+    #[cfg(feature = "parallel")]
+    fn split_at(self, mid: usize) -> (Columns<'a>, Columns<'a>) {
+        let (memory_a, memory_b) = self.memory.split_at_mut(mid * MEMORY_SIZE);
+        let (registers_a, registers_b) = self.registers.split_at_mut(mid);
+        let (pc_a, pc_b) = self.pc.split_at_mut(mid);
+        let (i_a, i_b) = self.i.split_at_mut(mid);
+        let (stacks_a, stacks_b) = self.stacks.split_at_mut(mid);
+        let (delay_a, delay_b) = self.delay_timers.split_at_mut(mid);
+        let (sound_a, sound_b) = self.sound_timers.split_at_mut(mid);
+        let (keyboards_a, keyboards_b) = self.keyboards.split_at_mut(mid);
+        let (rngs_a, rngs_b) = self.rngs.split_at_mut(mid);
+        let (screens_a, screens_b) = self.screens.split_at_mut(mid);
+        let (frames_a, frames_b) = self.frames.split_at_mut(mid);
+        let (debts_a, debts_b) = self.cycle_debts.split_at_mut(mid);
+        (
+            Columns {
+                memory: memory_a,
+                registers: registers_a,
+                pc: pc_a,
+                i: i_a,
+                stacks: stacks_a,
+                delay_timers: delay_a,
+                sound_timers: sound_a,
+                keyboards: keyboards_a,
+                rngs: rngs_a,
+                screens: screens_a,
+                frames: frames_a,
+                cycle_debts: debts_a,
+            },
+            Columns {
+                memory: memory_b,
+                registers: registers_b,
+                pc: pc_b,
+                i: i_b,
+                stacks: stacks_b,
+                delay_timers: delay_b,
+                sound_timers: sound_b,
+                keyboards: keyboards_b,
+                rngs: rngs_b,
+                screens: screens_b,
+                frames: frames_b,
+                cycle_debts: debts_b,
+            },
+        )
+    }
+
+    fn lane(&mut self, n: usize) -> Lane<'_> {
+        Lane {
+            memory: &mut self.memory[n * MEMORY_SIZE..(n + 1) * MEMORY_SIZE],
+            v: &mut self.registers[n],
+            pc: &mut self.pc[n],
+            i: &mut self.i[n],
+            stack: &mut self.stacks[n],
+            delay_timer: &mut self.delay_timers[n],
+            sound_timer: &mut self.sound_timers[n],
+            keyboard: &mut self.keyboards[n],
+            rng: &mut self.rngs[n],
+            screen: &mut self.screens[n],
+            frame: &mut self.frames[n],
+            cycle_debt: &mut self.cycle_debts[n],
+        }
+    }
+}
+
+fn run_frames(mut columns: Columns, settings: &Settings) {
+    #[cfg(feature = "parallel")]
+    if columns.len() > CHUNK {
+        let mid = columns.len() / 2;
+        let (a, b) = columns.split_at(mid);
+        rayon::join(|| run_frames(a, settings), || run_frames(b, settings));
+        return;
+    }
+    for n in 0..columns.len() {
+        columns.lane(n).run_frame(settings);
+    }
+}
+
+/// One instance's state, borrowed out of the columns
+struct Lane<'a> {
+    memory: &'a mut [u8],
+    v: &'a mut [u8; 16],
+    pc: &'a mut u16,
+    i: &'a mut u16,
+    stack: &'a mut Stack,
+    delay_timer: &'a mut u8,
+    sound_timer: &'a mut u8,
+    keyboard: &'a mut Keyboard,
+    rng: &'a mut Rng,
+    screen: &'a mut Screen,
+    frame: &'a mut u64,
+    cycle_debt: &'a mut u32,
+}
+
+impl Lane<'_> {
+    // Follows `Chip8::run_frame_until`
+    fn run_frame(&mut self, settings: &Settings) {
+        let budget = VIP_INTERPRETER_CYCLES_PER_FRAME;
+        let mut executed = 0;
+        let mut spent = *self.cycle_debt;
+        loop {
+            let more = match settings.timing {
+                Timing::Fixed => executed < settings.cycles_per_frame,
+                Timing::CosmacVip => spent < budget,
+            };
+            if !more {
+                break;
+            }
+            let (opcode, pc, registers) = (self.fetch(), *self.pc, *self.v);
+            self.execute(opcode, &settings.quirks);
+            executed += 1;
+            if settings.timing == Timing::CosmacVip {
+                spent += vip_cycles(opcode, &registers, *self.pc == pc.wrapping_add(4));
+            }
+            if settings.quirks.display_wait && opcode & 0xF000 == 0xD000 {
+                spent = budget;
+                break;
+            }
+        }
+        *self.cycle_debt = match settings.timing {
+            Timing::Fixed => 0,
+            Timing::CosmacVip => spent.saturating_sub(budget),
+        };
+        *self.delay_timer = self.delay_timer.saturating_sub(1);
+        *self.sound_timer = self.sound_timer.saturating_sub(1);
+        *self.frame += 1;
+    }
+
+    fn byte(&self, address: usize) -> u8 {
+        self.memory[address % MEMORY_SIZE]
+    }
+
+    fn fetch(&self) -> u16 {
+        let pc = *self.pc as usize;
+        u16::from_be_bytes([self.byte(pc), self.byte(pc + 1)])
+    }
+
+    fn skip_if(&mut self, condition: bool) {
+        if condition {
+            *self.pc = self.pc.wrapping_add(2);
+        }
+    }
+
+    // Follows `Chip8::emulate_cycle`, down to the opcodes it leaves out
+    fn execute(&mut self, opcode: u16, quirks: &Quirks) {
+        let x = (opcode >> 8 & 0xF) as usize;
+        let y = (opcode >> 4 & 0xF) as usize;
+        let n = opcode & 0xF;
+        let nn = opcode as u8;
+        let nnn = opcode & 0xFFF;
+        *self.pc = self.pc.wrapping_add(2);
+
+        match opcode >> 12 {
+            0x0 => match n {
+                0x0 => *self.screen = [0; DISPLAY_HEIGHT],
+                0xE => *self.pc = self.stack.pop(),
+                _ => {}
+            },
+            0x1 => *self.pc = nnn,
+            0x2 => {
+                self.stack.push(*self.pc);
+                *self.pc = nnn;
+            }
+            0x3 => self.skip_if(self.v[x] == nn),
+            0x4 => self.skip_if(self.v[x] != nn),
+            0x5 => self.skip_if(self.v[x] == self.v[y]),
+            0x6 => self.v[x] = nn,
+            0x7 => self.v[x] = self.v[x].wrapping_add(nn),
+            0x8 => self.alu(x, y, n, quirks),
+            0x9 => self.skip_if(self.v[x] != self.v[y]),
+            0xA => *self.i = nnn,
+            0xB => {
+                let offset = if quirks.jump_uses_vx { x } else { 0 };
+                *self.pc = (nnn + self.v[offset] as u16) & 0xFFF;
+            }
+            0xC => self.v[x] = self.rng.next_byte() & nn,
+            0xD => self.draw(x, y, n as usize, quirks),
+            0xE => {
+                let pressed = self.keyboard.key_is_pressed(self.v[x] & 0xF);
+                match nn {
+                    0x9E => self.skip_if(pressed),
+                    0xA1 => self.skip_if(!pressed),
+                    _ => {}
+                }
+            }
+            _ => match nn {
+                0x07 => self.v[x] = *self.delay_timer,
+                0x0A => self.wait_for_key(x, quirks),
+                0x15 => *self.delay_timer = self.v[x],
+                0x18 => *self.sound_timer = self.v[x],
+                0x1E => *self.i = self.i.wrapping_add(self.v[x] as u16),
+                0x33 => {
+                    let vx = self.v[x];
+                    let i = *self.i as usize;
+                    for (k, digit) in [vx / 100, vx / 10 % 10, vx % 10].into_iter().enumerate() {
+                        self.memory[(i + k) % MEMORY_SIZE] = digit;
+                    }
+                }
+                0x55 | 0x65 => {
+                    let i = *self.i as usize;
+                    for r in 0..=x {
+                        let address = (i + r) % MEMORY_SIZE;
+                        if nn == 0x55 {
+                            self.memory[address] = self.v[r];
+                        } else {
+                            self.v[r] = self.memory[address];
+                        }
+                    }
+                    if quirks.load_store_increments_i {
+                        *self.i = self.i.wrapping_add(x as u16 + 1);
+                    }
+                }
+                _ => {}
+            },
+        }
+    }
+
+    fn alu(&mut self, x: usize, y: usize, n: u16, quirks: &Quirks) {
+        let v = &mut *self.v;
+        let (vx, vy) = (v[x], v[y]);
+        match n {
+            0x0 => v[x] = vy,
+            0x1..=0x3 => {
+                v[x] = match n {
+                    0x1 => vx | vy,
+                    0x2 => vx & vy,
+                    _ => vx ^ vy,
+                };
+                if quirks.vf_reset {
+                    v[0xF] = 0;
+                }
+            }
+            0x4 => {
+                let (sum, carry) = vx.overflowing_add(vy);
+                v[x] = sum;
+                v[0xF] = carry as u8;
+            }
+            0x5 => {
+                v[x] = vx.wrapping_sub(vy);
+                v[0xF] = (vx >= vy) as u8;
+            }
+            0x7 => {
+                v[x] = vy.wrapping_sub(vx);
+                v[0xF] = (vy >= vx) as u8;
+            }
+            0x6 | 0xE => {
+                let value = if quirks.shift_uses_vy { vy } else { vx };
+                let (shifted, bit) = if n == 0x6 {
+                    (value >> 1, value & 1)
+                } else {
+                    (value << 1, value >> 7)
+                };
+                v[x] = shifted;
+                v[0xF] = bit;
+            }
+            _ => {}
+        }
+    }
+
+    fn draw(&mut self, x: usize, y: usize, rows: usize, quirks: &Quirks) {
+        // vF is cleared before the coordinates are read, as `Chip8` does
+        self.v[0xF] = 0;
+        let x0 = self.v[x] as usize % DISPLAY_WIDTH;
+        let y0 = self.v[y] as usize % DISPLAY_HEIGHT;
+        for row in 0..rows {
+            let mut y = y0 + row;
+            if y >= DISPLAY_HEIGHT {
+                if quirks.clip_sprites {
+                    break;
+                }
+                y -= DISPLAY_HEIGHT;
+            }
+            let bits = (self.byte(*self.i as usize + row) as u64) << (DISPLAY_WIDTH - 8);
+            let sprite = if quirks.clip_sprites {
+                bits >> x0
+            } else {
+                bits.rotate_right(x0 as u32)
+            };
+            if self.screen[y] & sprite != 0 {
+                self.v[0xF] = 1;
+            }
+            self.screen[y] ^= sprite;
+        }
+    }
+
+    fn wait_for_key(&mut self, x: usize, quirks: &Quirks) {
+        if let Some(key) = self.keyboard.take_waited_key() {
+            self.v[x] = key as u8;
+            return;
+        }
+        self.keyboard.begin_key_wait(quirks.key_wait_on_release);
+        // The VIP interpreter sounds the tone for as long as the key is held
+        if let KeyWait::Release(_) = self.keyboard.key_wait() {
+            *self.sound_timer = (*self.sound_timer).max(KEY_WAIT_BEEP);
+        }
+        *self.pc = self.pc.wrapping_sub(2);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Runs off the end of memory every way it can: BCD, FX55 and FX65 across
+    // 0xFFF, a sprite read from 0xFFD, I added past 0xFFF, 64 nested calls
+    // where the stack holds 32, EX9E on key 0x1A, and a JP 200 whose second
+    // byte is at 0x000 after it is fetched from 0xFFF
+    #[rustfmt::skip]
+    const WRAPS: &[u8] = &[
+        0xAF, 0xFE, // LD I, FFE
+        0x60, 0x1A, // LD V0, 1A
+        0x61, 0x12, // LD V1, 12
+        0x62, 0x00, // LD V2, 00
+        0xE0, 0x9E, // SKP V0
+        0x63, 0x05, // LD V3, 05
+        0xAF, 0xFF, // LD I, FFF
+        0xF3, 0x33, // LD B, V3
+        0xAF, 0xFE, // LD I, FFE
+        0xF3, 0x55, // LD [I], V3: 1A 12 at FFE, V2 V3 at 000
+        0xF3, 0x65, // LD V3, [I]
+        0xAF, 0xFD, // LD I, FFD
+        0xD3, 0x45, // DRW V3, V4, 5
+        0x74, 0x01, // ADD V4, 01
+        0xF4, 0x1E, // ADD I, V4
+        0x65, 0x00, // LD V5, 00
+        0x22, 0x24, // CALL 224
+        0x1F, 0xFF, // JP FFF
+        0x75, 0x01, // 224: ADD V5, 01
+        0x35, 0x40, // SE V5, 40
+        0x22, 0x24, // CALL 224
+        0x00, 0xEE, // RET
+    ];
+
+    const ROMS: [&[u8]; 6] = [
+        include_bytes!("../../roms/IBM_Logo.ch8"),
+        include_bytes!("../../roms/3-corax+.ch8"),
+        include_bytes!("../../roms/4-flags.ch8"),
+        include_bytes!("../../roms/5-quirks.ch8"),
+        include_bytes!("../../roms/ghosts.ch8"),
+        WRAPS,
+    ];
+
+    // Keys held on each frame, changing now and then so FX0A and EX9E see some action
+    fn keys(frame: u64, n: usize) -> u16 {
+        if (frame / 20).is_multiple_of(2) {
+            1 << ((frame / 40 + n as u64) % 16)
+        } else {
+            0
+        }
+    }
+
+    #[test]
+    fn runs_like_chip8() {
+        for platform in Platform::ALL {
+            for timing in [Timing::Fixed, Timing::CosmacVip] {
+                for rom in ROMS {
+                    let count = 130;
+                    let mut batch = Chip8Batch::new(count, platform, 7);
+                    batch.set_timing(timing);
+                    batch.load_rom(rom);
+                    let mut machines: Vec<Chip8> = (0..count)
+                        .map(|n| {
+                            let mut chip8 = Chip8::new();
+                            chip8.set_platform(platform);
+                            chip8.set_timing(timing);
+                            chip8.set_seed(7 + n as u64);
+                            chip8.load_rom_bytes(rom);
+                            chip8
+                        })
+                        .collect();
+
+                    for frame in 0..200 {
+                        for (n, chip8) in machines.iter_mut().enumerate() {
+                            batch.set_keys(n, keys(frame, n));
+                            let keyboard = chip8.key_mut();
+                            for key in 0..16 {
+                                keyboard.set_key(key, keys(frame, n) & 1 << key != 0);
+                            }
+                            chip8.run_frame();
+                        }
+                        batch.run_frame();
+                    }
+                    for (n, chip8) in machines.iter().enumerate() {
+                        assert!(
+                            batch.machine(n).save_state() == chip8.save_state(),
+                            "instance {} differs on {} with {}",
+                            n,
+                            platform,
+                            timing
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn machines_go_in_and_out() {
+        let mut chip8 = Chip8::new();
+        chip8.set_seed(3);
+        chip8.load_rom_bytes(ROMS[4]);
+        for _ in 0..30 {
+            chip8.run_frame();
+        }
+
+        let mut batch = Chip8Batch::new(2, Platform::default(), 0);
+        batch.load_rom(ROMS[4]);
+        batch.set_machine(1, &chip8);
+        assert_eq!(batch.machine(1).save_state(), chip8.save_state());
+        assert_eq!(batch.frame(1), 30);
+        batch.run_frame();
+        chip8.run_frame();
+        assert_eq!(batch.machine(1).save_state(), chip8.save_state());
+        assert_eq!(batch.pc(1), chip8.pc());
+
+        // A ROM that does not fit leaves the batch running the old one
+        assert_eq!(
+            batch.try_load_rom(&[0; MAX_ROM_SIZE + 1]),
+            Err(RomTooLarge {
+                len: MAX_ROM_SIZE + 1
+            })
+        );
+        assert_eq!(batch.machine(1).save_state(), chip8.save_state());
+
+        batch.reset(1, 9);
+        assert_eq!(batch.frame(1), 0);
+        assert_eq!(batch.machine(1).seed(), 9);
+        assert_eq!(batch.memory(1)[ROM_START..][..4], ROMS[4][..4]);
+        assert_eq!(batch.frame(0), 1);
+    }
+}
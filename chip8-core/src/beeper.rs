@@ -0,0 +1,54 @@
+//! The beeper as 16-bit PCM, for frontends and captures that produce audio.
+
+pub const SAMPLE_RATE: u32 = 44_100;
+pub const SAMPLES_PER_FRAME: usize = (SAMPLE_RATE / FRAME_RATE) as usize;
+
+const FRAME_RATE: u32 = 60;
+const BEEP_HZ: u32 = 440;
+const BEEP_AMPLITUDE: i16 = 8_000;
+
+/// A square wave while the sound timer runs, generated one 60 Hz frame at a time
+#[derive(Debug, Default, Clone)]
+pub struct Beeper {
+    phase: u32,
+}
+
+impl Beeper {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The mono samples for one frame, silent unless `beeping`
+    pub fn frame(&mut self, beeping: bool) -> [i16; SAMPLES_PER_FRAME] {
+        let half_period = SAMPLE_RATE / BEEP_HZ / 2;
+        let mut samples = [0; SAMPLES_PER_FRAME];
+        for sample in samples.iter_mut() {
+            if beeping {
+                *sample = if (self.phase / half_period).is_multiple_of(2) {
+                    BEEP_AMPLITUDE
+                } else {
+                    -BEEP_AMPLITUDE
+                };
+            }
+            // Keep the phase running through silence so beeps line up with the frame grid
+            self.phase = self.phase.wrapping_add(1);
+        }
+        samples
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn square_wave_only_while_beeping() {
+        let mut beeper = Beeper::new();
+        assert!(beeper.frame(false).iter().all(|&s| s == 0));
+        let samples = beeper.frame(true);
+        assert!(samples.iter().all(|&s| s.abs() == BEEP_AMPLITUDE));
+        // About 440 Hz: a little over seven cycles in a frame
+        let edges = samples.windows(2).filter(|w| w[0] != w[1]).count();
+        assert!((14..=15).contains(&edges));
+    }
+}
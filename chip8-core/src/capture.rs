@@ -0,0 +1,346 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::beeper::{Beeper, SAMPLES_PER_FRAME, SAMPLE_RATE};
+use crate::display::{Display, ExportError, Rgb, DISPLAY_HEIGHT, DISPLAY_WIDTH, MAX_SCALE};
+use crate::machine::Machine;
+
+const FRAME_RATE: u32 = 60;
+
+/// Records gameplay to `<base>.gif`, `<base>.y4m` and `<base>.wav`.
+///
+/// Every call to `frame` stands for one emulated 60 Hz frame, so the output
+/// plays back at the speed the game ran in the emulator regardless of how
+/// fast the host actually produced the frames.
+pub struct Capture {
+    gif: GifWriter,
+    y4m: Y4mWriter,
+    wav: WavWriter,
+    frames: u64,
+}
+
+impl Capture {
+    /// Create the three files. A scale of 0 or above `MAX_SCALE`, or an empty
+    /// palette, is an `InvalidInput` error and creates nothing.
+    pub fn start(base: &Path, scale: usize, palette: &[Rgb]) -> io::Result<Self> {
+        if scale == 0 {
+            return Err(ExportError::ZeroScale.into());
+        }
+        if scale > MAX_SCALE {
+            return Err(ExportError::ScaleTooLarge { max: MAX_SCALE }.into());
+        }
+        if palette.is_empty() {
+            return Err(ExportError::EmptyPalette.into());
+        }
+        Ok(Capture {
+            gif: GifWriter::create(&with_extension(base, "gif"), scale, palette)?,
+            y4m: Y4mWriter::create(&with_extension(base, "y4m"), scale, palette)?,
+            wav: WavWriter::create(&with_extension(base, "wav"))?,
+            frames: 0,
+        })
+    }
+
+    /// Append the frame `machine` just finished
+    pub fn frame(&mut self, machine: &(impl Machine + ?Sized)) -> io::Result<()> {
+        self.gif.frame(machine.display())?;
+        self.y4m.frame(machine.display())?;
+        self.wav.frame(machine.sound_active())?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Number of frames captured so far
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Flush all streams and patch up the headers that depend on the length
+    pub fn finish(self) -> io::Result<()> {
+        self.gif.finish()?;
+        self.y4m.finish()?;
+        self.wav.finish()
+    }
+}
+
+fn with_extension(base: &Path, extension: &str) -> PathBuf {
+    let mut path = base.as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+    path.into()
+}
+
+// Pixel values as palette indices, scaled up
+fn indexed_pixels(display: &Display, scale: usize) -> Vec<u8> {
+    let (width, height) = (DISPLAY_WIDTH * scale, DISPLAY_HEIGHT * scale);
+    let mut indices = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            indices.push(display.get_pixel(x / scale, y / scale));
+        }
+    }
+    indices
+}
+
+/// Animated GIF. Runs of identical frames are merged into one longer frame.
+struct GifWriter {
+    encoder: gif::Encoder<BufWriter<File>>,
+    scale: usize,
+    pending: Option<Vec<u8>>,
+    pending_frames: u64,
+    frames_written: u64,
+}
+
+impl GifWriter {
+    fn create(path: &Path, scale: usize, palette: &[Rgb]) -> io::Result<Self> {
+        let file = BufWriter::new(File::create(path)?);
+        let flat_palette: Vec<u8> = palette.iter().flatten().copied().collect();
+        let mut encoder = gif::Encoder::new(
+            file,
+            (DISPLAY_WIDTH * scale) as u16,
+            (DISPLAY_HEIGHT * scale) as u16,
+            &flat_palette,
+        )
+        .map_err(gif_error)?;
+        encoder
+            .set_repeat(gif::Repeat::Infinite)
+            .map_err(gif_error)?;
+        Ok(GifWriter {
+            encoder,
+            scale,
+            pending: None,
+            pending_frames: 0,
+            frames_written: 0,
+        })
+    }
+
+    fn frame(&mut self, display: &Display) -> io::Result<()> {
+        let pixels = indexed_pixels(display, self.scale);
+        if self.pending.as_ref() == Some(&pixels) {
+            self.pending_frames += 1;
+            return Ok(());
+        }
+        self.flush_pending()?;
+        self.pending = Some(pixels);
+        self.pending_frames = 1;
+        Ok(())
+    }
+
+    fn flush_pending(&mut self) -> io::Result<()> {
+        let Some(pixels) = self.pending.take() else {
+            return Ok(());
+        };
+        let delay = gif_delay(self.frames_written, self.pending_frames);
+        self.frames_written += self.pending_frames;
+
+        let mut frame = gif::Frame {
+            width: (DISPLAY_WIDTH * self.scale) as u16,
+            height: (DISPLAY_HEIGHT * self.scale) as u16,
+            buffer: pixels.into(),
+            delay,
+            ..Default::default()
+        };
+        frame.dispose = gif::DisposalMethod::Keep;
+        self.encoder.write_frame(&frame).map_err(gif_error)
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        self.flush_pending()?;
+        self.encoder.get_mut().flush()
+    }
+}
+
+// GIF delays are in hundredths of a second, so round the frame boundaries to
+// the nearest tick to keep the total in sync with 60 Hz. A picture held for
+// longer than the format can express (about 11 minutes) is cut short.
+fn gif_delay(start: u64, frames: u64) -> u16 {
+    let delay = centiseconds(start + frames) - centiseconds(start);
+    u16::try_from(delay).unwrap_or(u16::MAX)
+}
+
+fn centiseconds(frames: u64) -> u64 {
+    (frames * 100 + FRAME_RATE as u64 / 2) / FRAME_RATE as u64
+}
+
+fn gif_error(e: gif::EncodingError) -> io::Error {
+    match e {
+        gif::EncodingError::Io(e) => e,
+        e => io::Error::other(e),
+    }
+}
+
+/// Uncompressed YUV4MPEG2 stream (4:4:4, BT.601) that video tools read directly
+struct Y4mWriter {
+    out: BufWriter<File>,
+    scale: usize,
+    // Y, Cb and Cr for each palette entry
+    yuv_palette: Vec<[u8; 3]>,
+}
+
+impl Y4mWriter {
+    fn create(path: &Path, scale: usize, palette: &[Rgb]) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(
+            out,
+            "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444",
+            DISPLAY_WIDTH * scale,
+            DISPLAY_HEIGHT * scale,
+            FRAME_RATE
+        )?;
+        Ok(Y4mWriter {
+            out,
+            scale,
+            yuv_palette: palette.iter().map(|&rgb| rgb_to_yuv(rgb)).collect(),
+        })
+    }
+
+    fn frame(&mut self, display: &Display) -> io::Result<()> {
+        let pixels = indexed_pixels(display, self.scale);
+        self.out.write_all(b"FRAME\n")?;
+        for plane in 0..3 {
+            let bytes: Vec<u8> = pixels
+                .iter()
+                .map(|&p| self.yuv_palette[(p as usize).min(self.yuv_palette.len() - 1)][plane])
+                .collect();
+            self.out.write_all(&bytes)?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+fn rgb_to_yuv([r, g, b]: Rgb) -> [u8; 3] {
+    let (r, g, b) = (r as f32, g as f32, b as f32);
+    let y = 16.0 + 0.257 * r + 0.504 * g + 0.098 * b;
+    let u = 128.0 - 0.148 * r - 0.291 * g + 0.439 * b;
+    let v = 128.0 + 0.439 * r - 0.368 * g - 0.071 * b;
+    [y.round() as u8, u.round() as u8, v.round() as u8]
+}
+
+/// 16-bit mono PCM of the beeper as a square wave
+struct WavWriter {
+    out: BufWriter<File>,
+    samples: u32,
+    beeper: Beeper,
+}
+
+impl WavWriter {
+    fn create(path: &Path) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        // The sizes are patched in by `finish`
+        write_wav_header(&mut out, 0)?;
+        Ok(WavWriter {
+            out,
+            samples: 0,
+            beeper: Beeper::new(),
+        })
+    }
+
+    fn frame(&mut self, beeping: bool) -> io::Result<()> {
+        let samples = self.beeper.frame(beeping);
+        let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        self.samples += SAMPLES_PER_FRAME as u32;
+        self.out.write_all(&bytes)
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        self.out.seek(SeekFrom::Start(0))?;
+        write_wav_header(&mut self.out, self.samples)?;
+        self.out.flush()
+    }
+}
+
+fn write_wav_header<W: Write>(out: &mut W, samples: u32) -> io::Result<()> {
+    let data_len = samples * 2;
+    out.write_all(b"RIFF")?;
+    out.write_all(&(36 + data_len).to_le_bytes())?;
+    out.write_all(b"WAVEfmt ")?;
+    out.write_all(&16u32.to_le_bytes())?; // fmt chunk size
+    out.write_all(&1u16.to_le_bytes())?; // PCM
+    out.write_all(&1u16.to_le_bytes())?; // mono
+    out.write_all(&SAMPLE_RATE.to_le_bytes())?;
+    out.write_all(&(SAMPLE_RATE * 2).to_le_bytes())?; // byte rate
+    out.write_all(&2u16.to_le_bytes())?; // block align
+    out.write_all(&16u16.to_le_bytes())?; // bits per sample
+    out.write_all(b"data")?;
+    out.write_all(&data_len.to_le_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::DEFAULT_PALETTE;
+    use crate::emu::Chip8;
+
+    #[test]
+    fn gif_delays_add_up_to_wall_time() {
+        // 60 frames must last exactly one second however they are grouped
+        assert_eq!(centiseconds(60), 100);
+        assert_eq!(centiseconds(30), 50);
+        let total: u64 = (0..60).map(|n| centiseconds(n + 1) - centiseconds(n)).sum();
+        assert_eq!(total, 100);
+    }
+
+    #[test]
+    fn long_gif_delays_saturate() {
+        let most = u16::MAX as u64 * FRAME_RATE as u64 / 100;
+        assert_eq!(gif_delay(0, most), u16::MAX);
+        assert_eq!(gif_delay(0, most * 2), u16::MAX);
+        assert_eq!(gif_delay(7, 3), 5);
+    }
+
+    #[test]
+    fn start_rejects_bad_settings() {
+        let base = std::env::temp_dir().join(format!("chip8-bad-capture-{}", std::process::id()));
+        for (scale, palette) in [
+            (0, &DEFAULT_PALETTE[..]),
+            (MAX_SCALE + 1, &DEFAULT_PALETTE),
+            (1, &[]),
+        ] {
+            let e = Capture::start(&base, scale, palette).err().unwrap();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+        }
+        assert!(!with_extension(&base, "gif").exists());
+    }
+
+    #[test]
+    fn capture_writes_all_streams() {
+        let dir = std::env::temp_dir().join(format!("chip8-capture-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let base = dir.join("clip");
+
+        let mut chip8 = Chip8::new();
+        // 600A - LD V0, 10; F018 - LD ST, V0; 1204 - JP 0x204
+        chip8.load_rom_bytes(&[0x60, 0x0A, 0xF0, 0x18, 0x12, 0x04]);
+        let mut capture = Capture::start(&base, 2, &DEFAULT_PALETTE).unwrap();
+        for _ in 0..20 {
+            chip8.run_frame();
+            capture.frame(&chip8).unwrap();
+        }
+        capture.finish().unwrap();
+
+        let wav = std::fs::read(with_extension(&base, "wav")).unwrap();
+        assert_eq!(wav.len(), 44 + 20 * SAMPLES_PER_FRAME * 2);
+        assert_eq!(
+            &wav[40..44],
+            &(20 * SAMPLES_PER_FRAME as u32 * 2).to_le_bytes()
+        );
+        // The beep lasts 10 frames, the rest is silence
+        let sample = |n: usize| i16::from_le_bytes([wav[44 + n * 2], wav[45 + n * 2]]);
+        assert!((0..SAMPLES_PER_FRAME).any(|n| sample(n) != 0));
+        assert!((0..SAMPLES_PER_FRAME).all(|n| sample(15 * SAMPLES_PER_FRAME + n) == 0));
+
+        let y4m = std::fs::read(with_extension(&base, "y4m")).unwrap();
+        let header = b"YUV4MPEG2 W128 H64 F60:1 Ip A1:1 C444\n";
+        assert_eq!(&y4m[..header.len()], header);
+        assert_eq!(y4m.len(), header.len() + 20 * (6 + 128 * 64 * 3));
+
+        let gif = std::fs::read(with_extension(&base, "gif")).unwrap();
+        assert_eq!(&gif[..6], b"GIF89a");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
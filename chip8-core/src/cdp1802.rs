@@ -0,0 +1,474 @@
+//! The RCA CDP1802 "COSMAC" CPU that runs the COSMAC VIP.
+//!
+//! Every instruction takes two machine cycles of 8 clocks, except the long
+//! branches and skips (`CX`), which take three. DMA and interrupts are taken
+//! between instructions, which is also when the real chip honours them.
+
+/// What the CPU sees of the machine around it
+pub trait Bus {
+    fn read(&mut self, address: u16) -> u8;
+    fn write(&mut self, address: u16, value: u8);
+    /// The byte device `port` (1 to 7) puts on the bus for `INP`
+    fn input(&mut self, port: u8) -> u8;
+    /// `OUT` to device `port` (1 to 7)
+    fn output(&mut self, port: u8, value: u8);
+    /// Whether external flag line EF`n` (1 to 4) is asserted
+    fn flag(&mut self, n: u8) -> bool;
+}
+
+pub struct Cdp1802 {
+    r: [u16; 16],
+    // Register holding the program counter
+    p: u8,
+    // Register holding the data pointer
+    x: u8,
+    d: u8,
+    df: bool,
+    t: u8,
+    ie: bool,
+    q: bool,
+    idle: bool,
+}
+
+impl Default for Cdp1802 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cdp1802 {
+    /// A CPU straight out of reset: running from R0 at 0x0000 with interrupts enabled
+    pub fn new() -> Self {
+        Cdp1802 {
+            r: [0; 16],
+            p: 0,
+            x: 0,
+            d: 0,
+            df: false,
+            t: 0,
+            ie: true,
+            q: false,
+            idle: false,
+        }
+    }
+
+    pub fn r(&self) -> &[u16; 16] {
+        &self.r
+    }
+
+    pub fn p(&self) -> u8 {
+        self.p
+    }
+
+    pub fn x(&self) -> u8 {
+        self.x
+    }
+
+    pub fn d(&self) -> u8 {
+        self.d
+    }
+
+    pub fn df(&self) -> bool {
+        self.df
+    }
+
+    pub fn t(&self) -> u8 {
+        self.t
+    }
+
+    pub fn ie(&self) -> bool {
+        self.ie
+    }
+
+    /// The Q output, which drives the VIP's beeper
+    pub fn q(&self) -> bool {
+        self.q
+    }
+
+    /// Whether an IDL is waiting for DMA or an interrupt
+    pub fn is_idle(&self) -> bool {
+        self.idle
+    }
+
+    /// The program counter, R(P)
+    pub fn pc(&self) -> u16 {
+        self.r[self.p as usize]
+    }
+
+    /// One DMA out cycle: the byte at R0 goes to the requesting device and R0 moves on
+    pub fn dma_out<B: Bus>(&mut self, bus: &mut B) -> u8 {
+        let byte = bus.read(self.r[0]);
+        self.r[0] = self.r[0].wrapping_add(1);
+        self.idle = false;
+        byte
+    }
+
+    /// Take an interrupt if they are enabled: the old X and P go to T and the
+    /// handler runs from R1 with R2 as its data pointer. Takes one machine cycle.
+    pub fn interrupt(&mut self) -> bool {
+        if !self.ie {
+            return false;
+        }
+        self.t = self.x << 4 | self.p;
+        self.x = 2;
+        self.p = 1;
+        self.ie = false;
+        self.idle = false;
+        true
+    }
+
+    /// Execute one instruction, returning the machine cycles it took. An idle
+    /// CPU only spends one cycle per call.
+    pub fn step<B: Bus>(&mut self, bus: &mut B) -> u32 {
+        if self.idle {
+            return 1;
+        }
+        let opcode = self.fetch(bus);
+        let n = (opcode & 0x0F) as usize;
+        let x = self.x as usize;
+        match opcode >> 4 {
+            0x0 if n == 0 => self.idle = true,            // IDL
+            0x0 => self.d = bus.read(self.r[n]),          // LDN
+            0x1 => self.r[n] = self.r[n].wrapping_add(1), // INC
+            0x2 => self.r[n] = self.r[n].wrapping_sub(1), // DEC
+            0x3 => {
+                let taken = self.condition(bus, n);
+                self.short_branch(bus, taken);
+            }
+            0x4 => {
+                // LDA
+                self.d = bus.read(self.r[n]);
+                self.r[n] = self.r[n].wrapping_add(1);
+            }
+            0x5 => bus.write(self.r[n], self.d), // STR
+            0x6 => match n {
+                0x0 => self.r[x] = self.r[x].wrapping_add(1), // IRX
+                0x1..=0x7 => {
+                    // OUT
+                    let value = bus.read(self.r[x]);
+                    bus.output(n as u8, value);
+                    self.r[x] = self.r[x].wrapping_add(1);
+                }
+                0x8 => {} // Not an instruction on the 1802
+                _ => {
+                    // INP
+                    let value = bus.input(n as u8 - 8);
+                    bus.write(self.r[x], value);
+                    self.d = value;
+                }
+            },
+            0x7 => self.op_7n(bus, n),
+            0x8 => self.d = self.r[n] as u8,        // GLO
+            0x9 => self.d = (self.r[n] >> 8) as u8, // GHI
+            0xA => self.r[n] = self.r[n] & 0xFF00 | self.d as u16, // PLO
+            0xB => self.r[n] = self.r[n] & 0x00FF | (self.d as u16) << 8, // PHI
+            0xC => return self.op_cn(bus, n),
+            0xD => self.p = n as u8, // SEP
+            0xE => self.x = n as u8, // SEX
+            _ => self.op_fn(bus, n),
+        }
+        2
+    }
+
+    fn fetch<B: Bus>(&mut self, bus: &mut B) -> u8 {
+        let p = self.p as usize;
+        let byte = bus.read(self.r[p]);
+        self.r[p] = self.r[p].wrapping_add(1);
+        byte
+    }
+
+    // The condition a short branch tests; 8 to F test the opposite of 0 to 7
+    fn condition<B: Bus>(&mut self, bus: &mut B, n: usize) -> bool {
+        let result = match n & 0x7 {
+            0 => true,
+            1 => self.q,
+            2 => self.d == 0,
+            3 => self.df,
+            ef => bus.flag(ef as u8 - 3),
+        };
+        result != (n >= 8)
+    }
+
+    // Jump within the current page to the immediate byte, or step over it
+    fn short_branch<B: Bus>(&mut self, bus: &mut B, taken: bool) {
+        let p = self.p as usize;
+        if taken {
+            let target = bus.read(self.r[p]);
+            self.r[p] = self.r[p] & 0xFF00 | target as u16;
+        } else {
+            self.r[p] = self.r[p].wrapping_add(1);
+        }
+    }
+
+    fn op_7n<B: Bus>(&mut self, bus: &mut B, n: usize) {
+        let x = self.x as usize;
+        match n {
+            0x0 | 0x1 => {
+                // RET, DIS
+                let xp = bus.read(self.r[x]);
+                self.r[x] = self.r[x].wrapping_add(1);
+                self.x = xp >> 4;
+                self.p = xp & 0x0F;
+                self.ie = n == 0x0;
+            }
+            0x2 => {
+                // LDXA
+                self.d = bus.read(self.r[x]);
+                self.r[x] = self.r[x].wrapping_add(1);
+            }
+            0x3 => {
+                // STXD
+                bus.write(self.r[x], self.d);
+                self.r[x] = self.r[x].wrapping_sub(1);
+            }
+            0x4 => self.add(bus.read(self.r[x]), self.df), // ADC
+            0x5 => self.subtract(bus.read(self.r[x]), self.d), // SDB
+            0x6 => {
+                // SHRC
+                let carry = self.d & 1 != 0;
+                self.d = self.d >> 1 | (self.df as u8) << 7;
+                self.df = carry;
+            }
+            0x7 => self.subtract(self.d, bus.read(self.r[x])), // SMB
+            0x8 => bus.write(self.r[x], self.t),               // SAV
+            0x9 => {
+                // MARK
+                self.t = self.x << 4 | self.p;
+                bus.write(self.r[2], self.t);
+                self.x = self.p;
+                self.r[2] = self.r[2].wrapping_sub(1);
+            }
+            0xA => self.q = false, // REQ
+            0xB => self.q = true,  // SEQ
+            0xC => {
+                // ADCI
+                let value = self.fetch(bus);
+                self.add(value, self.df);
+            }
+            0xD => {
+                // SDBI
+                let value = self.fetch(bus);
+                self.subtract(value, self.d);
+            }
+            0xE => {
+                // SHLC
+                let carry = self.d & 0x80 != 0;
+                self.d = self.d << 1 | self.df as u8;
+                self.df = carry;
+            }
+            _ => {
+                // SMBI
+                let value = self.fetch(bus);
+                self.subtract(self.d, value);
+            }
+        }
+    }
+
+    // Long branches and skips, three machine cycles each
+    fn op_cn<B: Bus>(&mut self, bus: &mut B, n: usize) -> u32 {
+        let p = self.p as usize;
+        let test = match n & 0x3 {
+            0 => true,
+            1 => self.q,
+            2 => self.d == 0,
+            _ => self.df,
+        };
+        match n {
+            // LBR, LBQ, LBZ, LBDF and their inverses LBNQ, LBNZ, LBNF
+            0x0..=0x3 | 0x9..=0xB => {
+                if test != (n >= 8) {
+                    let high = bus.read(self.r[p]);
+                    let low = bus.read(self.r[p].wrapping_add(1));
+                    self.r[p] = u16::from_be_bytes([high, low]);
+                } else {
+                    self.r[p] = self.r[p].wrapping_add(2);
+                }
+            }
+            0x4 => {} // NOP
+            _ => {
+                // LSNQ, LSNZ, LSNF, LSKP, LSIE, LSQ, LSZ, LSDF
+                let skip = match n {
+                    0x5..=0x7 => !test,
+                    0x8 => true,
+                    0xC => self.ie,
+                    _ => test,
+                };
+                if skip {
+                    self.r[p] = self.r[p].wrapping_add(2);
+                }
+            }
+        }
+        3
+    }
+
+    fn op_fn<B: Bus>(&mut self, bus: &mut B, n: usize) {
+        let x = self.x as usize;
+        // F0 to F7 work on M(R(X)), F8 to FF on the immediate byte
+        let operand = match n {
+            0x6 | 0xE => 0,
+            0x0..=0x7 => bus.read(self.r[x]),
+            _ => self.fetch(bus),
+        };
+        match n & 0x7 {
+            0x0 => self.d = operand,         // LDX, LDI
+            0x1 => self.d |= operand,        // OR, ORI
+            0x2 => self.d &= operand,        // AND, ANI
+            0x3 => self.d ^= operand,        // XOR, XRI
+            0x4 => self.add(operand, false), // ADD, ADI
+            0x5 => {
+                // SD, SDI
+                self.df = true;
+                self.subtract(operand, self.d);
+            }
+            0x6 if n == 0x6 => {
+                // SHR
+                self.df = self.d & 1 != 0;
+                self.d >>= 1;
+            }
+            0x6 => {
+                // SHL
+                self.df = self.d & 0x80 != 0;
+                self.d <<= 1;
+            }
+            _ => {
+                // SM, SMI
+                self.df = true;
+                self.subtract(self.d, operand);
+            }
+        }
+    }
+
+    fn add(&mut self, value: u8, carry: bool) {
+        let sum = self.d as u16 + value as u16 + carry as u16;
+        self.d = sum as u8;
+        self.df = sum > 0xFF;
+    }
+
+    // `minuend - subtrahend`, borrowing when DF is clear; DF ends up set when
+    // nothing was borrowed
+    fn subtract(&mut self, minuend: u8, subtrahend: u8) {
+        let difference = minuend as i16 - subtrahend as i16 - !self.df as i16;
+        self.d = difference as u8;
+        self.df = difference >= 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Ram {
+        bytes: Vec<u8>,
+        outputs: Vec<(u8, u8)>,
+        flags: [bool; 4],
+    }
+
+    impl Ram {
+        fn with_program(program: &[u8]) -> Self {
+            let mut bytes = vec![0; 0x100];
+            bytes[..program.len()].copy_from_slice(program);
+            Ram {
+                bytes,
+                outputs: Vec::new(),
+                flags: [false; 4],
+            }
+        }
+    }
+
+    impl Bus for Ram {
+        fn read(&mut self, address: u16) -> u8 {
+            self.bytes[address as usize]
+        }
+
+        fn write(&mut self, address: u16, value: u8) {
+            self.bytes[address as usize] = value;
+        }
+
+        fn input(&mut self, port: u8) -> u8 {
+            0x40 | port
+        }
+
+        fn output(&mut self, port: u8, value: u8) {
+            self.outputs.push((port, value));
+        }
+
+        fn flag(&mut self, n: u8) -> bool {
+            self.flags[n as usize - 1]
+        }
+    }
+
+    fn run(cpu: &mut Cdp1802, ram: &mut Ram, instructions: usize) -> u32 {
+        (0..instructions).map(|_| cpu.step(ram)).sum()
+    }
+
+    #[test]
+    fn arithmetic_sets_df() {
+        // LDI 0xF0; ADI 0x20; SMI 0x20; SDI 0x00; SHRC
+        let mut ram = Ram::with_program(&[0xF8, 0xF0, 0xFC, 0x20, 0xFF, 0x20, 0xFD, 0x00, 0x76]);
+        let mut cpu = Cdp1802::new();
+        run(&mut cpu, &mut ram, 2);
+        assert_eq!((cpu.d(), cpu.df()), (0x10, true));
+        // 0x10 - 0x20 borrows
+        run(&mut cpu, &mut ram, 1);
+        assert_eq!((cpu.d(), cpu.df()), (0xF0, false));
+        // 0x00 - 0xF0 borrows as well
+        run(&mut cpu, &mut ram, 1);
+        assert_eq!((cpu.d(), cpu.df()), (0x10, false));
+        run(&mut cpu, &mut ram, 1);
+        assert_eq!((cpu.d(), cpu.df()), (0x08, false));
+    }
+
+    #[test]
+    fn branches_and_long_instruction_timing() {
+        // 00: LDI 0; BZ 06; SEQ; 06: LBR 0020
+        let mut ram = Ram::with_program(&[0xF8, 0x00, 0x32, 0x06, 0x7B, 0x00, 0xC0, 0x00, 0x20]);
+        // 20: B3 24; SEQ; 24: SEX 3; OUT 5; INP 2
+        ram.bytes[0x20..0x27].copy_from_slice(&[0x36, 0x24, 0x7B, 0x00, 0xE3, 0x65, 0x6A]);
+        ram.flags[2] = true;
+        let mut cpu = Cdp1802::new();
+        assert_eq!(run(&mut cpu, &mut ram, 3), 2 + 2 + 3);
+        assert_eq!(cpu.pc(), 0x20);
+        run(&mut cpu, &mut ram, 4);
+        assert!(!cpu.q());
+        // R3 still points at 0, where the program starts with LDI
+        assert_eq!(ram.outputs, [(5, 0xF8)]);
+        assert_eq!(cpu.d(), 0x42);
+        assert_eq!(ram.bytes[1], 0x42);
+    }
+
+    #[test]
+    fn interrupt_saves_and_returns() {
+        // R2 = 0x80, R1 = 0x10; SEX 2; MARK
+        let mut ram = Ram::with_program(&[0xF8, 0x80, 0xA2, 0xF8, 0x10, 0xA1, 0xE2, 0x79]);
+        // 10: DEC R2; SAV; RET
+        ram.bytes[0x10..0x13].copy_from_slice(&[0x22, 0x78, 0x70]);
+        let mut cpu = Cdp1802::new();
+        run(&mut cpu, &mut ram, 5);
+        assert!(cpu.interrupt());
+        assert_eq!((cpu.x(), cpu.p(), cpu.t()), (2, 1, 0x20));
+        assert!(!cpu.interrupt());
+
+        run(&mut cpu, &mut ram, 3);
+        assert_eq!((cpu.x(), cpu.p(), cpu.pc()), (2, 0, 0x07));
+        assert_eq!((ram.bytes[0x7F], cpu.r()[2]), (0x20, 0x80));
+        assert!(cpu.ie());
+
+        // MARK saves X and P at R2 and makes P the data pointer
+        run(&mut cpu, &mut ram, 1);
+        assert_eq!((cpu.x(), cpu.t(), ram.bytes[0x80]), (0, 0x20, 0x20));
+        assert_eq!(cpu.r()[2], 0x7F);
+    }
+
+    #[test]
+    fn idle_until_interrupt() {
+        let mut ram = Ram::with_program(&[0x00]);
+        let mut cpu = Cdp1802::new();
+        cpu.step(&mut ram);
+        assert!(cpu.is_idle());
+        assert_eq!(cpu.step(&mut ram), 1);
+        assert!(cpu.interrupt());
+        assert!(!cpu.is_idle());
+        assert_eq!((cpu.x(), cpu.p(), cpu.t()), (2, 1, 0x00));
+        assert!(!cpu.interrupt());
+    }
+}
@@ -0,0 +1,117 @@
+//! Instructions decoded into their operands, so the interpreter can cache
+//! them instead of picking every instruction word apart each time it runs.
+
+/// A decoded instruction, named after its opcode pattern
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+    Op00E0,
+    Op00EE,
+    Op1NNN(u16),
+    Op2NNN(u16),
+    Op3XNN(u8, u8),
+    Op4XNN(u8, u8),
+    Op5XY0(u8, u8),
+    Op6XNN(u8, u8),
+    Op7XNN(u8, u8),
+    Op8XY0(u8, u8),
+    Op8XY1(u8, u8),
+    Op8XY2(u8, u8),
+    Op8XY3(u8, u8),
+    Op8XY4(u8, u8),
+    Op8XY5(u8, u8),
+    Op8XY6(u8, u8),
+    Op8XY7(u8, u8),
+    Op8XYE(u8, u8),
+    Op9XY0(u8, u8),
+    OpANNN(u16),
+    OpBNNN(u16),
+    OpCXNN(u8, u8),
+    OpDXYN(u8, u8, u8),
+    OpEX9E(u8),
+    OpEXA1(u8),
+    OpFX07(u8),
+    OpFX0A(u8),
+    OpFX15(u8),
+    OpFX18(u8),
+    OpFX1E(u8),
+    OpFX29(u8),
+    OpFX33(u8),
+    OpFX55(u8),
+    OpFX65(u8),
+    /// A word the interpreter does not implement
+    Unknown(u16),
+}
+
+impl Opcode {
+    pub fn decode(v: u16) -> Opcode {
+        let x = ((v & 0x0F00) >> 8) as u8;
+        let y = ((v & 0x00F0) >> 4) as u8;
+        let n = (v & 0x000F) as u8;
+        let nn = (v & 0x00FF) as u8;
+        let nnn = v & 0x0FFF;
+        match v & 0xF000 {
+            0x0000 => match n {
+                0x0 => Opcode::Op00E0,
+                0xE => Opcode::Op00EE,
+                _ => Opcode::Unknown(v),
+            },
+            0x1000 => Opcode::Op1NNN(nnn),
+            0x2000 => Opcode::Op2NNN(nnn),
+            0x3000 => Opcode::Op3XNN(x, nn),
+            0x4000 => Opcode::Op4XNN(x, nn),
+            0x5000 => Opcode::Op5XY0(x, y),
+            0x6000 => Opcode::Op6XNN(x, nn),
+            0x7000 => Opcode::Op7XNN(x, nn),
+            0x8000 => match n {
+                0x0 => Opcode::Op8XY0(x, y),
+                0x1 => Opcode::Op8XY1(x, y),
+                0x2 => Opcode::Op8XY2(x, y),
+                0x3 => Opcode::Op8XY3(x, y),
+                0x4 => Opcode::Op8XY4(x, y),
+                0x5 => Opcode::Op8XY5(x, y),
+                0x6 => Opcode::Op8XY6(x, y),
+                0x7 => Opcode::Op8XY7(x, y),
+                0xE => Opcode::Op8XYE(x, y),
+                _ => Opcode::Unknown(v),
+            },
+            0x9000 => Opcode::Op9XY0(x, y),
+            0xA000 => Opcode::OpANNN(nnn),
+            0xB000 => Opcode::OpBNNN(nnn),
+            0xC000 => Opcode::OpCXNN(x, nn),
+            0xD000 => Opcode::OpDXYN(x, y, n),
+            0xE000 => match nn {
+                0x9E => Opcode::OpEX9E(x),
+                0xA1 => Opcode::OpEXA1(x),
+                _ => Opcode::Unknown(v),
+            },
+            _ => match nn {
+                0x07 => Opcode::OpFX07(x),
+                0x0A => Opcode::OpFX0A(x),
+                0x15 => Opcode::OpFX15(x),
+                0x18 => Opcode::OpFX18(x),
+                0x1E => Opcode::OpFX1E(x),
+                0x29 => Opcode::OpFX29(x),
+                0x33 => Opcode::OpFX33(x),
+                0x55 => Opcode::OpFX55(x),
+                0x65 => Opcode::OpFX65(x),
+                _ => Opcode::Unknown(v),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_operands() {
+        assert_eq!(Opcode::decode(0x00E0), Opcode::Op00E0);
+        assert_eq!(Opcode::decode(0x2ABC), Opcode::Op2NNN(0xABC));
+        assert_eq!(Opcode::decode(0x8A3E), Opcode::Op8XYE(0xA, 0x3));
+        assert_eq!(Opcode::decode(0xD12F), Opcode::OpDXYN(0x1, 0x2, 0xF));
+        assert_eq!(Opcode::decode(0xF733), Opcode::OpFX33(0x7));
+        assert_eq!(Opcode::decode(0x8008), Opcode::Unknown(0x8008));
+        assert_eq!(Opcode::decode(0xE0FF), Opcode::Unknown(0xE0FF));
+    }
+}
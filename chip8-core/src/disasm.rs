@@ -0,0 +1,104 @@
+//! Mnemonics for CHIP-8 and SUPER-CHIP instructions, in the style of Cowgod's
+//! technical reference.
+
+use crate::memory::{Memory, MEMORY_SIZE};
+
+/// The assembly for one instruction; words that are not an instruction come
+/// out as data
+pub fn disassemble(opcode: u16) -> String {
+    let x = (opcode >> 8) & 0xF;
+    let y = (opcode >> 4) & 0xF;
+    let n = opcode & 0xF;
+    let nn = opcode & 0xFF;
+    let nnn = opcode & 0xFFF;
+    match (opcode >> 12, x, y, n) {
+        (0x0, 0x0, 0xE, 0x0) => "CLS".to_string(),
+        (0x0, 0x0, 0xE, 0xE) => "RET".to_string(),
+        (0x0, 0x0, 0xC, _) => format!("SCD {}", n),
+        (0x0, 0x0, 0xF, 0xB) => "SCR".to_string(),
+        (0x0, 0x0, 0xF, 0xC) => "SCL".to_string(),
+        (0x0, 0x0, 0xF, 0xD) => "EXIT".to_string(),
+        (0x0, 0x0, 0xF, 0xE) => "LOW".to_string(),
+        (0x0, 0x0, 0xF, 0xF) => "HIGH".to_string(),
+        (0x1, ..) => format!("JP {:#05X}", nnn),
+        (0x2, ..) => format!("CALL {:#05X}", nnn),
+        (0x3, ..) => format!("SE V{:X}, {:#04X}", x, nn),
+        (0x4, ..) => format!("SNE V{:X}, {:#04X}", x, nn),
+        (0x5, _, _, 0x0) => format!("SE V{:X}, V{:X}", x, y),
+        (0x6, ..) => format!("LD V{:X}, {:#04X}", x, nn),
+        (0x7, ..) => format!("ADD V{:X}, {:#04X}", x, nn),
+        (0x8, _, _, 0x0) => format!("LD V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x1) => format!("OR V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x2) => format!("AND V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x3) => format!("XOR V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x4) => format!("ADD V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x5) => format!("SUB V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x6) => format!("SHR V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x7) => format!("SUBN V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0xE) => format!("SHL V{:X}, V{:X}", x, y),
+        (0x9, _, _, 0x0) => format!("SNE V{:X}, V{:X}", x, y),
+        (0xA, ..) => format!("LD I, {:#05X}", nnn),
+        (0xB, ..) => format!("JP V0, {:#05X}", nnn),
+        (0xC, ..) => format!("RND V{:X}, {:#04X}", x, nn),
+        (0xD, ..) => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        (0xE, _, 0x9, 0xE) => format!("SKP V{:X}", x),
+        (0xE, _, 0xA, 0x1) => format!("SKNP V{:X}", x),
+        (0xF, _, 0x0, 0x7) => format!("LD V{:X}, DT", x),
+        (0xF, _, 0x0, 0xA) => format!("LD V{:X}, K", x),
+        (0xF, _, 0x1, 0x5) => format!("LD DT, V{:X}", x),
+        (0xF, _, 0x1, 0x8) => format!("LD ST, V{:X}", x),
+        (0xF, _, 0x1, 0xE) => format!("ADD I, V{:X}", x),
+        (0xF, _, 0x2, 0x9) => format!("LD F, V{:X}", x),
+        (0xF, _, 0x3, 0x0) => format!("LD HF, V{:X}", x),
+        (0xF, _, 0x3, 0x3) => format!("LD B, V{:X}", x),
+        (0xF, _, 0x5, 0x5) => format!("LD [I], V{:X}", x),
+        (0xF, _, 0x6, 0x5) => format!("LD V{:X}, [I]", x),
+        (0xF, _, 0x7, 0x5) => format!("LD R, V{:X}", x),
+        (0xF, _, 0x8, 0x5) => format!("LD V{:X}, R", x),
+        _ => format!("DW {:#06X}", opcode),
+    }
+}
+
+/// The `count` instructions from `address` on, with their addresses. Stops
+/// at the end of memory.
+pub fn disassemble_range(memory: &Memory, address: usize, count: usize) -> Vec<(usize, String)> {
+    (address..MEMORY_SIZE - 1)
+        .step_by(2)
+        .take(count)
+        .map(|addr| {
+            let opcode = u16::from_be_bytes([memory.get_byte(addr), memory.get_byte(addr + 1)]);
+            (addr, disassemble(opcode))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mnemonics() {
+        assert_eq!(disassemble(0x00E0), "CLS");
+        assert_eq!(disassemble(0x1228), "JP 0x228");
+        assert_eq!(disassemble(0x6A0F), "LD VA, 0x0F");
+        assert_eq!(disassemble(0x8AB6), "SHR VA, VB");
+        assert_eq!(disassemble(0xD125), "DRW V1, V2, 5");
+        assert_eq!(disassemble(0xF355), "LD [I], V3");
+        assert_eq!(disassemble(0x00C4), "SCD 4");
+        // Not an instruction
+        assert_eq!(disassemble(0x5121), "DW 0x5121");
+        assert_eq!(disassemble(0xFFFF), "DW 0xFFFF");
+    }
+
+    #[test]
+    fn range_stops_at_the_end_of_memory() {
+        let mut memory = Memory::new();
+        memory.write_slice_at(0x200, &[0x00, 0xE0, 0x12, 0x00]);
+        let listing = disassemble_range(&memory, 0x200, 2);
+        assert_eq!(
+            listing,
+            [(0x200, "CLS".to_string()), (0x202, "JP 0x200".to_string())]
+        );
+        assert_eq!(disassemble_range(&memory, MEMORY_SIZE - 4, 8).len(), 2);
+    }
+}
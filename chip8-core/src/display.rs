@@ -1,40 +1,432 @@
-extern crate piston_window;
-use piston_window::*;
+use std::fmt;
+use std::io;
+use std::path::Path;
 
+use crate::state::{StateError, StateReader, StateWriter};
+
+pub const DISPLAY_WIDTH: usize = 64;
+pub const DISPLAY_HEIGHT: usize = 32;
+
+pub type Rgb = [u8; 3];
+
+/// The largest image scale: frames any wider would not fit the 16-bit sizes
+/// of a GIF
+pub const MAX_SCALE: usize = u16::MAX as usize / DISPLAY_WIDTH;
+
+/// Colours indexed by pixel value: background first, then foreground
+pub const DEFAULT_PALETTE: [Rgb; 2] = [[255, 218, 244], [255, 255, 255]];
+
+/// Why the framebuffer could not be rendered to an image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportError {
+    /// A scale of zero would make an empty image
+    ZeroScale,
+    /// The image would be wider than the format can describe
+    ScaleTooLarge { max: usize },
+    /// There is no colour to draw pixels with
+    EmptyPalette,
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::ZeroScale => write!(f, "image scale must be at least 1"),
+            ExportError::ScaleTooLarge { max } => write!(f, "image scale must be at most {}", max),
+            ExportError::EmptyPalette => write!(f, "palette has no colours"),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+impl From<ExportError> for io::Error {
+    fn from(e: ExportError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, e)
+    }
+}
+
+// More separate dirty rectangles than this are merged into their bounding box
+const MAX_DIRTY_RECTS: usize = 8;
+
+/// A block of pixels, in display coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    pub const SCREEN: Rect = Rect {
+        x: 0,
+        y: 0,
+        width: DISPLAY_WIDTH,
+        height: DISPLAY_HEIGHT,
+    };
+
+    fn right(&self) -> usize {
+        self.x + self.width
+    }
+
+    fn bottom(&self) -> usize {
+        self.y + self.height
+    }
+
+    // Whether the two overlap or share an edge, so merging them costs nothing
+    fn touches(&self, other: &Rect) -> bool {
+        self.x <= other.right()
+            && other.x <= self.right()
+            && self.y <= other.bottom()
+            && other.y <= self.bottom()
+    }
+
+    fn union(&self, other: &Rect) -> Rect {
+        let (x, y) = (self.x.min(other.x), self.y.min(other.y));
+        Rect {
+            x,
+            y,
+            width: self.right().max(other.right()) - x,
+            height: self.bottom().max(other.bottom()) - y,
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Display {
-    pixels: [[u8; 32]; 64],
+    pixels: [[u8; DISPLAY_HEIGHT]; DISPLAY_WIDTH],
+    // What changed since a frontend last collected it
+    dirty: Vec<Rect>,
+}
+
+// Two displays are equal when they show the same picture
+impl PartialEq for Display {
+    fn eq(&self, other: &Self) -> bool {
+        self.pixels == other.pixels
+    }
+}
+
+impl Eq for Display {}
+
+impl Default for Display {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Display {
     pub fn new() -> Self {
         Display {
-            pixels: [[0u8; 32]; 64],
-        }
-    }
-
-    pub fn draw_graphics(&self, window: &mut PistonWindow, e: &Event) {
-        window.draw_2d(e, |c, g, _| {
-            clear([1.0, 218.0 / 255.0, 244.0 / 255.0, 1.0], g); // Clear the screen to (255,218,244)
-            for x in 0..64 {
-                for y in 0..32 {
-                    if self.pixels[x][y] != 0 {
-                        rectangle(
-                            [1.0, 1.0, 1.0, 1.0],                           // White color
-                            [x as f64 * 10.0, y as f64 * 10.0, 10.0, 10.0], // Scale each pixel to 10x10
-                            c.transform,
-                            g,
-                        );
-                    }
-                }
-            }
-        });
+            pixels: [[0u8; DISPLAY_HEIGHT]; DISPLAY_WIDTH],
+            dirty: vec![Rect::SCREEN],
+        }
+    }
+
+    /// Emulated resolution in pixels
+    pub fn width(&self) -> usize {
+        DISPLAY_WIDTH
+    }
+
+    pub fn height(&self) -> usize {
+        DISPLAY_HEIGHT
     }
 
     pub fn update_pixel(&mut self, x: usize, y: usize, val: u8) {
-        self.pixels[x][y] = val;
+        if self.pixels[x][y] != val {
+            self.pixels[x][y] = val;
+            self.mark_dirty(Rect {
+                x,
+                y,
+                width: 1,
+                height: 1,
+            });
+        }
+    }
+
+    /// Turn every pixel off
+    pub fn clear(&mut self) {
+        if self.pixels.iter().flatten().any(|&p| p != 0) {
+            self.pixels = [[0; DISPLAY_HEIGHT]; DISPLAY_WIDTH];
+            self.mark_dirty(Rect::SCREEN);
+        }
+    }
+
+    /// Shift the picture `dx` pixels right and `dy` pixels down (negative
+    /// values go left and up), filling the uncovered edge with dark pixels
+    pub fn scroll(&mut self, dx: isize, dy: isize) {
+        let old = self.pixels;
+        for x in 0..DISPLAY_WIDTH {
+            for y in 0..DISPLAY_HEIGHT {
+                let from = (x as isize - dx, y as isize - dy);
+                let inside = (0..DISPLAY_WIDTH as isize).contains(&from.0)
+                    && (0..DISPLAY_HEIGHT as isize).contains(&from.1);
+                self.pixels[x][y] = if inside {
+                    old[from.0 as usize][from.1 as usize]
+                } else {
+                    0
+                };
+            }
+        }
+        if self.pixels != old {
+            self.mark_dirty(Rect::SCREEN);
+        }
+    }
+
+    /// Whether anything changed since the dirty regions were last taken. A new
+    /// display counts as changed, since nothing has shown it yet.
+    pub fn is_dirty(&self) -> bool {
+        !self.dirty.is_empty()
+    }
+
+    /// The regions that changed since the last call, as a few non-touching
+    /// rectangles
+    pub fn take_dirty_rects(&mut self) -> Vec<Rect> {
+        std::mem::take(&mut self.dirty)
+    }
+
+    fn mark_dirty(&mut self, mut rect: Rect) {
+        // Swallow every rectangle the new one touches, growing it as it goes
+        while let Some(n) = self.dirty.iter().position(|r| r.touches(&rect)) {
+            rect = rect.union(&self.dirty.swap_remove(n));
+        }
+        self.dirty.push(rect);
+        if self.dirty.len() > MAX_DIRTY_RECTS {
+            let all = self.dirty.iter().fold(rect, |all, r| all.union(r));
+            self.dirty = vec![all];
+        }
     }
 
-    pub fn get_pixel(&mut self, x: usize, y: usize) -> u8 {
+    pub fn get_pixel(&self, x: usize, y: usize) -> u8 {
         self.pixels[x][y]
     }
+
+    /// Render the framebuffer as packed 8-bit RGB, each pixel scaled to a
+    /// `scale`x`scale` block. The scale must be 1 to `MAX_SCALE`.
+    pub fn to_rgb(&self, scale: usize, palette: &[Rgb]) -> Result<Vec<u8>, ExportError> {
+        if scale == 0 {
+            return Err(ExportError::ZeroScale);
+        }
+        if palette.is_empty() {
+            return Err(ExportError::EmptyPalette);
+        }
+        let too_large = ExportError::ScaleTooLarge { max: MAX_SCALE };
+        if scale > MAX_SCALE {
+            return Err(too_large);
+        }
+        let (width, height) = (DISPLAY_WIDTH * scale, DISPLAY_HEIGHT * scale);
+        // Even the largest scale is too much for a 32-bit address space
+        let len = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(3))
+            .ok_or(too_large)?;
+        let mut rgb = Vec::with_capacity(len);
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = self.pixels[x / scale][y / scale] as usize;
+                rgb.extend_from_slice(&palette[pixel.min(palette.len() - 1)]);
+            }
+        }
+        Ok(rgb)
+    }
+
+    /// Render the framebuffer as packed 8-bit RGBA at one texel per pixel
+    pub fn to_rgba(&self, palette: &[Rgb]) -> Result<Vec<u8>, ExportError> {
+        self.rect_to_rgba(Rect::SCREEN, palette)
+    }
+
+    /// Render part of the framebuffer as packed 8-bit RGBA, row by row
+    pub fn rect_to_rgba(&self, rect: Rect, palette: &[Rgb]) -> Result<Vec<u8>, ExportError> {
+        if palette.is_empty() {
+            return Err(ExportError::EmptyPalette);
+        }
+        let mut rgba = Vec::with_capacity(rect.width * rect.height * 4);
+        for y in rect.y..rect.bottom() {
+            for x in rect.x..rect.right() {
+                let pixel = self.pixels[x][y] as usize;
+                rgba.extend_from_slice(&palette[pixel.min(palette.len() - 1)]);
+                rgba.push(0xFF);
+            }
+        }
+        Ok(rgba)
+    }
+
+    /// Encode the framebuffer as a binary PPM (P6) image
+    pub fn to_ppm(&self, scale: usize, palette: &[Rgb]) -> Result<Vec<u8>, ExportError> {
+        let rgb = self.to_rgb(scale, palette)?;
+        let mut ppm = format!(
+            "P6\n{} {}\n255\n",
+            DISPLAY_WIDTH * scale,
+            DISPLAY_HEIGHT * scale
+        )
+        .into_bytes();
+        ppm.extend(rgb);
+        Ok(ppm)
+    }
+
+    /// Encode the framebuffer as a PNG image
+    pub fn to_png(&self, scale: usize, palette: &[Rgb]) -> Result<Vec<u8>, ExportError> {
+        let rgb = self.to_rgb(scale, palette)?;
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(
+            &mut png,
+            (DISPLAY_WIDTH * scale) as u32,
+            (DISPLAY_HEIGHT * scale) as u32,
+        );
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        // Writing into a Vec cannot fail
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&rgb).unwrap();
+        writer.finish().unwrap();
+        Ok(png)
+    }
+
+    /// Write a screenshot, picking PNG or PPM from the file extension
+    pub fn save_screenshot(&self, path: &Path, scale: usize, palette: &[Rgb]) -> io::Result<()> {
+        let bytes = match path.extension().and_then(|e| e.to_str()) {
+            Some("png") => self.to_png(scale, palette)?,
+            Some("ppm") => self.to_ppm(scale, palette)?,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "screenshots must be .png or .ppm",
+                ))
+            }
+        };
+        std::fs::write(path, bytes)
+    }
+
+    pub(crate) fn save_state(&self, w: &mut StateWriter) {
+        for column in self.pixels.iter() {
+            w.bytes(column);
+        }
+    }
+
+    pub(crate) fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        for column in self.pixels.iter_mut() {
+            column.copy_from_slice(r.bytes(DISPLAY_HEIGHT)?);
+        }
+        self.dirty = vec![Rect::SCREEN];
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ppm_export_scales_pixels() {
+        let mut display = Display::new();
+        display.update_pixel(1, 0, 1);
+
+        let ppm = display.to_ppm(2, &DEFAULT_PALETTE).unwrap();
+        let header = b"P6\n128 64\n255\n";
+        assert_eq!(&ppm[..header.len()], header);
+
+        let rgb = &ppm[header.len()..];
+        assert_eq!(rgb.len(), 128 * 64 * 3);
+        let at = |x: usize, y: usize| &rgb[(y * 128 + x) * 3..(y * 128 + x) * 3 + 3];
+        assert_eq!(at(1, 1), DEFAULT_PALETTE[0]);
+        assert_eq!(at(2, 0), DEFAULT_PALETTE[1]);
+        assert_eq!(at(3, 1), DEFAULT_PALETTE[1]);
+        assert_eq!(at(4, 0), DEFAULT_PALETTE[0]);
+    }
+
+    fn rect(x: usize, y: usize, width: usize, height: usize) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn only_changes_make_the_display_dirty() {
+        let mut display = Display::new();
+        assert_eq!(display.take_dirty_rects(), [Rect::SCREEN]);
+        assert!(!display.is_dirty());
+        display.update_pixel(5, 5, 0);
+        display.clear();
+        display.scroll(0, 4);
+        assert!(!display.is_dirty());
+
+        display.update_pixel(5, 5, 1);
+        assert_eq!(display.take_dirty_rects(), [rect(5, 5, 1, 1)]);
+        display.clear();
+        assert_eq!(display.take_dirty_rects(), [Rect::SCREEN]);
+    }
+
+    #[test]
+    fn dirty_pixels_merge_into_rects() {
+        let mut display = Display::new();
+        display.take_dirty_rects();
+        // A sprite's worth of neighbouring pixels, and one far away
+        for (x, y) in [(10, 10), (11, 10), (12, 11), (10, 12), (40, 20)] {
+            display.update_pixel(x, y, 1);
+        }
+        let mut rects = display.take_dirty_rects();
+        rects.sort_by_key(|r| r.x);
+        assert_eq!(rects, [rect(10, 10, 3, 3), rect(40, 20, 1, 1)]);
+
+        // Too many scattered changes collapse into one box
+        for n in 0..=MAX_DIRTY_RECTS {
+            display.update_pixel(n * 3, n * 3, 1);
+        }
+        let last = MAX_DIRTY_RECTS * 3;
+        assert_eq!(display.take_dirty_rects(), [rect(0, 0, last + 1, last + 1)]);
+
+        let rgba = display.rect_to_rgba(rect(3, 3, 2, 1), &DEFAULT_PALETTE);
+        assert_eq!(rgba.unwrap(), [255, 255, 255, 255, 255, 218, 244, 255]);
+    }
+
+    #[test]
+    fn scroll_moves_pixels_and_fills_with_dark() {
+        let mut display = Display::new();
+        display.update_pixel(0, 0, 1);
+        display.update_pixel(63, 31, 1);
+        display.scroll(4, 0);
+        assert_eq!(display.get_pixel(4, 0), 1);
+        assert_eq!(display.get_pixel(0, 0), 0);
+        assert_eq!(display.get_pixel(63, 31), 0);
+        display.scroll(-2, 1);
+        assert_eq!(display.get_pixel(2, 1), 1);
+        assert_eq!(display.get_pixel(4, 0), 0);
+    }
+
+    #[test]
+    fn png_export_roundtrip() {
+        let mut display = Display::new();
+        display.update_pixel(63, 31, 1);
+
+        let png = display.to_png(3, &DEFAULT_PALETTE).unwrap();
+        let decoder = png::Decoder::new(png.as_slice());
+        let mut reader = decoder.read_info().unwrap();
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf).unwrap();
+        assert_eq!((info.width, info.height), (192, 96));
+        assert_eq!(
+            buf[..info.buffer_size()],
+            display.to_rgb(3, &DEFAULT_PALETTE).unwrap()
+        );
+    }
+
+    #[test]
+    fn export_rejects_bad_scales_and_empty_palette() {
+        let display = Display::new();
+        assert_eq!(
+            display.to_png(0, &DEFAULT_PALETTE),
+            Err(ExportError::ZeroScale)
+        );
+        assert_eq!(display.to_rgb(1, &[]), Err(ExportError::EmptyPalette));
+        assert_eq!(display.to_ppm(1, &[]), Err(ExportError::EmptyPalette));
+        assert_eq!(display.to_rgba(&[]), Err(ExportError::EmptyPalette));
+        for scale in [MAX_SCALE + 1, usize::MAX] {
+            assert_eq!(
+                display.to_png(scale, &DEFAULT_PALETTE),
+                Err(ExportError::ScaleTooLarge { max: MAX_SCALE })
+            );
+        }
+    }
 }
@@ -1,49 +1,162 @@
 #![allow(dead_code)]
-extern crate piston_window;
-use crate::display::Display;
-use crate::keyboard::Keyboard;
-use crate::memory::Memory;
+use std::fmt;
+
+use crate::decode::Opcode;
+use crate::display::{Display, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use crate::keyboard::{KeyWait, Keyboard};
+use crate::memory::{Memory, MEMORY_SIZE};
+use crate::platform::{Platform, Quirks};
+use crate::rng::Rng;
+use crate::state::{StateError, StateReader, StateWriter};
+use crate::timing::{vip_cycles, Timing, VIP_INTERPRETER_CYCLES_PER_FRAME};
+
+/// Instructions executed per 60 Hz frame, roughly 500 instructions per second
+pub const DEFAULT_CYCLES_PER_FRAME: usize = 8;
+
+/// Where ROMs are loaded and execution starts
+pub const ROM_START: usize = 0x200;
+/// The most a ROM can hold, everything from `ROM_START` to the end of memory
+pub const MAX_ROM_SIZE: usize = MEMORY_SIZE - ROM_START;
+
+// The stack starts out with this many zeroes on it
+pub(crate) const STACK_START: usize = 16;
+/// Most return addresses the stack holds: room for 32 nested calls on top of
+/// the zeroes it starts with. Deeper calls lose their return address.
+pub const STACK_SIZE: usize = STACK_START + 32;
+
+// Sound timer value kept up while FX0A waits for a held key to be released
+pub(crate) const KEY_WAIT_BEEP: u8 = 4;
+
+/// A ROM longer than `MAX_ROM_SIZE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RomTooLarge {
+    pub len: usize,
+}
+
+impl fmt::Display for RomTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ROM is {} bytes, at most {} fit", self.len, MAX_ROM_SIZE)
+    }
+}
+
+impl std::error::Error for RomTooLarge {}
 
+#[derive(Clone)]
 pub struct Chip8 {
-    registers: [u8; 16], // V0 to VF
-    stack: Vec<u16>,
-    memory: Memory,
-    display: Display,
-    pc: u16,
+    pub(crate) registers: [u8; 16], // V0 to VF
+    pub(crate) stack: Vec<u16>,
+    pub(crate) memory: Memory,
+    pub(crate) display: Display,
+    pub(crate) pc: u16,
     sp: u8,
-    i: u16,
-    delay_timer: u8,
-    sound_timer: u8,
-    key: Keyboard,
+    pub(crate) i: u16,
+    pub(crate) delay_timer: u8,
+    pub(crate) sound_timer: u8,
+    pub(crate) key: Keyboard,
+    cycles_per_frame: usize,
+    timing: Timing,
+    // VIP machine cycles the last frame overran its budget by
+    pub(crate) cycle_debt: u32,
+    platform: Platform,
+    quirks: Quirks,
+    seed: u64,
+    pub(crate) rng: Rng,
+    pub(crate) frame: u64,
 }
 
-// enum Opcode {
-//     OP_1NNN(u16),
-//     OP_4XNN(u8, u8),
-//     OP_6XNN(u8, u8),
-//     OP_7XNN(u8, u8),
-//     OP_8XY4(u8, u8),
-//     OP_ANNN(u16),
-//     OP_DXYN(u8, u8, u8),
-//     UNKNOWN,
-// }
+impl Default for Chip8 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl Chip8 {
     pub fn new() -> Self {
         Chip8 {
             registers: [0u8; 16],
-            stack: vec![0; 16],
+            stack: vec![0; STACK_START],
             memory: Memory::new(),
             display: Display::new(),
-            pc: 0x200,
+            pc: ROM_START as u16,
             sp: 0x00,
             i: 0x0000,
             delay_timer: 0x00,
             sound_timer: 0x00,
             key: Keyboard::new(),
+            cycles_per_frame: DEFAULT_CYCLES_PER_FRAME,
+            timing: Timing::default(),
+            cycle_debt: 0,
+            platform: Platform::default(),
+            quirks: Platform::default().quirks(),
+            seed: 0,
+            rng: Rng::new(0),
+            frame: 0,
         }
     }
 
+    /// Power on afresh, keeping the platform, quirks, timing and seed
+    pub fn reset(&mut self) {
+        let mut next = Chip8::new();
+        next.cycles_per_frame = self.cycles_per_frame;
+        next.timing = self.timing;
+        next.platform = self.platform;
+        next.quirks = self.quirks;
+        next.set_seed(self.seed);
+        *self = next;
+    }
+
+    /// Switch to another platform, resetting the quirks to its defaults
+    pub fn set_platform(&mut self, platform: Platform) {
+        self.platform = platform;
+        self.quirks = platform.quirks();
+    }
+
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// Override individual behaviours of the current platform
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    /// Reseed the random number generator used by CXNN
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = Rng::new(seed);
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Number of frames run since power on
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn set_cycles_per_frame(&mut self, cycles: usize) {
+        self.cycles_per_frame = cycles;
+    }
+
+    pub fn cycles_per_frame(&self) -> usize {
+        self.cycles_per_frame
+    }
+
+    /// Choose between a fixed instruction count and the VIP cycle budget per frame
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.timing = timing;
+        self.cycle_debt = 0;
+    }
+
+    pub fn timing(&self) -> Timing {
+        self.timing
+    }
+
     pub fn key_mut(&mut self) -> &mut Keyboard {
         &mut self.key
     }
@@ -52,9 +165,91 @@ impl Chip8 {
         &mut self.display
     }
 
+    pub fn display(&self) -> &Display {
+        &self.display
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.registers
+    }
+
+    pub fn registers_mut(&mut self) -> &mut [u8; 16] {
+        &mut self.registers
+    }
+
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    pub fn i(&self) -> u16 {
+        self.i
+    }
+
+    pub fn set_i(&mut self, i: u16) {
+        self.i = i;
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.delay_timer = value;
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.sound_timer = value;
+    }
+
+    /// The instruction the next cycle will execute
+    pub fn current_opcode(&self) -> u16 {
+        let pc = self.pc as usize;
+        u16::from_be_bytes([self.memory.get_byte(pc), self.memory.get_byte(pc + 1)])
+    }
+
     pub fn load_rom(&mut self, filename: &str) {
         let rom_bytes = std::fs::read(filename).expect("Failed to read ROM file");
-        self.memory.write_slice_at(0x200, &rom_bytes);
+        self.load_rom_bytes(&rom_bytes);
+    }
+
+    /// Copy a ROM into memory at `ROM_START`. Panics if it is longer than
+    /// `MAX_ROM_SIZE`; `try_load_rom_bytes` reports that instead.
+    pub fn load_rom_bytes(&mut self, rom_bytes: &[u8]) {
+        if let Err(e) = self.try_load_rom_bytes(rom_bytes) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Like `load_rom_bytes`, but a ROM that does not fit is an error and
+    /// leaves memory untouched
+    pub fn try_load_rom_bytes(&mut self, rom_bytes: &[u8]) -> Result<(), RomTooLarge> {
+        if rom_bytes.len() > MAX_ROM_SIZE {
+            return Err(RomTooLarge {
+                len: rom_bytes.len(),
+            });
+        }
+        self.memory.write_slice_at(ROM_START, rom_bytes);
+        Ok(())
     }
 
     pub fn delay_timer_tick(&mut self) {
@@ -63,172 +258,176 @@ impl Chip8 {
         }
     }
 
-    pub fn emulate_cycle(&mut self) {
-        // Fetch the instruction from memory at the current pc
-        let v = u16::from_be_bytes([
-            self.memory.get_byte(self.pc as usize),
-            self.memory.get_byte((self.pc + 1) as usize),
-        ]);
-        // Increment pc
-        self.pc += 2;
-
-        // Decode and Execute the instruction
-        match v & 0xF000 {
-            0x0000 => match v & 0x000F {
-                0x0000 => {
-                    self.op_00e0();
-                }
-                0x000E => {
-                    self.op_00ee();
-                }
-                _ => println!("OPCODE {} not implemented!", v),
-            },
-            0x1000 => {
-                // 1NNN
-                let nnn: u16 = v & 0x0FFF;
-                self.op_1nnn(nnn);
-            }
-            0x2000 => {
-                // 2NNN
-                let nnn: u16 = (v & 0x0FFF) as u16;
-                self.op_2nnn(nnn);
-            }
-            0x3000 => {
-                // 3XNN
-                let x = ((v & 0x0F00) >> 8) as u8;
-                let nn: u8 = (v & 0x00FF) as u8;
-                self.op_3xnn(x, nn);
-            }
-            0x4000 => {
-                // 4XNN
-                let x = ((v & 0x0F00) >> 8) as u8;
-                let nn: u8 = (v & 0x00FF) as u8;
-                self.op_4xnn(x, nn);
-            }
-            0x5000 => {
-                // 5XY0
-                let x = ((v & 0x0F00) >> 8) as u8;
-                let y = ((v & 0x00F0) >> 4) as u8;
-                self.op_5xy0(x, y);
-            }
-            0x6000 => {
-                // 6XNN
-                let x = ((v & 0x0F00) >> 8) as u8;
-                let nn: u8 = (v & 0x00FF) as u8;
-                self.op_6xnn(x as usize, nn);
-            }
-            0x7000 => {
-                // 7XNN
-                let x = ((v & 0x0F00) >> 8) as u8;
-                let nn: u8 = (v & 0x00FF) as u8;
-                self.op_7xnn(x, nn);
-            }
-            0x8000 => {
-                let x = ((v & 0x0F00) >> 8) as u8;
-                let y = ((v & 0x00F0) >> 4) as u8;
-                match v & 0x000F {
-                    0x0000 => {
-                        self.op_8xy0(x, y);
-                    }
-                    0x0001 => {
-                        self.op_8xy1(x, y);
-                    }
-                    0x0002 => {
-                        self.op_8xy2(x, y);
-                    }
-                    0x0003 => {
-                        self.op_8xy3(x, y);
-                    }
-                    0x0004 => {
-                        self.op_8xy4(x, y);
-                    }
-                    0x0005 => {
-                        self.op_8xy5(x, y);
-                    }
-                    0x0006 => {
-                        self.op_8xy6(x, y);
-                    }
-                    0x0007 => {
-                        self.op_8xy7(x, y);
-                    }
-                    0x000E => {
-                        self.op_8xye(x, y);
-                    }
-                    _ => println!("OPCODE {} not implemented!", v),
-                }
-            }
-            0x9000 => {
-                // 9xy0
-                let x = ((v & 0x0F00) >> 8) as u8;
-                let y = ((v & 0x00F0) >> 4) as u8;
-                self.op_9xy0(x, y);
-            }
-            0xA000 => {
-                // ANNN
-                let nnn: u16 = v & 0x0FFF;
-                self.op_annn(nnn);
-            }
-            0xB000 => {
-                // BNNN - TODO
+    pub fn sound_timer_tick(&mut self) {
+        if self.sound_timer != 0 {
+            self.sound_timer -= 1;
+        }
+    }
+
+    /// Whether the beeper is sounding
+    pub fn sound_active(&self) -> bool {
+        self.sound_timer > 0
+    }
+
+    /// Run one 60 Hz frame: a batch of instructions followed by a timer tick
+    pub fn run_frame(&mut self) {
+        self.run_frame_until(|_| false);
+    }
+
+    /// Like `run_frame`, but `stop` is checked before every instruction and
+    /// ends the frame early when it returns true. Returns whether it stopped.
+    pub fn run_frame_until<F: FnMut(&Chip8) -> bool>(&mut self, mut stop: F) -> bool {
+        let budget = VIP_INTERPRETER_CYCLES_PER_FRAME;
+        let mut executed = 0;
+        let mut spent = self.cycle_debt;
+        loop {
+            let more = match self.timing {
+                Timing::Fixed => executed < self.cycles_per_frame,
+                Timing::CosmacVip => spent < budget,
+            };
+            if !more {
+                break;
             }
-            0xC000 => {
-                // CXNN - TODO
+            if stop(self) {
+                return true;
             }
-            0xD000 => {
-                // DXYN
-                let vx = ((v & 0x0F00) >> 8) as u8;
-                let vy = ((v & 0x00F0) >> 4) as u8;
-                let n: u8 = (v & 0x000F) as u8;
-                self.op_dxyn(vx, vy, n);
+            // Only the VIP cycle count needs the raw word and the registers it ran on
+            let before = (self.timing == Timing::CosmacVip)
+                .then(|| (self.current_opcode(), self.pc, self.registers));
+            let opcode = self.step();
+            executed += 1;
+            if let Some((word, pc, registers)) = before {
+                spent += vip_cycles(word, &registers, self.pc == pc.wrapping_add(4));
             }
-            0xE000 => {
-                let x = ((v & 0x0F00) >> 8) as u8;
-                match v & 0x00FF {
-                    0x009E => {
-                        self.op_ex9e(x);
-                    }
-                    0x00A1 => {
-                        self.op_exa1(x);
-                    }
-                    _ => println!("OPCODE {} not implemented!", v),
-                }
+            // With the display wait quirk DXYN waits for the vertical blank,
+            // which ends the frame
+            if self.quirks.display_wait && matches!(opcode, Opcode::OpDXYN(..)) {
+                spent = budget;
+                break;
             }
-            0xF000 => {
-                let x = ((v & 0x0F00) >> 8) as u8;
-                match v & 0x00FF {
-                    0x0007 => {
-                        self.op_fx07(x);
-                    }
-                    0x000A => {
-                        self.op_fx0a(x);
-                    }
-                    0x0015 => {
-                        self.op_fx15(x);
-                    }
-                    0x0018 => {
-                        // self.op_fx18(x);
-                    }
-                    0x001E => {
-                        self.op_fx1e(x);
-                    }
-                    0x0029 => {
-                        // self.op_fx29(x);
-                    }
-                    0x0033 => {
-                        self.op_fx33(x);
-                    }
-                    0x0055 => {
-                        self.op_fx55(x);
-                    }
-                    0x0065 => {
-                        self.op_fx65(x);
-                    }
-
-                    _ => println!("OPCODE {} not implemented!", v),
-                }
+        }
+        // Whatever the last instruction overran by comes out of the next frame
+        self.cycle_debt = match self.timing {
+            Timing::Fixed => 0,
+            Timing::CosmacVip => spent.saturating_sub(budget),
+        };
+        self.delay_timer_tick();
+        self.sound_timer_tick();
+        self.frame += 1;
+        false
+    }
+
+    /// Serialize the whole machine into a byte buffer
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        w.bytes(&self.registers);
+        w.u16(self.stack.len() as u16);
+        for &address in &self.stack {
+            w.u16(address);
+        }
+        w.u16(self.pc);
+        w.u8(self.sp);
+        w.u16(self.i);
+        w.u8(self.delay_timer);
+        w.u8(self.sound_timer);
+        self.memory.save_state(&mut w);
+        self.display.save_state(&mut w);
+        self.key.save_state(&mut w);
+        w.u64(self.rng.state());
+        w.u64(self.frame);
+        w.u16(self.cycle_debt as u16);
+        w.finish()
+    }
+
+    /// Restore the machine from a buffer produced by `save_state`
+    pub fn load_state(&mut self, bytes: &[u8]) -> Result<(), StateError> {
+        // Decode into a scratch machine so a bad buffer leaves `self` untouched
+        let mut next = Chip8::new();
+        let mut r = StateReader::new(bytes)?;
+        next.registers.copy_from_slice(r.bytes(16)?);
+        let stack_len = r.u16()?;
+        if stack_len as usize > STACK_SIZE {
+            return Err(StateError::Corrupt("stack depth"));
+        }
+        next.stack.clear();
+        for _ in 0..stack_len {
+            next.stack.push(r.u16()?);
+        }
+        next.pc = r.u16()?;
+        next.sp = r.u8()?;
+        next.i = r.u16()?;
+        next.delay_timer = r.u8()?;
+        next.sound_timer = r.u8()?;
+        next.memory.load_state(&mut r)?;
+        next.display.load_state(&mut r)?;
+        next.key.load_state(&mut r)?;
+        next.rng = Rng::from_state(r.u64()?).ok_or(StateError::Corrupt("rng state"))?;
+        next.frame = r.u64()?;
+        next.cycle_debt = r.u16()? as u32;
+        next.cycles_per_frame = self.cycles_per_frame;
+        next.timing = self.timing;
+        next.platform = self.platform;
+        next.quirks = self.quirks;
+        next.seed = self.seed;
+        *self = next;
+        Ok(())
+    }
+
+    /// Execute a single instruction. The timers only count down in `run_frame`.
+    pub fn emulate_cycle(&mut self) {
+        self.step();
+    }
+
+    // Execute the instruction at pc, returning it
+    fn step(&mut self) -> Opcode {
+        // Fetch the instruction, decoded already unless it is new or was overwritten
+        let opcode = self.memory.opcode(self.pc as usize);
+        // Increment pc; fetches past the end of memory wrap around to 0
+        self.pc = self.pc.wrapping_add(2);
+
+        // Execute the instruction
+        match opcode {
+            Opcode::Op00E0 => self.op_00e0(),
+            Opcode::Op00EE => self.op_00ee(),
+            Opcode::Op1NNN(nnn) => self.op_1nnn(nnn),
+            Opcode::Op2NNN(nnn) => self.op_2nnn(nnn),
+            Opcode::Op3XNN(x, nn) => self.op_3xnn(x, nn),
+            Opcode::Op4XNN(x, nn) => self.op_4xnn(x, nn),
+            Opcode::Op5XY0(x, y) => self.op_5xy0(x, y),
+            Opcode::Op6XNN(x, nn) => self.op_6xnn(x as usize, nn),
+            Opcode::Op7XNN(x, nn) => self.op_7xnn(x, nn),
+            Opcode::Op8XY0(x, y) => self.op_8xy0(x, y),
+            Opcode::Op8XY1(x, y) => self.op_8xy1(x, y),
+            Opcode::Op8XY2(x, y) => self.op_8xy2(x, y),
+            Opcode::Op8XY3(x, y) => self.op_8xy3(x, y),
+            Opcode::Op8XY4(x, y) => self.op_8xy4(x, y),
+            Opcode::Op8XY5(x, y) => self.op_8xy5(x, y),
+            Opcode::Op8XY6(x, y) => self.op_8xy6(x, y),
+            Opcode::Op8XY7(x, y) => self.op_8xy7(x, y),
+            Opcode::Op8XYE(x, y) => self.op_8xye(x, y),
+            Opcode::Op9XY0(x, y) => self.op_9xy0(x, y),
+            Opcode::OpANNN(nnn) => self.op_annn(nnn),
+            Opcode::OpBNNN(nnn) => self.op_bnnn(nnn),
+            Opcode::OpCXNN(x, nn) => self.op_cxnn(x, nn),
+            Opcode::OpDXYN(x, y, n) => self.op_dxyn(x, y, n),
+            Opcode::OpEX9E(x) => self.op_ex9e(x),
+            Opcode::OpEXA1(x) => self.op_exa1(x),
+            Opcode::OpFX07(x) => self.op_fx07(x),
+            Opcode::OpFX0A(x) => self.op_fx0a(x),
+            Opcode::OpFX15(x) => self.op_fx15(x),
+            Opcode::OpFX18(x) => self.op_fx18(x),
+            Opcode::OpFX1E(x) => self.op_fx1e(x),
+            Opcode::OpFX29(_x) => {
+                // self.op_fx29(x);
             }
-            _ => println!("OPCODE {} not implemented!", v),
+            Opcode::OpFX33(x) => self.op_fx33(x),
+            Opcode::OpFX55(x) => self.op_fx55(x),
+            Opcode::OpFX65(x) => self.op_fx65(x),
+            // Skipped silently: frontends may own stdout for screen dumps or a
+            // raw-mode terminal
+            Opcode::Unknown(_) => {}
         }
+        opcode
     }
 }
 
@@ -236,7 +435,7 @@ impl Chip8 {
 impl Chip8 {
     /// 00E0 - CLS
     fn op_00e0(&mut self) {
-        self.display = Display::new();
+        self.display.clear();
     }
 
     /// 00EE - RET
@@ -253,28 +452,30 @@ impl Chip8 {
 
     // 2NNN - CALL addr
     fn op_2nnn(&mut self, nnn: u16) {
-        self.stack.push(self.pc);
+        if self.stack.len() < STACK_SIZE {
+            self.stack.push(self.pc);
+        }
         self.pc = nnn;
     }
 
     // 3XNN - SE Vx, byte
     fn op_3xnn(&mut self, x: u8, nn: u8) {
         if self.registers[x as usize] == nn {
-            self.pc += 2;
+            self.pc = self.pc.wrapping_add(2);
         }
     }
 
     // 4XNN - SNE Vx, byte
     fn op_4xnn(&mut self, x: u8, nn: u8) {
         if self.registers[x as usize] != nn {
-            self.pc += 2;
+            self.pc = self.pc.wrapping_add(2);
         }
     }
 
     // 5XY0 - SE Vx, Vy
     fn op_5xy0(&mut self, x: u8, y: u8) {
         if self.registers[x as usize] == self.registers[y as usize] {
-            self.pc += 2;
+            self.pc = self.pc.wrapping_add(2);
         }
     }
 
@@ -295,17 +496,27 @@ impl Chip8 {
 
     // 8XY1 - OR Vx, Vy
     fn op_8xy1(&mut self, x: u8, y: u8) {
-        self.registers[x as usize] = self.registers[x as usize] | self.registers[y as usize];
+        self.registers[x as usize] |= self.registers[y as usize];
+        self.logic_vf_reset();
     }
 
     // 8XY2 - AND Vx, Vy
     fn op_8xy2(&mut self, x: u8, y: u8) {
-        self.registers[x as usize] = self.registers[x as usize] & self.registers[y as usize];
+        self.registers[x as usize] &= self.registers[y as usize];
+        self.logic_vf_reset();
     }
 
     // 8XY3 - XOR Vx, Vy
     fn op_8xy3(&mut self, x: u8, y: u8) {
-        self.registers[x as usize] = self.registers[x as usize] ^ self.registers[y as usize];
+        self.registers[x as usize] ^= self.registers[y as usize];
+        self.logic_vf_reset();
+    }
+
+    // The VIP interpreter clobbers vF in 8XY1, 8XY2 and 8XY3
+    fn logic_vf_reset(&mut self) {
+        if self.quirks.vf_reset {
+            self.registers[0x0F] = 0;
+        }
     }
 
     // 8XY4 - ADD Vx, Vy
@@ -339,10 +550,12 @@ impl Chip8 {
     // 8XY6 - SHR Vx {, Vy}
     fn op_8xy6(&mut self, x: u8, y: u8) {
         // Set vX to vY
-        self.registers[x as usize] = self.registers[y as usize];
+        if self.quirks.shift_uses_vy {
+            self.registers[x as usize] = self.registers[y as usize];
+        }
         let shifted_bit: u8 = self.registers[x as usize] & 0x01;
         // Shift right by one
-        self.registers[x as usize] = self.registers[x as usize] >> 1;
+        self.registers[x as usize] >>= 1;
         // Store shifted bit in vF
         self.registers[0x0F] = shifted_bit;
     }
@@ -364,10 +577,12 @@ impl Chip8 {
     // 8XYE - SHL Vx {, Vy}
     fn op_8xye(&mut self, x: u8, y: u8) {
         // Set vX to vY
-        let shifted_bit: u8 = (self.registers[y as usize] & 0x80) >> 7;
-        self.registers[y as usize] = self.registers[y as usize] << 1;
-        self.registers[x as usize] = self.registers[y as usize];
-        // Shift right by one
+        if self.quirks.shift_uses_vy {
+            self.registers[x as usize] = self.registers[y as usize];
+        }
+        let shifted_bit: u8 = (self.registers[x as usize] & 0x80) >> 7;
+        // Shift left by one
+        self.registers[x as usize] <<= 1;
         // Store shifted bit in vF
         self.registers[0x0F] = shifted_bit;
     }
@@ -375,7 +590,7 @@ impl Chip8 {
     // 9XY0 - SNE Vx, Vy
     fn op_9xy0(&mut self, x: u8, y: u8) {
         if self.registers[x as usize] != self.registers[y as usize] {
-            self.pc += 2;
+            self.pc = self.pc.wrapping_add(2);
         }
     }
 
@@ -389,17 +604,29 @@ impl Chip8 {
         self.delay_timer = self.registers[x as usize];
     }
 
+    // Fx18 - LD ST, Vx
+    fn op_fx18(&mut self, x: u8) {
+        self.sound_timer = self.registers[x as usize];
+    }
+
     // FX65 - LD Vx, [I]
     fn op_fx65(&mut self, x: u8) {
-        let mem_bytes = self.memory.read_slice_at(self.i as usize, x as usize);
-        self.registers[0..=(x as usize)].copy_from_slice(mem_bytes);
-        self.i += (x + 1) as u16;
+        for r in 0..=(x as usize) {
+            self.registers[r] = self.memory.get_byte(self.i as usize + r);
+        }
+        if self.quirks.load_store_increments_i {
+            self.i = self.i.wrapping_add((x + 1) as u16);
+        }
     }
 
     // FX55 - LD [I], Vx
     fn op_fx55(&mut self, x: u8) {
-        self.memory
-            .write_slice_at(self.i as usize, &self.registers[..=(x as usize)]);
+        for r in 0..=(x as usize) {
+            self.memory.set_byte(self.i as usize + r, self.registers[r]);
+        }
+        if self.quirks.load_store_increments_i {
+            self.i = self.i.wrapping_add((x + 1) as u16);
+        }
     }
 
     // FX33 - LD B, Vx
@@ -409,40 +636,63 @@ impl Chip8 {
         let tens = (vx % 100) / 10;
         let ones = vx % 10;
 
-        self.memory
-            .write_slice_at(self.i as usize, &[hundreds, tens, ones]);
+        for (k, digit) in [hundreds, tens, ones].into_iter().enumerate() {
+            self.memory.set_byte(self.i as usize + k, digit);
+        }
     }
 
     // Fx1E
     fn op_fx1e(&mut self, x: u8) {
-        self.i += self.registers[x as usize] as u16;
+        self.i = self.i.wrapping_add(self.registers[x as usize] as u16);
     }
 
     // FX0A - LD Vx, K
     fn op_fx0a(&mut self, x: u8) {
-        self.key_mut().set_waiting_for_key_release();
-        match self.key_mut().get_key_register() {
-            Some(key) => self.registers[x as usize] = key as u8,
-            None => self.pc -= 2,
+        if let Some(key) = self.key.take_waited_key() {
+            self.registers[x as usize] = key as u8;
+            return;
         }
+        self.key.begin_key_wait(self.quirks.key_wait_on_release);
+        // The VIP interpreter sounds the tone for as long as the key is held
+        if let KeyWait::Release(_) = self.key.key_wait() {
+            self.sound_timer = self.sound_timer.max(KEY_WAIT_BEEP);
+        }
+        self.pc = self.pc.wrapping_sub(2);
     }
 
     // Ex9E - SKP Vx
     fn op_ex9e(&mut self, x: u8) {
-        let key = self.registers[x as usize];
+        // Only the low nibble picks the key, as on the VIP
+        let key = self.registers[x as usize] & 0x0F;
         if self.key_mut().key_is_pressed(key) {
-            self.pc += 2;
+            self.pc = self.pc.wrapping_add(2);
         }
     }
 
     // EXA1 - SKNP Vx
     fn op_exa1(&mut self, x: u8) {
-        let key = self.registers[x as usize];
+        let key = self.registers[x as usize] & 0x0F;
         if !self.key_mut().key_is_pressed(key) {
-            self.pc += 2;
+            self.pc = self.pc.wrapping_add(2);
         }
     }
 
+    // BNNN - JP V0, addr
+    fn op_bnnn(&mut self, nnn: u16) {
+        // With the jump quirk this is BXNN, offsetting by vX instead of v0
+        let offset_register = if self.quirks.jump_uses_vx {
+            (nnn >> 8) as usize
+        } else {
+            0
+        };
+        self.pc = (nnn + self.registers[offset_register] as u16) & 0x0FFF;
+    }
+
+    // CXNN - RND Vx, byte
+    fn op_cxnn(&mut self, x: u8, nn: u8) {
+        self.registers[x as usize] = self.rng.next_byte() & nn;
+    }
+
     // ANNN - LD I, addr
     fn op_annn(&mut self, nnn: u16) {
         self.i = nnn;
@@ -451,19 +701,29 @@ impl Chip8 {
     // DXYN - DRW Vx, Vy, nibble
     fn op_dxyn(&mut self, vx: u8, vy: u8, n: u8) {
         self.registers[0xF] = 0; // Reset VF before starting the drawing
+
+        // The start position always wraps, the sprite itself clips or wraps per quirk
+        let x0 = self.registers[vx as usize] as usize % DISPLAY_WIDTH;
+        let y0 = self.registers[vy as usize] as usize % DISPLAY_HEIGHT;
         for byte_index in 0..n {
-            let byte = self.memory.get_byte((self.i + byte_index as u16) as usize);
+            let y = y0 + byte_index as usize;
+            if y >= DISPLAY_HEIGHT && self.quirks.clip_sprites {
+                break;
+            }
+            let byte = self.memory.get_byte(self.i as usize + byte_index as usize);
             for bit_index in 0..8 {
+                let x = x0 + bit_index;
+                if x >= DISPLAY_WIDTH && self.quirks.clip_sprites {
+                    break;
+                }
+                let (x, y) = (x % DISPLAY_WIDTH, y % DISPLAY_HEIGHT);
                 let pixel_bit = byte & (0x80 >> bit_index);
-                let vx_wrapped = ((self.registers[vx as usize] as usize + bit_index) % 64) as usize;
-                let vy_wrapped =
-                    ((self.registers[vy as usize] as usize + byte_index as usize) % 32) as usize;
-                let current_pixel = self.display.get_pixel(vx_wrapped, vy_wrapped);
+                let current_pixel = self.display.get_pixel(x, y);
                 let new_pixel = (pixel_bit > 0) as u8 ^ current_pixel;
                 if current_pixel == 1 && new_pixel == 0 {
                     self.registers[0xF] = 1;
                 }
-                self.display.update_pixel(vx_wrapped, vy_wrapped, new_pixel);
+                self.display.update_pixel(x, y, new_pixel);
             }
         }
     }
@@ -568,4 +828,187 @@ mod tests {
         // Check the result, expecting V3 to now be 0x05 + 0x12 = 0x17
         assert_eq!(emulator.registers[0x0F], 0x01, "vF should be equal to 0x01");
     }
+
+    #[test]
+    fn test_unknown_opcode_is_skipped() {
+        let mut emulator = Chip8::new();
+        // 8008 is no instruction, 6001 - LD V0, 1
+        emulator.load_rom_bytes(&[0x80, 0x08, 0x60, 0x01]);
+        emulator.emulate_cycle();
+        assert_eq!(emulator.pc, 0x202);
+        emulator.emulate_cycle();
+        assert_eq!(emulator.registers[0], 0x01);
+    }
+
+    #[test]
+    fn test_addresses_wrap_at_the_end_of_memory() {
+        let mut emulator = Chip8::new();
+        // AFFF - LD I, 0xFFF; F155 - LD [I], V1; D22F - DRW V2, V2, 15;
+        // F065 - LD V0, [I]; 1FFF - JP 0xFFF
+        emulator.load_rom_bytes(&[0xAF, 0xFF, 0xF1, 0x55, 0xD2, 0x2F, 0xF0, 0x65, 0x1F, 0xFF]);
+        emulator.registers[0] = 0x12;
+        emulator.registers[1] = 0x34;
+        for _ in 0..4 {
+            emulator.emulate_cycle();
+        }
+        assert_eq!(emulator.memory.get_byte(0xFFF), 0x12);
+        assert_eq!(emulator.memory.get_byte(0x000), 0x34);
+        assert_eq!(emulator.i, 0x1002);
+        assert_eq!(emulator.registers[0], 0x00);
+
+        // The instruction at 0xFFF takes its second byte from 0x000: 1234 - JP 0x234
+        emulator.emulate_cycle();
+        emulator.emulate_cycle();
+        assert_eq!(emulator.pc, 0x234);
+
+        // Keys come from the low nibble of the register
+        emulator.registers[2] = 0x1A;
+        emulator.key.press_key(0xA);
+        emulator.load_rom_bytes(&[0xE2, 0x9E]);
+        emulator.pc = 0x200;
+        emulator.emulate_cycle();
+        assert_eq!(emulator.pc, 0x204);
+    }
+
+    #[test]
+    fn test_save_and_load_state_roundtrip() {
+        let mut emulator = Chip8::new();
+        emulator.registers[4] = 0x42;
+        emulator.i = 0x345;
+        emulator.pc = 0x246;
+        emulator.stack.push(0x202);
+        emulator.memory.set_byte(0x300, 0xAB);
+        emulator.display.update_pixel(10, 20, 1);
+
+        let state = emulator.save_state();
+
+        let mut restored = Chip8::new();
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.registers[4], 0x42);
+        assert_eq!(restored.i, 0x345);
+        assert_eq!(restored.pc, 0x246);
+        assert_eq!(restored.stack, emulator.stack);
+        assert_eq!(restored.memory.get_byte(0x300), 0xAB);
+        assert_eq!(restored.display.get_pixel(10, 20), 1);
+        assert_eq!(restored.save_state(), state);
+    }
+
+    #[test]
+    fn test_call_stack_is_bounded() {
+        let mut emulator = Chip8::new();
+        // CALL 200, forever
+        emulator.load_rom_bytes(&[0x22, 0x00]);
+        for _ in 0..STACK_SIZE {
+            emulator.emulate_cycle();
+        }
+        assert_eq!(emulator.stack.len(), STACK_SIZE);
+
+        // A state with a deeper stack cannot have come from a machine
+        emulator.stack.push(0x200);
+        let state = emulator.save_state();
+        assert_eq!(
+            Chip8::new().load_state(&state),
+            Err(StateError::Corrupt("stack depth"))
+        );
+    }
+
+    #[test]
+    fn test_load_state_rejects_truncated_buffer() {
+        let mut emulator = Chip8::new();
+        emulator.registers[0] = 0x11;
+        let state = Chip8::new().save_state();
+
+        assert_eq!(
+            emulator.load_state(&state[..state.len() - 1]),
+            Err(StateError::Truncated)
+        );
+        assert_eq!(emulator.load_state(b"nope"), Err(StateError::BadMagic));
+        // A failed load must not clobber the running machine
+        assert_eq!(emulator.registers[0], 0x11);
+    }
+
+    #[test]
+    fn test_fx0a_waits_for_a_fresh_key_each_time() {
+        let mut emulator = Chip8::new();
+        // F10A - LD V1, K; F20A - LD V2, K
+        emulator.load_rom_bytes(&[0xF1, 0x0A, 0xF2, 0x0A]);
+
+        emulator.emulate_cycle();
+        emulator.key_mut().press_key(0x6);
+        emulator.emulate_cycle();
+        // Still waiting for the release, with the tone on
+        assert_eq!(emulator.pc, 0x200);
+        assert!(emulator.sound_active());
+
+        emulator.key_mut().release_key(0x6);
+        emulator.emulate_cycle();
+        assert_eq!(emulator.registers[1], 0x6);
+        assert_eq!(emulator.pc, 0x202);
+
+        // The second FX0A must not see the first key again
+        emulator.emulate_cycle();
+        emulator.emulate_cycle();
+        assert_eq!(emulator.pc, 0x202);
+    }
+
+    #[test]
+    fn test_fx0a_on_press_without_release_quirk() {
+        let mut emulator = Chip8::new();
+        emulator.set_platform(Platform::SuperChip);
+        emulator.load_rom_bytes(&[0xF1, 0x0A]);
+
+        emulator.emulate_cycle();
+        emulator.key_mut().press_key(0xE);
+        emulator.emulate_cycle();
+        assert_eq!(emulator.registers[1], 0xE);
+        assert_eq!(emulator.pc, 0x202);
+        assert!(!emulator.sound_active());
+    }
+
+    #[test]
+    fn test_display_wait_ends_the_frame_after_dxyn() {
+        // D001 - DRW V0, V0, 1 twice, then 1204 - JP 0x204
+        let program = [0xD0, 0x01, 0xD0, 0x01, 0x12, 0x04];
+
+        let mut emulator = Chip8::new();
+        emulator.load_rom_bytes(&program);
+        emulator.run_frame();
+        assert_eq!(emulator.pc, 0x202);
+        emulator.run_frame();
+        assert_eq!(emulator.pc, 0x204);
+
+        // Without the quirk both sprites go out in the first frame
+        emulator = Chip8::new();
+        emulator.set_platform(Platform::SuperChip);
+        emulator.load_rom_bytes(&program);
+        emulator.run_frame();
+        assert_eq!(emulator.pc, 0x204);
+    }
+
+    #[test]
+    fn test_vip_timing_spends_a_cycle_budget() {
+        // 7001 - ADD V0, 1; 1200 - JP 0x200, looped until the frame's budget runs out
+        let mut emulator = Chip8::new();
+        emulator.set_timing(Timing::CosmacVip);
+        emulator.load_rom_bytes(&[0x70, 0x01, 0x12, 0x00]);
+        emulator.run_frame();
+        let adds = emulator.registers[0] as u32;
+        let per_pair = vip_cycles(0x7001, &emulator.registers, false)
+            + vip_cycles(0x1200, &emulator.registers, false);
+        assert_eq!(adds, VIP_INTERPRETER_CYCLES_PER_FRAME.div_ceil(per_pair));
+
+        // 00E0 takes longer than a frame, so the overrun comes out of the next one
+        let mut emulator = Chip8::new();
+        emulator.set_timing(Timing::CosmacVip);
+        emulator.load_rom_bytes(&[0x00, 0xE0, 0x00, 0xE0, 0x12, 0x00]);
+        emulator.run_frame();
+        assert_eq!(emulator.pc, 0x202);
+        let overrun =
+            vip_cycles(0x00E0, &emulator.registers, false) - VIP_INTERPRETER_CYCLES_PER_FRAME;
+        assert_eq!(emulator.cycle_debt, overrun);
+        let state = emulator.save_state();
+        let mut restored = Chip8::new();
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.cycle_debt, emulator.cycle_debt);
+    }
 }
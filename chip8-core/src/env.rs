@@ -0,0 +1,501 @@
+//! A Gym-style environment for training agents on CHIP-8 games.
+//!
+//! Each step holds down one of a fixed set of key combinations for a few
+//! frames. The reward and the end of an episode come from an `Extractor`,
+//! usually a `MemoryExtractor` reading the score and lives out of memory.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::display::{Display, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use crate::emu::{Chip8, RomTooLarge};
+use crate::memory::{Memory, MEMORY_SIZE};
+use crate::platform::Platform;
+
+/// How a number is laid out in memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// Unsigned, most significant byte first
+    Binary,
+    /// One decimal digit per byte, most significant first, as FX33 stores them
+    Bcd,
+}
+
+/// A number the game keeps in memory, written as `<bin|bcd>:<address>[:<bytes>]`,
+/// for example `bcd:0x2F0:3` for a three digit score
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryValue {
+    pub address: usize,
+    pub len: usize,
+    pub encoding: Encoding,
+}
+
+impl MemoryValue {
+    pub fn read(&self, memory: &Memory) -> u64 {
+        let bytes = memory.read_slice_at(self.address, self.len);
+        match self.encoding {
+            Encoding::Binary => bytes.iter().fold(0, |n, &b| n << 8 | b as u64),
+            Encoding::Bcd => bytes.iter().fold(0, |n, &b| n * 10 + (b % 10) as u64),
+        }
+    }
+}
+
+impl FromStr for MemoryValue {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let encoding = match parts.next() {
+            Some("bin") => Encoding::Binary,
+            Some("bcd") => Encoding::Bcd,
+            _ => return Err(format!("'{}' should start with bin: or bcd:", s)),
+        };
+        let address = parts
+            .next()
+            .and_then(parse_number)
+            .ok_or_else(|| format!("bad address in '{}'", s))?;
+        let len = match parts.next() {
+            Some(len) => parse_number(len).ok_or_else(|| format!("bad length in '{}'", s))?,
+            None => 1,
+        };
+        if parts.next().is_some() {
+            return Err(format!("too many fields in '{}'", s));
+        }
+        // A u64 holds 8 bytes, or 19 decimal digits
+        let max_len = match encoding {
+            Encoding::Binary => 8,
+            Encoding::Bcd => 19,
+        };
+        if len == 0 || len > max_len {
+            return Err(format!("length of '{}' must be 1 to {}", s, max_len));
+        }
+        if address.saturating_add(len) > MEMORY_SIZE {
+            return Err(format!("'{}' runs past the end of memory", s));
+        }
+        Ok(MemoryValue {
+            address,
+            len,
+            encoding,
+        })
+    }
+}
+
+fn parse_number(s: &str) -> Option<usize> {
+    match s.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    const ALL: [(Comparison, &'static str); 6] = [
+        (Comparison::Eq, "=="),
+        (Comparison::Ne, "!="),
+        (Comparison::Lt, "<"),
+        (Comparison::Le, "<="),
+        (Comparison::Gt, ">"),
+        (Comparison::Ge, ">="),
+    ];
+
+    fn holds(self, a: u64, b: u64) -> bool {
+        match self {
+            Comparison::Eq => a == b,
+            Comparison::Ne => a != b,
+            Comparison::Lt => a < b,
+            Comparison::Le => a <= b,
+            Comparison::Gt => a > b,
+            Comparison::Ge => a >= b,
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (_, symbol) = Comparison::ALL.iter().find(|(c, _)| c == self).unwrap();
+        f.write_str(symbol)
+    }
+}
+
+/// A test on a value in memory, written as `<value> <op> <number>`, for
+/// example `bin:0x1E0 == 0` for a game over once the lives run out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Condition {
+    pub value: MemoryValue,
+    pub comparison: Comparison,
+    pub operand: u64,
+}
+
+impl Condition {
+    pub fn holds(&self, memory: &Memory) -> bool {
+        self.comparison.holds(self.value.read(memory), self.operand)
+    }
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split_whitespace().collect();
+        let [value, op, operand] = parts[..] else {
+            return Err(format!("'{}' should be <value> <op> <number>", s));
+        };
+        let comparison = Comparison::ALL
+            .iter()
+            .find(|(_, symbol)| *symbol == op)
+            .map(|&(c, _)| c)
+            .ok_or_else(|| format!("unknown comparison '{}'", op))?;
+        Ok(Condition {
+            value: value.parse()?,
+            comparison,
+            operand: parse_number(operand).ok_or_else(|| format!("bad number '{}'", operand))?
+                as u64,
+        })
+    }
+}
+
+/// Reads a game's progress, to reward an agent and end its episodes.
+/// Implement it for games that need more than `MemoryExtractor` offers.
+pub trait Extractor: Send + Sync {
+    /// The score now; a step's reward is how much it changed
+    fn score(&self, _chip8: &Chip8) -> f64 {
+        0.0
+    }
+
+    /// Whether the episode is over
+    fn done(&self, _chip8: &Chip8) -> bool {
+        false
+    }
+}
+
+/// Score and game over read straight from memory
+#[derive(Debug, Clone, Default)]
+pub struct MemoryExtractor {
+    pub score: Option<MemoryValue>,
+    /// The episode ends once any of these holds
+    pub done: Vec<Condition>,
+}
+
+impl Extractor for MemoryExtractor {
+    fn score(&self, chip8: &Chip8) -> f64 {
+        self.score.map_or(0.0, |v| v.read(chip8.memory()) as f64)
+    }
+
+    fn done(&self, chip8: &Chip8) -> bool {
+        self.done.iter().any(|c| c.holds(chip8.memory()))
+    }
+}
+
+/// No action, then each key on its own, as bit masks of keys held
+pub fn default_actions() -> Vec<u16> {
+    std::iter::once(0).chain((0..16).map(|k| 1 << k)).collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct EnvConfig {
+    pub platform: Platform,
+    /// Frames each step holds its keys for
+    pub frame_skip: usize,
+    /// Episodes longer than this many frames are cut short
+    pub max_frames: Option<u64>,
+    /// The keys held by each action, bit n standing for key n
+    pub actions: Vec<u16>,
+}
+
+impl Default for EnvConfig {
+    fn default() -> Self {
+        EnvConfig {
+            platform: Platform::default(),
+            frame_skip: 4,
+            max_frames: None,
+            actions: default_actions(),
+        }
+    }
+}
+
+/// What came of a step
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Step {
+    pub reward: f64,
+    /// The game is over
+    pub terminated: bool,
+    /// The episode ran into `max_frames`
+    pub truncated: bool,
+}
+
+/// Why an `Env` could not be made or stepped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnvError {
+    RomTooLarge(RomTooLarge),
+    /// `frame_skip` was 0, so a step would never run the game
+    NoFrameSkip,
+    NoActions,
+    /// A step asked for an action past the end of `actions`
+    InvalidAction {
+        action: usize,
+        count: usize,
+    },
+}
+
+impl fmt::Display for EnvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnvError::RomTooLarge(e) => write!(f, "{}", e),
+            EnvError::NoFrameSkip => write!(f, "frame_skip must be at least 1"),
+            EnvError::NoActions => write!(f, "there must be at least one action"),
+            EnvError::InvalidAction { action, count } => {
+                write!(f, "action {} out of range 0 to {}", action, count - 1)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EnvError {}
+
+impl From<RomTooLarge> for EnvError {
+    fn from(e: RomTooLarge) -> Self {
+        EnvError::RomTooLarge(e)
+    }
+}
+
+/// A machine state to go back to with `Env::restore_state`
+#[derive(Clone)]
+pub struct EnvState(Chip8);
+
+pub struct Env {
+    rom: Vec<u8>,
+    config: EnvConfig,
+    extractor: Box<dyn Extractor>,
+    chip8: Chip8,
+}
+
+impl Env {
+    pub fn new(
+        rom: &[u8],
+        config: EnvConfig,
+        extractor: Box<dyn Extractor>,
+    ) -> Result<Self, EnvError> {
+        if config.frame_skip == 0 {
+            return Err(EnvError::NoFrameSkip);
+        }
+        if config.actions.is_empty() {
+            return Err(EnvError::NoActions);
+        }
+        // Load once up front so `reset` can't fail
+        let mut chip8 = Chip8::new();
+        chip8.try_load_rom_bytes(rom)?;
+        let mut env = Env {
+            rom: rom.to_vec(),
+            config,
+            extractor,
+            chip8,
+        };
+        env.reset(0);
+        Ok(env)
+    }
+
+    pub fn config(&self) -> &EnvConfig {
+        &self.config
+    }
+
+    pub fn action_count(&self) -> usize {
+        self.config.actions.len()
+    }
+
+    /// Start a new episode: power on afresh with `seed` driving CXNN
+    pub fn reset(&mut self, seed: u64) {
+        let mut chip8 = Chip8::new();
+        chip8.set_platform(self.config.platform);
+        chip8.set_seed(seed);
+        chip8.load_rom_bytes(&self.rom);
+        self.chip8 = chip8;
+    }
+
+    /// Hold the keys of `action` for up to `frame_skip` frames, stopping
+    /// early if the game ends
+    pub fn step(&mut self, action: usize) -> Result<Step, EnvError> {
+        let Some(&keys) = self.config.actions.get(action) else {
+            return Err(EnvError::InvalidAction {
+                action,
+                count: self.action_count(),
+            });
+        };
+        let keyboard = self.chip8.key_mut();
+        for key in 0..16 {
+            keyboard.set_key(key, keys & 1 << key != 0);
+        }
+
+        let before = self.extractor.score(&self.chip8);
+        let mut terminated = false;
+        for _ in 0..self.config.frame_skip {
+            self.chip8.run_frame();
+            terminated = self.extractor.done(&self.chip8);
+            if terminated {
+                break;
+            }
+        }
+        Ok(Step {
+            reward: self.extractor.score(&self.chip8) - before,
+            terminated,
+            truncated: self
+                .config
+                .max_frames
+                .is_some_and(|max| self.chip8.frame() >= max),
+        })
+    }
+
+    /// The screen, row by row, 1 for a lit pixel
+    pub fn observation(&self) -> Vec<u8> {
+        let display = self.display();
+        (0..DISPLAY_WIDTH * DISPLAY_HEIGHT)
+            .map(|n| display.get_pixel(n % DISPLAY_WIDTH, n / DISPLAY_WIDTH))
+            .collect()
+    }
+
+    pub fn display(&self) -> &Display {
+        self.chip8.display()
+    }
+
+    pub fn chip8(&self) -> &Chip8 {
+        &self.chip8
+    }
+
+    /// The whole machine, for search algorithms that explore from a state
+    pub fn clone_state(&self) -> EnvState {
+        EnvState(self.chip8.clone())
+    }
+
+    pub fn restore_state(&mut self, state: &EnvState) {
+        self.chip8 = state.0.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Counts up in V1 while key 0 is held, keeping the count as BCD at 0x300
+    const ROM: [u8; 10] = [
+        0xA3, 0x00, // LD I, 0x300
+        0xE3, 0xA1, // SKNP V3
+        0x71, 0x01, // ADD V1, 1
+        0xF1, 0x33, // LD B, V1
+        0x12, 0x02, // JP 0x202
+    ];
+
+    fn env() -> Env {
+        let extractor = MemoryExtractor {
+            score: Some("bcd:0x300:3".parse().unwrap()),
+            done: vec!["bcd:0x300:3 >= 10".parse().unwrap()],
+        };
+        Env::new(&ROM, EnvConfig::default(), Box::new(extractor)).unwrap()
+    }
+
+    #[test]
+    fn parses_specs() {
+        let value: MemoryValue = "bin:0x2F0:2".parse().unwrap();
+        assert_eq!(
+            value,
+            MemoryValue {
+                address: 0x2F0,
+                len: 2,
+                encoding: Encoding::Binary
+            }
+        );
+        let mut memory = Memory::new();
+        memory.write_slice_at(0x2F0, &[0x01, 0x02, 0x07]);
+        assert_eq!(value.read(&memory), 0x0102);
+        assert_eq!(
+            "bcd:752:3".parse::<MemoryValue>().unwrap().read(&memory),
+            127
+        );
+
+        let condition: Condition = "bcd:0x2F0:3 >= 127".parse().unwrap();
+        assert!(condition.holds(&memory));
+        assert_eq!(condition.comparison.to_string(), ">=");
+
+        for bad in ["u8:0x10", "bin:zz", "bin:0xFFF:2", "bin:0:9", "bin:0:1:2"] {
+            assert!(bad.parse::<MemoryValue>().is_err(), "{}", bad);
+        }
+        for bad in ["bin:0 == ", "bin:0 =< 1", "bin:0 == x"] {
+            assert!(bad.parse::<Condition>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn rewards_and_terminates() {
+        let mut env = env();
+        assert_eq!(env.action_count(), 17);
+        let step = env.step(0).unwrap();
+        assert_eq!((step.reward, step.terminated), (0.0, false));
+
+        // Action 1 holds key 0; the count goes up twice a frame
+        env.reset(0);
+        assert_eq!(env.step(1).unwrap().reward, 8.0);
+        let step = env.step(1).unwrap();
+        assert_eq!((step.reward, step.terminated), (2.0, true));
+        assert!(!step.truncated);
+        // The episode ended part way through the step
+        assert_eq!(env.chip8().frame(), 5);
+    }
+
+    #[test]
+    fn truncates_and_restores() {
+        let mut env = env();
+        env.config.max_frames = Some(8);
+        env.step(0).unwrap();
+        let state = env.clone_state();
+        let observation = env.observation();
+        let step = env.step(1).unwrap();
+        assert!(step.truncated);
+        assert!(!step.terminated);
+
+        env.restore_state(&state);
+        assert_eq!(env.chip8().frame(), 4);
+        assert_eq!(env.observation(), observation);
+        assert_eq!(env.step(1).unwrap(), step);
+
+        env.reset(3);
+        assert_eq!(env.chip8().frame(), 0);
+        assert_eq!(env.chip8().seed(), 3);
+    }
+
+    #[test]
+    fn rejects_bad_configs_and_actions() {
+        let new = |rom: &[u8], config| Env::new(rom, config, Box::new(MemoryExtractor::default()));
+        let rom = vec![0; 4000];
+        assert_eq!(
+            new(&rom, EnvConfig::default()).err(),
+            Some(EnvError::RomTooLarge(RomTooLarge { len: 4000 }))
+        );
+        let config = EnvConfig {
+            frame_skip: 0,
+            ..EnvConfig::default()
+        };
+        assert_eq!(new(&ROM, config).err(), Some(EnvError::NoFrameSkip));
+        let config = EnvConfig {
+            actions: Vec::new(),
+            ..EnvConfig::default()
+        };
+        assert_eq!(new(&ROM, config).err(), Some(EnvError::NoActions));
+
+        let mut env = env();
+        assert_eq!(
+            env.step(17),
+            Err(EnvError::InvalidAction {
+                action: 17,
+                count: 17
+            })
+        );
+        assert_eq!(env.chip8().frame(), 0);
+    }
+}
@@ -0,0 +1,97 @@
+//! Named gamepad presets, shared by the frontends. A preset binds controls
+//! named by their role to CHIP-8 keys; each frontend decides which of its
+//! buttons and axes play those roles.
+
+/// A control a preset can bind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PadControl {
+    Up,
+    Down,
+    Left,
+    Right,
+    /// The main face button
+    Action,
+    /// The face button next to it
+    Secondary,
+}
+
+/// A named set of gamepad bindings
+#[derive(Debug)]
+pub struct Preset {
+    pub name: &'static str,
+    pub bindings: &'static [(PadControl, usize)],
+}
+
+/// Directions on 2/4/6/8 like the keypad's arrows, 5 to act
+pub const DEFAULT_PRESET: Preset = Preset {
+    name: "dpad-2468",
+    bindings: &[
+        (PadControl::Up, 0x2),
+        (PadControl::Down, 0x8),
+        (PadControl::Left, 0x4),
+        (PadControl::Right, 0x6),
+        (PadControl::Action, 0x5),
+        (PadControl::Secondary, 0x0),
+    ],
+};
+
+pub const PRESETS: [Preset; 4] = [
+    DEFAULT_PRESET,
+    // Octo's WASD convention, E to act
+    Preset {
+        name: "wasd-5789",
+        bindings: &[
+            (PadControl::Up, 0x5),
+            (PadControl::Down, 0x8),
+            (PadControl::Left, 0x7),
+            (PadControl::Right, 0x9),
+            (PadControl::Action, 0x6),
+            (PadControl::Secondary, 0x4),
+        ],
+    },
+    // Left paddle in Pong
+    Preset {
+        name: "paddle-14",
+        bindings: &[(PadControl::Up, 0x1), (PadControl::Down, 0x4)],
+    },
+    // Move on 4/6 and fire on 5, as in Brix and Space Invaders
+    Preset {
+        name: "shooter-456",
+        bindings: &[
+            (PadControl::Left, 0x4),
+            (PadControl::Right, 0x6),
+            (PadControl::Action, 0x5),
+        ],
+    },
+];
+
+pub fn preset(name: &str) -> Option<&'static Preset> {
+    PRESETS.iter().find(|p| p.name == name)
+}
+
+/// The preset for well known games, matched by ROM file name
+pub fn rom_preset(rom_name: &str) -> Option<&'static Preset> {
+    let stem = rom_name
+        .rsplit_once('.')
+        .map_or(rom_name, |(stem, _)| stem)
+        .to_ascii_lowercase();
+    let name = match stem.as_str() {
+        "pong" | "pong2" | "pong (1 player)" => "paddle-14",
+        "brix" | "breakout" | "invaders" | "space invaders" => "shooter-456",
+        _ => return None,
+    };
+    preset(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_roms_get_their_preset() {
+        assert_eq!(rom_preset("PONG.ch8").unwrap().name, "paddle-14");
+        assert_eq!(rom_preset("brix.ch8").unwrap().name, "shooter-456");
+        assert!(rom_preset("ghosts.ch8").is_none());
+        assert_eq!(preset("dpad-2468").unwrap().name, DEFAULT_PRESET.name);
+    }
+}
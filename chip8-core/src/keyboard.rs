@@ -1,50 +1,64 @@
-extern crate piston_window;
-use piston_window::*;
+use crate::state::{StateError, StateReader, StateWriter};
 
+/// Progress of an FX0A wait for a key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyWait {
+    Idle,
+    /// Waiting for a key to go down; `on_release` holds on until it comes back up
+    Press {
+        on_release: bool,
+    },
+    /// Key went down, waiting for its release
+    Release(usize),
+    /// The wait is over; FX0A collects the key on its next execution
+    Done(usize),
+}
+
+#[derive(Clone)]
 pub struct Keyboard {
     keys: [bool; 16],
-    waiting_for_key_release: bool,
-    key_register: Option<usize>
+    wait: KeyWait,
 }
 
-// Mapping of Chip8's native keys to host keys
-fn map_key(key: Key) -> Option<usize> {
-    match key {
-        Key::D1 => Some(0x01),
-        Key::D2 => Some(0x02),
-        Key::D3 => Some(0x03),
-        Key::D4 => Some(0x0C),
-        Key::Q => Some(0x04),
-        Key::W => Some(0x05),
-        Key::E => Some(0x06),
-        Key::R => Some(0x0D),
-        Key::A => Some(0x07),
-        Key::S => Some(0x08),
-        Key::D => Some(0x09),
-        Key::F => Some(0x0E),
-        Key::Z => Some(0x0A),
-        Key::X => Some(0x00),
-        Key::C => Some(0x0B),
-        Key::V => Some(0x0F),
-        _ => None, // Map other keys to 0 for now...
+impl Default for Keyboard {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl Keyboard {
     pub fn new() -> Self {
-        Keyboard { keys: [false; 16], waiting_for_key_release: false, key_register: None }
+        Keyboard {
+            keys: [false; 16],
+            wait: KeyWait::Idle,
+        }
     }
 
-    pub fn get_keys(&mut self) -> [bool; 16] {
+    pub fn get_keys(&self) -> [bool; 16] {
         self.keys
     }
 
-    pub fn get_key_register(&self) -> Option<usize> {
-        self.key_register
+    pub fn key_wait(&self) -> KeyWait {
+        self.wait
     }
 
-    pub fn set_waiting_for_key_release(&mut self) {
-        self.waiting_for_key_release = true;
+    /// Start waiting for a key unless a wait is already under way. Keys held
+    /// at this point do not count; only a fresh press ends the wait.
+    pub fn begin_key_wait(&mut self, on_release: bool) {
+        if self.wait == KeyWait::Idle {
+            self.wait = KeyWait::Press { on_release };
+        }
+    }
+
+    /// Collect the key that ended the wait, making way for the next one
+    pub fn take_waited_key(&mut self) -> Option<usize> {
+        match self.wait {
+            KeyWait::Done(key) => {
+                self.wait = KeyWait::Idle;
+                Some(key)
+            }
+            _ => None,
+        }
     }
 
     pub fn key_is_pressed(&self, key: u8) -> bool {
@@ -52,22 +66,126 @@ impl Keyboard {
         self.keys[key as usize]
     }
 
-    pub fn handle_key_press(&mut self, key: Key) {
-        let mapped_key = map_key(key);
-        if let Some(key) = mapped_key {
-            self.keys[key as usize] = true;
-        }
-        
+    /// Overwrite which keys are held without touching an FX0A wait, for
+    /// carrying the live input over a state restore
+    pub(crate) fn set_held_keys(&mut self, keys: [bool; 16]) {
+        self.keys = keys;
     }
 
-    pub fn handle_key_release(&mut self, key: Key) {
-        let mapped_key = map_key(key);
-        if let Some(key) = mapped_key {
-            self.keys[key] = false;
-            if self.waiting_for_key_release {
-                self.key_register = Some(key);
-                self.waiting_for_key_release = false;
+    /// Press a CHIP-8 key (0x0 to 0xF)
+    pub fn press_key(&mut self, key: usize) {
+        if let KeyWait::Press { on_release } = self.wait {
+            if !self.keys[key] {
+                self.wait = if on_release {
+                    KeyWait::Release(key)
+                } else {
+                    KeyWait::Done(key)
+                };
             }
         }
+        self.keys[key] = true;
+    }
+
+    /// Release a CHIP-8 key (0x0 to 0xF)
+    pub fn release_key(&mut self, key: usize) {
+        self.keys[key] = false;
+        if self.wait == KeyWait::Release(key) {
+            self.wait = KeyWait::Done(key);
+        }
+    }
+
+    /// Press or release a CHIP-8 key (0x0 to 0xF) to match the host's
+    /// input. Releasing a key that is not held does nothing, where
+    /// `release_key` would let it end an FX0A wait.
+    pub fn set_key(&mut self, key: usize, pressed: bool) {
+        if pressed {
+            self.press_key(key);
+        } else if self.keys[key] {
+            self.release_key(key);
+        }
+    }
+
+    pub(crate) fn save_state(&self, w: &mut StateWriter) {
+        for key in self.keys {
+            w.bool(key);
+        }
+        let (tag, key) = match self.wait {
+            KeyWait::Idle => (0, 0),
+            KeyWait::Press { on_release } => (1, on_release as usize),
+            KeyWait::Release(key) => (2, key),
+            KeyWait::Done(key) => (3, key),
+        };
+        w.u8(tag);
+        w.u8(key as u8);
+    }
+
+    pub(crate) fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        for key in self.keys.iter_mut() {
+            *key = r.bool()?;
+        }
+        let tag = r.u8()?;
+        let key = r.u8()? as usize;
+        if key > 0x0F {
+            return Err(StateError::Corrupt("key wait"));
+        }
+        self.wait = match tag {
+            0 => KeyWait::Idle,
+            1 => KeyWait::Press {
+                on_release: key != 0,
+            },
+            2 => KeyWait::Release(key),
+            3 => KeyWait::Done(key),
+            _ => return Err(StateError::Corrupt("key wait")),
+        };
+        Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wait_on_release_needs_a_fresh_press_and_its_release() {
+        let mut keyboard = Keyboard::new();
+        keyboard.press_key(0x3);
+        keyboard.begin_key_wait(true);
+
+        // Releasing a key held before the wait does nothing
+        keyboard.release_key(0x3);
+        assert_eq!(keyboard.take_waited_key(), None);
+
+        keyboard.press_key(0x7);
+        assert_eq!(keyboard.key_wait(), KeyWait::Release(0x7));
+        keyboard.press_key(0x8);
+        keyboard.release_key(0x8);
+        assert_eq!(keyboard.take_waited_key(), None);
+        keyboard.release_key(0x7);
+        assert_eq!(keyboard.take_waited_key(), Some(0x7));
+
+        // The key is handed out once
+        assert_eq!(keyboard.take_waited_key(), None);
+        assert_eq!(keyboard.key_wait(), KeyWait::Idle);
+    }
+
+    #[test]
+    fn set_key_ignores_releases_of_keys_not_held() {
+        let mut keyboard = Keyboard::new();
+        keyboard.press_key(0x2);
+        keyboard.begin_key_wait(true);
+        keyboard.set_key(0x2, false);
+        keyboard.set_key(0x4, false);
+        assert_eq!(keyboard.key_wait(), KeyWait::Press { on_release: true });
+        keyboard.set_key(0x4, true);
+        keyboard.set_key(0x4, false);
+        assert_eq!(keyboard.take_waited_key(), Some(0x4));
+    }
+
+    #[test]
+    fn wait_on_press_ends_immediately() {
+        let mut keyboard = Keyboard::new();
+        keyboard.begin_key_wait(false);
+        keyboard.press_key(0xB);
+        assert_eq!(keyboard.take_waited_key(), Some(0xB));
+    }
+}
@@ -1,4 +1,21 @@
+pub mod batch;
+pub mod beeper;
+pub mod capture;
+pub mod cdp1802;
+pub mod decode;
+pub mod disasm;
 pub mod display;
 pub mod emu;
+pub mod env;
+pub mod gamepad;
 pub mod keyboard;
+pub mod machine;
 pub mod memory;
+pub mod palette;
+pub mod platform;
+pub mod replay;
+pub mod rewind;
+pub mod rng;
+pub mod state;
+pub mod timing;
+pub mod vip;
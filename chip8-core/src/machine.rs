@@ -0,0 +1,70 @@
+use crate::display::Display;
+use crate::emu::Chip8;
+use crate::keyboard::Keyboard;
+use crate::vip::Vip;
+
+/// What a frontend needs from an emulated machine, so the high-level `Chip8`
+/// and the VIP hardware emulation can be driven by the same code
+pub trait Machine {
+    /// Run one 60 Hz frame
+    fn run_frame(&mut self);
+    /// Number of frames run since power on
+    fn frame(&self) -> u64;
+    fn display(&self) -> &Display;
+    fn display_mut(&mut self) -> &mut Display;
+    fn key_mut(&mut self) -> &mut Keyboard;
+    /// Whether the beeper is sounding
+    fn sound_active(&self) -> bool;
+}
+
+impl Machine for Chip8 {
+    fn run_frame(&mut self) {
+        Chip8::run_frame(self)
+    }
+
+    fn frame(&self) -> u64 {
+        Chip8::frame(self)
+    }
+
+    fn display(&self) -> &Display {
+        Chip8::display(self)
+    }
+
+    fn display_mut(&mut self) -> &mut Display {
+        Chip8::display_mut(self)
+    }
+
+    fn key_mut(&mut self) -> &mut Keyboard {
+        Chip8::key_mut(self)
+    }
+
+    fn sound_active(&self) -> bool {
+        Chip8::sound_active(self)
+    }
+}
+
+impl Machine for Vip {
+    fn run_frame(&mut self) {
+        Vip::run_frame(self)
+    }
+
+    fn frame(&self) -> u64 {
+        Vip::frame(self)
+    }
+
+    fn display(&self) -> &Display {
+        Vip::display(self)
+    }
+
+    fn display_mut(&mut self) -> &mut Display {
+        Vip::display_mut(self)
+    }
+
+    fn key_mut(&mut self) -> &mut Keyboard {
+        Vip::key_mut(self)
+    }
+
+    fn sound_active(&self) -> bool {
+        Vip::sound_active(self)
+    }
+}
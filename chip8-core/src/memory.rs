@@ -1,34 +1,97 @@
+use crate::decode::Opcode;
+use crate::state::{StateError, StateReader, StateWriter};
+
 pub const MEMORY_SIZE: usize = 4096;
 
+#[derive(Clone)]
 pub struct Memory {
     bytes: [u8; MEMORY_SIZE],
+    // Instructions decoded so far, by address. Every write clears the
+    // entries of the instructions it lands in.
+    decoded: Box<[Option<Opcode>; MEMORY_SIZE]>,
+    cache: bool,
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Memory {
     pub fn new() -> Self {
         Memory {
             bytes: [0; MEMORY_SIZE],
+            decoded: Box::new([None; MEMORY_SIZE]),
+            cache: true,
         }
     }
 
-    pub fn get_byte(&mut self, pos: usize) -> u8 {
-        self.bytes[pos]
+    /// The byte at `pos`, wrapping around past the end of memory
+    pub fn get_byte(&self, pos: usize) -> u8 {
+        self.bytes[pos % MEMORY_SIZE]
     }
 
+    /// Write the byte at `pos`, wrapping around past the end of memory
     pub fn set_byte(&mut self, pos: usize, value: u8) {
+        let pos = pos % MEMORY_SIZE;
         self.bytes[pos] = value;
+        self.invalidate(pos, 1);
     }
 
     pub fn write_slice_at(&mut self, at: usize, data: &[u8]) {
         // Ensure the operation is safe
         assert!(at + data.len() <= MEMORY_SIZE);
-        self.bytes[at..at + data.len()].copy_from_slice(&data);
+        self.bytes[at..at + data.len()].copy_from_slice(data);
+        self.invalidate(at, data.len());
     }
 
-    pub fn read_slice_at(&mut self, at: usize, n: usize) -> &[u8] {
+    /// The `n` bytes from `at` on. Panics if they run past the end of memory.
+    pub fn read_slice_at(&self, at: usize, n: usize) -> &[u8] {
         // Ensure the operation is safe
         assert!(at + n <= MEMORY_SIZE);
-        &self.bytes[at..=at + n]
+        &self.bytes[at..at + n]
+    }
+
+    /// The instruction at `pos`, decoded the first time it is asked for. An
+    /// instruction at the last byte takes its second byte from address 0.
+    pub fn opcode(&mut self, pos: usize) -> Opcode {
+        let pos = pos % MEMORY_SIZE;
+        if let Some(opcode) = self.decoded[pos] {
+            return opcode;
+        }
+        let next = (pos + 1) % MEMORY_SIZE;
+        let opcode = Opcode::decode(u16::from_be_bytes([self.bytes[pos], self.bytes[next]]));
+        if self.cache {
+            self.decoded[pos] = Some(opcode);
+        }
+        opcode
+    }
+
+    /// Whether `opcode` keeps what it decodes. Turning this off decodes
+    /// every fetch afresh, which is only useful to measure what the cache saves.
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.cache = enabled;
+        self.decoded.fill(None);
+    }
+
+    fn invalidate(&mut self, at: usize, n: usize) {
+        // An instruction starting the byte before also reads the first byte,
+        // and the one at the last byte reads the byte at 0
+        self.decoded[at.saturating_sub(1)..at + n].fill(None);
+        if at == 0 {
+            self.decoded[MEMORY_SIZE - 1] = None;
+        }
+    }
+
+    pub(crate) fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.bytes);
+    }
+
+    pub(crate) fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.bytes.copy_from_slice(r.bytes(MEMORY_SIZE)?);
+        self.decoded.fill(None);
+        Ok(())
     }
 }
 
@@ -38,7 +101,7 @@ mod tests {
 
     #[test]
     fn read_slice_test() {
-        let mut mem = Memory::new();
+        let mem = Memory::new();
 
         // Get one byte after another
         for i in 0..12 {
@@ -46,5 +109,32 @@ mod tests {
         }
         // Get bytes all at once
         assert_eq!(mem.read_slice_at(1527, 1), [0; 1]);
+        assert_eq!(mem.read_slice_at(1527, 12), [0; 12]);
+        // Right up to the end of memory
+        assert_eq!(mem.read_slice_at(0, MEMORY_SIZE).len(), MEMORY_SIZE);
+    }
+
+    #[test]
+    fn writes_clear_decoded_instructions() {
+        let mut mem = Memory::new();
+        mem.write_slice_at(0x200, &[0x12, 0x00, 0x60, 0x01]);
+        assert_eq!(mem.opcode(0x200), Opcode::Op1NNN(0x200));
+        assert_eq!(mem.opcode(0x202), Opcode::Op6XNN(0, 1));
+
+        // Either byte of an instruction changes it
+        mem.set_byte(0x201, 0x04);
+        assert_eq!(mem.opcode(0x200), Opcode::Op1NNN(0x204));
+        mem.write_slice_at(0x202, &[0x70]);
+        assert_eq!(mem.opcode(0x202), Opcode::Op7XNN(0, 1));
+        assert_eq!(mem.opcode(0x200), Opcode::Op1NNN(0x204));
+    }
+
+    #[test]
+    fn instruction_at_the_last_byte_wraps() {
+        let mut mem = Memory::new();
+        mem.set_byte(MEMORY_SIZE - 1, 0x61);
+        assert_eq!(mem.opcode(MEMORY_SIZE - 1), Opcode::Op6XNN(1, 0));
+        mem.set_byte(0, 0x23);
+        assert_eq!(mem.opcode(MEMORY_SIZE - 1), Opcode::Op6XNN(1, 0x23));
     }
 }
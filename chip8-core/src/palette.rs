@@ -0,0 +1,137 @@
+use std::fmt;
+
+use crate::display::{Rgb, DEFAULT_PALETTE};
+
+/// Most colours a palette can have: one per value of a 4-plane pixel
+pub const MAX_COLORS: usize = 16;
+
+// Built-in palettes, background first
+const BUILTIN: [(&str, &[Rgb]); 6] = [
+    ("default", &DEFAULT_PALETTE),
+    ("green-phosphor", &[[0x0A, 0x1A, 0x0F], [0x33, 0xFF, 0x66]]),
+    ("amber", &[[0x1A, 0x0F, 0x00], [0xFF, 0xB0, 0x00]]),
+    // Shades of an old handheld's green LCD, darkest for lit pixels
+    (
+        "lcd",
+        &[
+            [0x9B, 0xBC, 0x0F],
+            [0x0F, 0x38, 0x0F],
+            [0x8B, 0xAC, 0x0F],
+            [0x30, 0x62, 0x30],
+        ],
+    ),
+    ("high-contrast", &[[0x00, 0x00, 0x00], [0xFF, 0xFF, 0xFF]]),
+    // Octo's background, fill, second plane and blend colours
+    (
+        "octo",
+        &[
+            [0x99, 0x66, 0x00],
+            [0xFF, 0xCC, 0x00],
+            [0xFF, 0x66, 0x00],
+            [0x66, 0x22, 0x00],
+        ],
+    ),
+];
+
+/// A named set of colours, indexed by pixel value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette {
+    name: String,
+    colors: Vec<Rgb>,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette {
+            name: BUILTIN[0].0.to_string(),
+            colors: DEFAULT_PALETTE.to_vec(),
+        }
+    }
+}
+
+impl Palette {
+    /// A palette of 2 to 16 colours
+    pub fn new(name: &str, colors: Vec<Rgb>) -> Result<Self, String> {
+        if !(2..=MAX_COLORS).contains(&colors.len()) {
+            return Err(format!(
+                "palette '{}' needs 2 to {} colours, not {}",
+                name,
+                MAX_COLORS,
+                colors.len()
+            ));
+        }
+        Ok(Palette {
+            name: name.to_string(),
+            colors,
+        })
+    }
+
+    /// One of the palettes that come with the emulator
+    pub fn builtin(name: &str) -> Option<Self> {
+        Self::builtins().into_iter().find(|p| p.name == name)
+    }
+
+    pub fn builtins() -> Vec<Self> {
+        BUILTIN
+            .iter()
+            .map(|(name, colors)| Palette {
+                name: name.to_string(),
+                colors: colors.to_vec(),
+            })
+            .collect()
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn colors(&self) -> &[Rgb] {
+        &self.colors
+    }
+}
+
+impl fmt::Display for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+/// Parse a colour written as `#RRGGBB`
+pub fn parse_color(s: &str) -> Result<Rgb, String> {
+    let bad = || format!("bad colour '{}', expected #RRGGBB", s);
+    let hex = s
+        .strip_prefix('#')
+        .filter(|hex| hex.len() == 6 && hex.is_ascii())
+        .ok_or_else(bad)?;
+    let channel = |n: usize| u8::from_str_radix(&hex[n * 2..n * 2 + 2], 16).map_err(|_| bad());
+    Ok([channel(0)?, channel(1)?, channel(2)?])
+}
+
+pub fn format_color([r, g, b]: Rgb) -> String {
+    format!("#{:02X}{:02X}{:02X}", r, g, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtins_are_valid() {
+        for palette in Palette::builtins() {
+            assert!(Palette::new(palette.name(), palette.colors().to_vec()).is_ok());
+        }
+        assert_eq!(Palette::builtin("default"), Some(Palette::default()));
+        assert!(Palette::builtin("nope").is_none());
+        assert!(Palette::new("one", vec![[0, 0, 0]]).is_err());
+        assert!(Palette::new("many", vec![[0, 0, 0]; 17]).is_err());
+    }
+
+    #[test]
+    fn colours_roundtrip() {
+        assert_eq!(parse_color("#FF8000"), Ok([0xFF, 0x80, 0x00]));
+        assert_eq!(format_color([0x0A, 0xbc, 0x00]), "#0ABC00");
+        assert!(parse_color("FF8000").is_err());
+        assert!(parse_color("#FF80").is_err());
+        assert!(parse_color("#GG8000").is_err());
+    }
+}
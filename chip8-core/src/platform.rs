@@ -0,0 +1,83 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// The CHIP-8 implementation whose behaviour the emulator follows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Platform {
+    /// The original interpreter on the RCA COSMAC VIP
+    #[default]
+    CosmacVip,
+    /// SUPER-CHIP 1.1 on the HP48 calculators
+    SuperChip,
+}
+
+/// Behavioural differences between CHIP-8 implementations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY6/8XYE shift vY into vX instead of shifting vX in place
+    pub shift_uses_vy: bool,
+    /// FX55/FX65 leave I pointing past the last register stored or loaded
+    pub load_store_increments_i: bool,
+    /// 8XY1/8XY2/8XY3 reset vF to zero
+    pub vf_reset: bool,
+    /// Sprites are cut off at the screen edges instead of wrapping around
+    pub clip_sprites: bool,
+    /// BNNN behaves as BXNN, jumping to XNN + vX
+    pub jump_uses_vx: bool,
+    /// FX0A returns once the pressed key is released, beeping while it is held,
+    /// instead of as soon as a key goes down
+    pub key_wait_on_release: bool,
+    /// DXYN waits for the vertical blank, so at most one sprite is drawn per frame
+    pub display_wait: bool,
+}
+
+impl Platform {
+    pub const ALL: [Platform; 2] = [Platform::CosmacVip, Platform::SuperChip];
+
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Platform::CosmacVip => Quirks {
+                shift_uses_vy: true,
+                load_store_increments_i: true,
+                vf_reset: true,
+                clip_sprites: true,
+                jump_uses_vx: false,
+                key_wait_on_release: true,
+                display_wait: true,
+            },
+            Platform::SuperChip => Quirks {
+                shift_uses_vy: false,
+                load_store_increments_i: false,
+                vf_reset: false,
+                clip_sprites: true,
+                jump_uses_vx: true,
+                key_wait_on_release: false,
+                display_wait: false,
+            },
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Platform::CosmacVip => "vip",
+            Platform::SuperChip => "schip",
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Platform::ALL
+            .into_iter()
+            .find(|p| p.name() == s)
+            .ok_or_else(|| format!("unknown platform '{}'", s))
+    }
+}
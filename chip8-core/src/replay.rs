@@ -0,0 +1,398 @@
+use std::fmt;
+use std::fs;
+use std::str::FromStr;
+
+use crate::emu::{Chip8, RomTooLarge};
+use crate::platform::Platform;
+use crate::timing::Timing;
+
+const HEADER: &str = "chip8-input-recording 1";
+
+/// A single key transition, applied before frame `frame` runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputEvent {
+    pub frame: u64,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+/// Everything needed to reproduce a session from power on: the ROM it was
+/// recorded against, the machine configuration and the key timeline.
+///
+/// Recordings are stored as plain text so they can be attached to bug reports
+/// and diffed in the regression suite:
+///
+/// ```text
+/// chip8-input-recording 1
+/// rom 4c6f8e0b2d1a7c39
+/// platform vip
+/// seed 1234
+/// cycles-per-frame 8
+/// timing fixed
+/// frames 600
+/// press 12 5
+/// release 20 5
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputRecording {
+    pub rom_hash: u64,
+    pub platform: Platform,
+    pub seed: u64,
+    pub cycles_per_frame: usize,
+    pub timing: Timing,
+    pub frames: u64,
+    pub events: Vec<InputEvent>,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(std::io::Error),
+    /// Line number and description of a malformed line
+    Parse(usize, String),
+    /// The ROM handed to `replay` is not the one the input was recorded on
+    RomMismatch {
+        expected: u64,
+        found: u64,
+    },
+    /// A key event for something other than keys 0x0 to 0xF
+    InvalidKey(u8),
+    /// The ROM matches the recording but does not fit in memory
+    RomTooLarge(RomTooLarge),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(e) => write!(f, "{}", e),
+            ReplayError::Parse(line, msg) => write!(f, "line {}: {}", line, msg),
+            ReplayError::RomMismatch { expected, found } => write!(
+                f,
+                "recording was made with ROM {:016x}, got {:016x}",
+                expected, found
+            ),
+            ReplayError::InvalidKey(key) => write!(f, "no such key {:#x}", key),
+            ReplayError::RomTooLarge(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl From<std::io::Error> for ReplayError {
+    fn from(e: std::io::Error) -> Self {
+        ReplayError::Io(e)
+    }
+}
+
+impl From<RomTooLarge> for ReplayError {
+    fn from(e: RomTooLarge) -> Self {
+        ReplayError::RomTooLarge(e)
+    }
+}
+
+/// 64-bit FNV-1a, used to tie recordings to the ROM they were made with
+pub fn rom_hash(rom: &[u8]) -> u64 {
+    rom.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0100_0000_01B3)
+    })
+}
+
+/// Feeds key transitions into a running machine and logs them with the frame they land in
+pub struct InputRecorder {
+    recording: InputRecording,
+}
+
+impl InputRecorder {
+    /// Start recording a freshly booted machine that has `rom` loaded
+    pub fn new(chip8: &Chip8, rom: &[u8]) -> Self {
+        assert_eq!(chip8.frame(), 0, "recordings must start at power on");
+        InputRecorder {
+            recording: InputRecording {
+                rom_hash: rom_hash(rom),
+                platform: chip8.platform(),
+                seed: chip8.seed(),
+                cycles_per_frame: chip8.cycles_per_frame(),
+                timing: chip8.timing(),
+                frames: 0,
+                events: Vec::new(),
+            },
+        }
+    }
+
+    pub fn key_event(
+        &mut self,
+        chip8: &mut Chip8,
+        key: u8,
+        pressed: bool,
+    ) -> Result<(), ReplayError> {
+        if key > 0x0F {
+            return Err(ReplayError::InvalidKey(key));
+        }
+        if pressed {
+            chip8.key_mut().press_key(key as usize);
+        } else {
+            chip8.key_mut().release_key(key as usize);
+        }
+        self.recording.events.push(InputEvent {
+            frame: chip8.frame(),
+            key,
+            pressed,
+        });
+        Ok(())
+    }
+
+    pub fn finish(mut self, chip8: &Chip8) -> InputRecording {
+        self.recording.frames = chip8.frame();
+        self.recording
+    }
+}
+
+impl InputRecording {
+    pub fn load(path: &str) -> Result<Self, ReplayError> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), ReplayError> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+
+    /// Boot a machine configured like the recorded one, with `rom` loaded
+    pub fn boot(&self, rom: &[u8]) -> Result<Chip8, ReplayError> {
+        let found = rom_hash(rom);
+        if found != self.rom_hash {
+            return Err(ReplayError::RomMismatch {
+                expected: self.rom_hash,
+                found,
+            });
+        }
+        let mut chip8 = Chip8::new();
+        chip8.set_platform(self.platform);
+        chip8.set_seed(self.seed);
+        chip8.set_cycles_per_frame(self.cycles_per_frame);
+        chip8.set_timing(self.timing);
+        chip8.try_load_rom_bytes(rom)?;
+        Ok(chip8)
+    }
+
+    /// Start feeding the events into a machine from `boot`
+    pub fn player(&self) -> InputPlayer<'_> {
+        InputPlayer {
+            events: &self.events,
+            next: 0,
+        }
+    }
+
+    /// Run the whole recording headlessly and return the final machine
+    pub fn replay(&self, rom: &[u8]) -> Result<Chip8, ReplayError> {
+        let mut chip8 = self.boot(rom)?;
+        let mut player = self.player();
+        while chip8.frame() < self.frames {
+            player.apply_events(&mut chip8);
+            chip8.run_frame();
+        }
+        Ok(chip8)
+    }
+}
+
+/// Cursor over a recording's events, which are kept in frame order
+pub struct InputPlayer<'a> {
+    events: &'a [InputEvent],
+    next: usize,
+}
+
+impl InputPlayer<'_> {
+    /// Apply the events scheduled for the frame `chip8` is about to run
+    pub fn apply_events(&mut self, chip8: &mut Chip8) {
+        let frame = chip8.frame();
+        // Seek again if the machine went back in time, say through a rewind
+        if self.next > 0 && self.events[self.next - 1].frame >= frame {
+            self.next = self.events.partition_point(|e| e.frame < frame);
+        }
+        while let Some(event) = self.events.get(self.next) {
+            if event.frame > frame {
+                break;
+            }
+            if event.frame == frame {
+                if event.pressed {
+                    chip8.key_mut().press_key(event.key as usize);
+                } else {
+                    chip8.key_mut().release_key(event.key as usize);
+                }
+            }
+            self.next += 1;
+        }
+    }
+}
+
+impl fmt::Display for InputRecording {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        writeln!(f, "rom {:016x}", self.rom_hash)?;
+        writeln!(f, "platform {}", self.platform)?;
+        writeln!(f, "seed {}", self.seed)?;
+        writeln!(f, "cycles-per-frame {}", self.cycles_per_frame)?;
+        writeln!(f, "timing {}", self.timing)?;
+        writeln!(f, "frames {}", self.frames)?;
+        for event in &self.events {
+            let action = if event.pressed { "press" } else { "release" };
+            writeln!(f, "{} {} {:X}", action, event.frame, event.key)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for InputRecording {
+    type Err = ReplayError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines().enumerate().map(|(n, line)| (n + 1, line.trim()));
+        match lines.next() {
+            Some((_, HEADER)) => {}
+            _ => return Err(ReplayError::Parse(1, "missing recording header".into())),
+        }
+
+        let mut recording = InputRecording {
+            rom_hash: 0,
+            platform: Platform::default(),
+            seed: 0,
+            cycles_per_frame: crate::emu::DEFAULT_CYCLES_PER_FRAME,
+            // Recordings from before the VIP timing model have no timing line
+            timing: Timing::Fixed,
+            frames: 0,
+            events: Vec::new(),
+        };
+        for (n, line) in lines {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let err = |msg: &str| ReplayError::Parse(n, format!("{}: '{}'", msg, line));
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                ["rom", hash] => {
+                    recording.rom_hash =
+                        u64::from_str_radix(hash, 16).map_err(|_| err("bad ROM hash"))?
+                }
+                ["platform", name] => {
+                    recording.platform = name.parse().map_err(|_| err("bad platform"))?
+                }
+                ["seed", seed] => recording.seed = seed.parse().map_err(|_| err("bad seed"))?,
+                ["cycles-per-frame", cycles] => {
+                    recording.cycles_per_frame =
+                        cycles.parse().map_err(|_| err("bad cycle count"))?
+                }
+                ["timing", name] => {
+                    recording.timing = name.parse().map_err(|_| err("bad timing"))?
+                }
+                ["frames", frames] => {
+                    recording.frames = frames.parse().map_err(|_| err("bad frame count"))?
+                }
+                [action @ ("press" | "release"), frame, key] => {
+                    let frame = frame.parse().map_err(|_| err("bad frame number"))?;
+                    let key = u8::from_str_radix(key, 16)
+                        .ok()
+                        .filter(|&k| k <= 0x0F)
+                        .ok_or_else(|| err("bad key"))?;
+                    recording.events.push(InputEvent {
+                        frame,
+                        key,
+                        pressed: *action == "press",
+                    });
+                }
+                _ => return Err(err("unrecognised line")),
+            }
+        }
+        // Players walk the events in order; the sort is stable so events
+        // within a frame keep the order they were written in
+        recording.events.sort_by_key(|e| e.frame);
+        Ok(recording)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Waits for a key with FX0A, then draws a random-width sprite row from
+    // the key value and loops: 6000 F00A C1FF A300 F155 A300 D011 1202
+    const ROM: [u8; 16] = [
+        0x60, 0x00, 0xF0, 0x0A, 0xC1, 0xFF, 0xA3, 0x00, 0xF1, 0x55, 0xA3, 0x00, 0xD0, 0x11, 0x12,
+        0x02,
+    ];
+
+    fn record_session() -> (InputRecording, Chip8) {
+        let mut chip8 = Chip8::new();
+        chip8.set_seed(99);
+        chip8.load_rom_bytes(&ROM);
+        let mut recorder = InputRecorder::new(&chip8, &ROM);
+        for frame in 0..40 {
+            match frame {
+                3 => recorder.key_event(&mut chip8, 0x5, true).unwrap(),
+                6 => recorder.key_event(&mut chip8, 0x5, false).unwrap(),
+                20 => recorder.key_event(&mut chip8, 0xA, true).unwrap(),
+                22 => recorder.key_event(&mut chip8, 0xA, false).unwrap(),
+                _ => {}
+            }
+            chip8.run_frame();
+        }
+        (recorder.finish(&chip8), chip8)
+    }
+
+    #[test]
+    fn replay_reproduces_the_recorded_session() {
+        let (recording, live) = record_session();
+        let replayed = recording.replay(&ROM).unwrap();
+        assert_eq!(replayed.save_state(), live.save_state());
+    }
+
+    #[test]
+    fn recording_text_roundtrip() {
+        let (recording, _) = record_session();
+        let parsed: InputRecording = recording.to_string().parse().unwrap();
+        assert_eq!(parsed, recording);
+        assert_eq!(parsed.events.len(), 4);
+    }
+
+    #[test]
+    fn events_out_of_order_are_sorted_by_frame() {
+        let (recording, live) = record_session();
+        let mut text: Vec<String> = recording.to_string().lines().map(String::from).collect();
+        let first_event = text.len() - recording.events.len();
+        // Parsing puts the events back in frame order
+        text[first_event..].reverse();
+        let parsed: InputRecording = text.join("\n").parse().unwrap();
+        assert_eq!(parsed.events, recording.events);
+        assert_eq!(parsed.replay(&ROM).unwrap().save_state(), live.save_state());
+    }
+
+    #[test]
+    fn recorder_rejects_invalid_keys() {
+        let mut chip8 = Chip8::new();
+        let mut recorder = InputRecorder::new(&chip8, &ROM);
+        assert!(matches!(
+            recorder.key_event(&mut chip8, 0x10, true),
+            Err(ReplayError::InvalidKey(0x10))
+        ));
+        assert!(recorder.finish(&chip8).events.is_empty());
+    }
+
+    #[test]
+    fn replay_rejects_other_rom() {
+        let (recording, _) = record_session();
+        assert!(matches!(
+            recording.replay(&[0x12, 0x00]),
+            Err(ReplayError::RomMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn replay_rejects_rom_too_large() {
+        // A recording of a ROM that cannot have been loaded
+        let rom = vec![0; 0xE01];
+        let recording = InputRecorder::new(&Chip8::new(), &rom).finish(&Chip8::new());
+        assert!(matches!(
+            recording.boot(&rom),
+            Err(ReplayError::RomTooLarge(RomTooLarge { len: 0xE01 }))
+        ));
+    }
+}
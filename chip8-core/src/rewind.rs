@@ -0,0 +1,240 @@
+use std::collections::VecDeque;
+
+use crate::emu::Chip8;
+
+/// Frames per second the rewind budget is measured in
+const FRAME_RATE: usize = 60;
+
+/// Ring buffer of past machine states for scrubbing backwards.
+///
+/// Only the newest snapshot is kept in full. Every older snapshot is stored as
+/// the XOR of itself with its successor, run-length encoded. Since most of
+/// `Memory` and `Display` is unchanged between frames those deltas are
+/// mostly zero and compress down to a handful of bytes.
+pub struct RewindBuffer {
+    capacity: usize,
+    interval: usize,
+    frames_since_snapshot: usize,
+    head: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>,
+}
+
+impl RewindBuffer {
+    /// Keep up to `seconds` of history, taking a snapshot every `interval` frames
+    pub fn new(seconds: usize, interval: usize) -> Self {
+        assert!(interval > 0);
+        RewindBuffer {
+            capacity: seconds * FRAME_RATE / interval,
+            interval,
+            frames_since_snapshot: 0,
+            head: None,
+            deltas: VecDeque::new(),
+        }
+    }
+
+    /// Record the state after a frame, honouring the snapshot interval
+    pub fn push_frame(&mut self, chip8: &Chip8) {
+        self.frames_since_snapshot += 1;
+        if self.head.is_some() && self.frames_since_snapshot < self.interval {
+            return;
+        }
+        self.frames_since_snapshot = 0;
+        self.push_state(chip8.save_state());
+    }
+
+    /// Step back one snapshot, restoring it into `chip8`. The keys held right
+    /// now stay held, whatever was pressed when the snapshot was taken.
+    /// Returns false once the history is exhausted.
+    pub fn rewind(&mut self, chip8: &mut Chip8) -> bool {
+        let (Some(head), Some(delta)) = (self.head.as_mut(), self.deltas.pop_back()) else {
+            return false;
+        };
+        apply_delta(head, &delta);
+        self.frames_since_snapshot = 0;
+        let held = chip8.key_mut().get_keys();
+        chip8
+            .load_state(head)
+            .expect("rewind buffer holds a corrupt snapshot");
+        chip8.key_mut().set_held_keys(held);
+        true
+    }
+
+    /// Number of snapshots that can still be rewound
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    /// Total bytes used by the stored history
+    pub fn memory_usage(&self) -> usize {
+        self.head.as_ref().map_or(0, Vec::len) + self.deltas.iter().map(Vec::len).sum::<usize>()
+    }
+
+    pub fn clear(&mut self) {
+        self.head = None;
+        self.deltas.clear();
+        self.frames_since_snapshot = 0;
+    }
+
+    fn push_state(&mut self, state: Vec<u8>) {
+        if let Some(previous) = self.head.take() {
+            self.deltas.push_back(encode_delta(&state, &previous));
+        }
+        while self.deltas.len() > self.capacity {
+            self.deltas.pop_front();
+        }
+        self.head = Some(state);
+    }
+}
+
+// The delta starts with the length of the previous state (the stack depth can
+// change it), followed by (zero run, literal length, literal bytes) records over
+// the XOR of both states zero-padded to the same length. All lengths are LEB128.
+fn encode_delta(current: &[u8], previous: &[u8]) -> Vec<u8> {
+    let len = current.len().max(previous.len());
+    let xor: Vec<u8> = (0..len)
+        .map(|i| current.get(i).unwrap_or(&0) ^ previous.get(i).unwrap_or(&0))
+        .collect();
+    let mut out = Vec::new();
+    write_varint(&mut out, previous.len());
+    let mut pos = 0;
+    while pos < xor.len() {
+        let zeros = xor[pos..].iter().take_while(|&&b| b == 0).count();
+        pos += zeros;
+        let literals = xor[pos..].iter().take_while(|&&b| b != 0).count();
+        write_varint(&mut out, zeros);
+        write_varint(&mut out, literals);
+        out.extend_from_slice(&xor[pos..pos + literals]);
+        pos += literals;
+    }
+    out
+}
+
+fn apply_delta(state: &mut Vec<u8>, delta: &[u8]) {
+    let mut cursor = 0;
+    let target_len = read_varint(delta, &mut cursor);
+    state.resize(state.len().max(target_len), 0);
+    let mut pos = 0;
+    while cursor < delta.len() {
+        pos += read_varint(delta, &mut cursor);
+        let literals = read_varint(delta, &mut cursor);
+        for (dst, src) in state[pos..pos + literals]
+            .iter_mut()
+            .zip(&delta[cursor..cursor + literals])
+        {
+            *dst ^= src;
+        }
+        pos += literals;
+        cursor += literals;
+    }
+    state.truncate(target_len);
+}
+
+fn write_varint(out: &mut Vec<u8>, mut v: usize) {
+    while v >= 0x80 {
+        out.push((v as u8) | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+fn read_varint(bytes: &[u8], cursor: &mut usize) -> usize {
+    let mut v = 0;
+    let mut shift = 0;
+    loop {
+        let b = bytes[*cursor];
+        *cursor += 1;
+        v |= ((b & 0x7F) as usize) << shift;
+        if b & 0x80 == 0 {
+            return v;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delta_roundtrip() {
+        let previous = vec![0u8; 300];
+        let mut current = previous.clone();
+        current[3] = 7;
+        current[4] = 9;
+        current[299] = 1;
+
+        let delta = encode_delta(&current, &previous);
+        assert!(delta.len() < 16);
+
+        let mut restored = current.clone();
+        apply_delta(&mut restored, &delta);
+        assert_eq!(restored, previous);
+    }
+
+    #[test]
+    fn delta_roundtrip_with_length_change() {
+        let previous = vec![1u8, 2, 3, 4, 5];
+        let current = vec![1u8, 2, 3];
+
+        let mut restored = current.clone();
+        apply_delta(&mut restored, &encode_delta(&current, &previous));
+        assert_eq!(restored, previous);
+
+        let mut restored = previous.clone();
+        apply_delta(&mut restored, &encode_delta(&previous, &current));
+        assert_eq!(restored, current);
+    }
+
+    #[test]
+    fn rewind_restores_earlier_frames() {
+        let mut chip8 = Chip8::new();
+        let mut rewind = RewindBuffer::new(10, 1);
+        // 7001 - ADD V0, 1; 2206 - CALL 0x206; 1200 - JP 0x200; 00EE - RET
+        chip8.load_rom_bytes(&[0x70, 0x01, 0x22, 0x06, 0x12, 0x00, 0x00, 0xEE]);
+        let mut states = Vec::new();
+        for frame in 0..5 {
+            chip8.set_cycles_per_frame(frame + 1);
+            chip8.run_frame();
+            rewind.push_frame(&chip8);
+            states.push(chip8.save_state());
+        }
+
+        for expected in states.iter().rev().skip(1) {
+            assert!(rewind.rewind(&mut chip8));
+            assert_eq!(&chip8.save_state(), expected);
+        }
+        assert!(!rewind.rewind(&mut chip8));
+    }
+
+    #[test]
+    fn rewind_keeps_the_live_keys() {
+        let mut chip8 = Chip8::new();
+        let mut rewind = RewindBuffer::new(10, 1);
+        chip8.key_mut().press_key(0x5);
+        for _ in 0..3 {
+            chip8.run_frame();
+            rewind.push_frame(&chip8);
+        }
+
+        // Let go of the key before scrubbing back over the frames it was held in
+        chip8.key_mut().release_key(0x5);
+        chip8.key_mut().press_key(0x9);
+        assert!(rewind.rewind(&mut chip8));
+        assert!(!chip8.key_mut().key_is_pressed(0x5));
+        assert!(chip8.key_mut().key_is_pressed(0x9));
+    }
+
+    #[test]
+    fn rewind_respects_time_budget() {
+        let chip8 = Chip8::new();
+        let mut rewind = RewindBuffer::new(1, 2);
+        for _ in 0..1000 {
+            rewind.push_frame(&chip8);
+        }
+        assert_eq!(rewind.len(), 30);
+    }
+}
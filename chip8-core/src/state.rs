@@ -0,0 +1,122 @@
+use std::fmt;
+
+pub(crate) const STATE_MAGIC: &[u8; 4] = b"C8ST";
+pub(crate) const STATE_VERSION: u8 = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateError {
+    /// The buffer does not start with the save state magic bytes
+    BadMagic,
+    /// The save state was written by an incompatible version of the emulator
+    UnsupportedVersion(u8),
+    /// The buffer ended before the whole state was read
+    Truncated,
+    /// A field holds a value the emulator cannot represent
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::BadMagic => write!(f, "not a CHIP-8 save state"),
+            StateError::UnsupportedVersion(v) => write!(f, "unsupported save state version {}", v),
+            StateError::Truncated => write!(f, "save state is truncated"),
+            StateError::Corrupt(field) => write!(f, "save state has an invalid {}", field),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+// Little helpers to (de)serialize the machine into a flat byte buffer.
+// The layout is fixed-size apart from the stack, which keeps consecutive
+// snapshots byte-aligned and cheap to delta-encode.
+pub(crate) struct StateWriter {
+    bytes: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        let mut bytes = Vec::with_capacity(8192);
+        bytes.extend_from_slice(STATE_MAGIC);
+        bytes.push(STATE_VERSION);
+        StateWriter { bytes }
+    }
+
+    pub fn u8(&mut self, v: u8) {
+        self.bytes.push(v);
+    }
+
+    pub fn u16(&mut self, v: u16) {
+        self.bytes.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, v: u64) {
+        self.bytes.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn bool(&mut self, v: bool) {
+        self.bytes.push(v as u8);
+    }
+
+    pub fn bytes(&mut self, v: &[u8]) {
+        self.bytes.extend_from_slice(v);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+pub(crate) struct StateReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Result<Self, StateError> {
+        if bytes.len() < STATE_MAGIC.len() + 1 || &bytes[..STATE_MAGIC.len()] != STATE_MAGIC {
+            return Err(StateError::BadMagic);
+        }
+        let version = bytes[STATE_MAGIC.len()];
+        if version != STATE_VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        Ok(StateReader {
+            bytes,
+            pos: STATE_MAGIC.len() + 1,
+        })
+    }
+
+    pub fn bytes(&mut self, n: usize) -> Result<&'a [u8], StateError> {
+        if self.pos + n > self.bytes.len() {
+            return Err(StateError::Truncated);
+        }
+        let slice = &self.bytes[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(slice)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, StateError> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, StateError> {
+        let mut b = [0u8; 8];
+        b.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(b))
+    }
+
+    pub fn bool(&mut self) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::Corrupt("flag")),
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! How much work the emulator does per 60 Hz frame.
+//!
+//! `Timing::Fixed` runs a set number of instructions per frame. `Timing::CosmacVip`
+//! instead charges every instruction the machine cycles the VIP interpreter
+//! spends on it and runs until the frame's share of the 1.76 MHz CDP1802 is
+//! used up, so slow instructions like DXYN and FX33 cost what they did on the
+//! original hardware.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::display::DISPLAY_HEIGHT;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Timing {
+    /// `cycles_per_frame` instructions per frame, whatever they are
+    #[default]
+    Fixed,
+    /// A budget of COSMAC VIP machine cycles per frame
+    CosmacVip,
+}
+
+/// CDP1802 clock on the VIP; one machine cycle takes 8 clocks
+pub const VIP_CLOCK_HZ: u32 = 1_760_900;
+
+/// Machine cycles in one 60 Hz frame
+pub const VIP_CYCLES_PER_FRAME: u32 = VIP_CLOCK_HZ / 8 / 60;
+
+/// Machine cycles the CDP1861 takes for video DMA each frame: 8 bytes on
+/// each of the 128 scanlines, with every CHIP-8 row shown on 4 of them
+pub const VIP_DMA_CYCLES_PER_FRAME: u32 = 8 * 4 * DISPLAY_HEIGHT as u32;
+
+/// Machine cycles left for the interpreter each frame
+pub const VIP_INTERPRETER_CYCLES_PER_FRAME: u32 = VIP_CYCLES_PER_FRAME - VIP_DMA_CYCLES_PER_FRAME;
+
+// Fetching and decoding an instruction, before its own routine runs
+const FETCH_CYCLES: u32 = 40;
+
+// Extra cost of the conditional skips when they skip
+const SKIP_CYCLES: u32 = 4;
+
+impl Timing {
+    pub const ALL: [Timing; 2] = [Timing::Fixed, Timing::CosmacVip];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Timing::Fixed => "fixed",
+            Timing::CosmacVip => "vip",
+        }
+    }
+}
+
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Timing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Timing::ALL
+            .into_iter()
+            .find(|t| t.name() == s)
+            .ok_or_else(|| format!("unknown timing '{}'", s))
+    }
+}
+
+/// Machine cycles the VIP interpreter takes for `opcode`, given the registers
+/// it ran with and whether it skipped the next instruction
+pub fn vip_cycles(opcode: u16, registers: &[u8; 16], skipped: bool) -> u32 {
+    let x = ((opcode & 0x0F00) >> 8) as usize;
+    let n = (opcode & 0x000F) as u32;
+    let skip = if skipped { SKIP_CYCLES } else { 0 };
+    let routine = match opcode & 0xF000 {
+        0x0000 => match opcode {
+            // Clearing the 256 byte display buffer
+            0x00E0 => 24 + 12 * 256,
+            0x00EE => 10,
+            _ => 0,
+        },
+        0x1000 => 12,
+        0x2000 => 26,
+        0x3000 | 0x4000 => 10 + skip,
+        0x5000 | 0x9000 => 14 + skip,
+        0x6000 => 6,
+        0x7000 => 10,
+        0x8000 => 44,
+        0xA000 => 12,
+        0xB000 => 22,
+        0xC000 => 36,
+        // Each row is shifted into place byte by byte unless it is aligned
+        0xD000 => {
+            let per_row = if registers[x].is_multiple_of(8) {
+                46
+            } else {
+                68
+            };
+            26 + n * per_row
+        }
+        0xE000 => 14 + skip,
+        0xF000 => match opcode & 0x00FF {
+            0x07 | 0x0A | 0x15 | 0x18 => 10,
+            0x1E => 16,
+            0x29 => 16,
+            // Repeated subtraction, one round per unit of every digit
+            0x33 => {
+                let v = registers[x] as u32;
+                80 + 16 * (v / 100 + v / 10 % 10 + v % 10)
+            }
+            0x55 | 0x65 => 14 + 14 * (x as u32 + 1),
+            _ => 0,
+        },
+        _ => 0,
+    };
+    FETCH_CYCLES + routine
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn budget_matches_the_vip_clock() {
+        assert_eq!(VIP_CYCLES_PER_FRAME, 3668);
+        assert_eq!(VIP_INTERPRETER_CYCLES_PER_FRAME, 3668 - 1024);
+    }
+
+    #[test]
+    fn costs_depend_on_operands() {
+        let mut registers = [0; 16];
+        assert_eq!(vip_cycles(0x6012, &registers, false), 46);
+
+        // Unaligned sprites cost more per row
+        registers[1] = 8;
+        let aligned = vip_cycles(0xD125, &registers, false);
+        registers[1] = 9;
+        assert!(vip_cycles(0xD125, &registers, false) > aligned);
+
+        registers[3] = 199;
+        let slow = vip_cycles(0xF333, &registers, false);
+        registers[3] = 100;
+        assert!(vip_cycles(0xF333, &registers, false) < slow);
+
+        assert!(vip_cycles(0x3000, &registers, true) > vip_cycles(0x3000, &registers, false));
+    }
+}
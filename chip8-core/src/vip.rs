@@ -0,0 +1,381 @@
+//! The COSMAC VIP itself: a CDP1802 with 4 KiB of RAM, the monitor ROM, the
+//! CDP1861 video chip, the hex keypad and the beeper.
+//!
+//! Instead of interpreting CHIP-8 directly this runs a CHIP-8 interpreter image
+//! on the emulated CPU, so every timing and quirk of the original comes along.
+//! The stock interpreter expects the VIP monitor ROM as well: the monitor
+//! sizes RAM before starting it, and its display interrupt lives in the ROM at
+//! 0x8146. Self-contained interpreters can run without a monitor, starting
+//! straight from 0x0000.
+//!
+//! I/O as wired on the VIP:
+//! - `INP 1` turns the CDP1861 on, `OUT 1` turns it off
+//! - `OUT 2` selects the keypad key that EF3 reports as held
+//! - EF1 is the CDP1861's display status, Q drives the beeper
+
+use crate::cdp1802::{Bus, Cdp1802};
+use std::fmt;
+
+use crate::display::{Display, DISPLAY_HEIGHT};
+use crate::emu::RomTooLarge;
+use crate::keyboard::Keyboard;
+use crate::memory::{Memory, MEMORY_SIZE};
+use crate::timing::VIP_CYCLES_PER_FRAME;
+
+pub const MONITOR_SIZE: usize = 512;
+
+// The monitor ROM answers everywhere from 0x8000 up
+const ROM_START: u16 = 0x8000;
+
+// Where CHIP-8 programs are loaded, right after the interpreter
+const PROGRAM_START: usize = 0x200;
+
+// CDP1861 frame layout in scanlines of 14 machine cycles
+const LINE_CYCLES: u32 = 14;
+const FIRST_DISPLAY_LINE: u32 = 80;
+const DISPLAY_LINES: u32 = 128;
+// Bytes fetched by DMA on every displayed line, one machine cycle each
+const DMA_BYTES: u32 = 8;
+// The interrupt request comes two lines before the display starts
+const INTERRUPT_LINES: std::ops::Range<u32> = 78..80;
+// EF1 is asserted for four lines before the display starts and before it ends
+const EF1_LINES: [std::ops::Range<u32>; 2] = [76..80, 204..208];
+
+// CHIP-8 pixels are four scanlines tall
+const LINES_PER_ROW: u32 = DISPLAY_LINES / DISPLAY_HEIGHT as u32;
+
+/// An interpreter image larger than the VIP's RAM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InterpreterTooLarge {
+    pub len: usize,
+}
+
+impl fmt::Display for InterpreterTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "interpreter is {} bytes, at most {} fit",
+            self.len, MEMORY_SIZE
+        )
+    }
+}
+
+impl std::error::Error for InterpreterTooLarge {}
+
+pub struct Vip {
+    cpu: Cdp1802,
+    bus: VipBus,
+    display: Display,
+    frame: u64,
+}
+
+struct VipBus {
+    ram: Memory,
+    monitor: Option<Vec<u8>>,
+    // After reset the ROM shows up at 0x0000 until the CPU first addresses it at 0x8000
+    rom_at_zero: bool,
+    display_on: bool,
+    key_latch: u8,
+    key: Keyboard,
+    // Machine cycles into the current frame
+    cycle: u32,
+}
+
+impl Default for Vip {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Vip {
+    pub fn new() -> Self {
+        Vip {
+            cpu: Cdp1802::new(),
+            bus: VipBus {
+                ram: Memory::new(),
+                monitor: None,
+                rom_at_zero: false,
+                display_on: false,
+                key_latch: 0,
+                key: Keyboard::new(),
+                cycle: 0,
+            },
+            display: Display::new(),
+            frame: 0,
+        }
+    }
+
+    /// Install the monitor ROM and reset into it, as when powering on
+    pub fn load_monitor(&mut self, rom: &[u8]) {
+        let mut monitor = vec![0xFF; MONITOR_SIZE];
+        let len = rom.len().min(MONITOR_SIZE);
+        monitor[..len].copy_from_slice(&rom[..len]);
+        self.bus.monitor = Some(monitor);
+        self.reset();
+    }
+
+    /// Load a CHIP-8 interpreter at 0x0000, where the monitor hands over to it.
+    /// Panics if it is larger than RAM; `try_load_interpreter` reports that instead.
+    pub fn load_interpreter(&mut self, interpreter: &[u8]) {
+        if let Err(e) = self.try_load_interpreter(interpreter) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Like `load_interpreter`, but an image that does not fit is an error and
+    /// leaves RAM untouched
+    pub fn try_load_interpreter(&mut self, interpreter: &[u8]) -> Result<(), InterpreterTooLarge> {
+        if interpreter.len() > MEMORY_SIZE {
+            return Err(InterpreterTooLarge {
+                len: interpreter.len(),
+            });
+        }
+        self.bus.ram.write_slice_at(0, interpreter);
+        Ok(())
+    }
+
+    /// Load a CHIP-8 program at 0x200 for the interpreter to run. Panics if it
+    /// is longer than `MAX_ROM_SIZE`; `try_load_rom_bytes` reports that instead.
+    pub fn load_rom_bytes(&mut self, rom_bytes: &[u8]) {
+        if let Err(e) = self.try_load_rom_bytes(rom_bytes) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Like `load_rom_bytes`, but a ROM that does not fit is an error and
+    /// leaves RAM untouched
+    pub fn try_load_rom_bytes(&mut self, rom_bytes: &[u8]) -> Result<(), RomTooLarge> {
+        if PROGRAM_START + rom_bytes.len() > MEMORY_SIZE {
+            return Err(RomTooLarge {
+                len: rom_bytes.len(),
+            });
+        }
+        self.bus.ram.write_slice_at(PROGRAM_START, rom_bytes);
+        Ok(())
+    }
+
+    /// Press the reset switch: RAM and the keypad are left alone
+    pub fn reset(&mut self) {
+        self.cpu = Cdp1802::new();
+        self.bus.rom_at_zero = self.bus.monitor.is_some();
+        self.bus.display_on = false;
+        self.bus.cycle = 0;
+    }
+
+    pub fn cpu(&self) -> &Cdp1802 {
+        &self.cpu
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.bus.ram
+    }
+
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        &mut self.bus.ram
+    }
+
+    pub fn display(&self) -> &Display {
+        &self.display
+    }
+
+    pub fn display_mut(&mut self) -> &mut Display {
+        &mut self.display
+    }
+
+    pub fn key_mut(&mut self) -> &mut Keyboard {
+        &mut self.bus.key
+    }
+
+    /// Whether the beeper is sounding
+    pub fn sound_active(&self) -> bool {
+        self.cpu.q()
+    }
+
+    /// Number of frames run since power on
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Run the 3668 machine cycles of one 60 Hz frame, with the CDP1861
+    /// interrupting and stealing cycles for DMA as it scans the picture
+    pub fn run_frame(&mut self) {
+        let mut next_line = FIRST_DISPLAY_LINE;
+        let mut interrupted = false;
+        while self.bus.cycle < VIP_CYCLES_PER_FRAME {
+            let line = self.bus.cycle / LINE_CYCLES;
+            if !self.bus.display_on {
+                self.bus.cycle += self.cpu.step(&mut self.bus);
+            } else if line >= next_line && next_line < FIRST_DISPLAY_LINE + DISPLAY_LINES {
+                self.dma_line(next_line - FIRST_DISPLAY_LINE);
+                next_line += 1;
+            } else if !interrupted && INTERRUPT_LINES.contains(&line) && self.cpu.interrupt() {
+                interrupted = true;
+                self.bus.cycle += 1;
+            } else {
+                self.bus.cycle += self.cpu.step(&mut self.bus);
+            }
+        }
+        // An instruction running over the end of the frame delays the next one
+        self.bus.cycle -= VIP_CYCLES_PER_FRAME;
+        if !self.bus.display_on {
+            self.display.clear();
+        }
+        self.frame += 1;
+    }
+
+    // Fetch one scanline's 64 pixels. A CHIP-8 row shows whatever was lit on
+    // any of its four scanlines.
+    fn dma_line(&mut self, line: u32) {
+        let y = (line / LINES_PER_ROW) as usize;
+        let first = line.is_multiple_of(LINES_PER_ROW);
+        for byte in 0..DMA_BYTES as usize {
+            let bits = self.cpu.dma_out(&mut self.bus);
+            for bit in 0..8 {
+                let x = byte * 8 + bit;
+                let lit = bits >> (7 - bit) & 1;
+                let old = if first {
+                    0
+                } else {
+                    self.display.get_pixel(x, y)
+                };
+                self.display.update_pixel(x, y, old | lit);
+            }
+        }
+        self.bus.cycle += DMA_BYTES;
+    }
+}
+
+impl Bus for VipBus {
+    fn read(&mut self, address: u16) -> u8 {
+        if address >= ROM_START {
+            self.rom_at_zero = false;
+        }
+        if address >= ROM_START || self.rom_at_zero {
+            return self
+                .monitor
+                .as_ref()
+                .map_or(0xFF, |rom| rom[address as usize % MONITOR_SIZE]);
+        }
+        self.ram.get_byte(address as usize % MEMORY_SIZE)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if address < ROM_START {
+            self.ram.set_byte(address as usize % MEMORY_SIZE, value);
+        }
+    }
+
+    fn input(&mut self, port: u8) -> u8 {
+        if port == 1 {
+            self.display_on = true;
+        }
+        0
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        match port {
+            1 => self.display_on = false,
+            2 => self.key_latch = value & 0x0F,
+            _ => {}
+        }
+    }
+
+    fn flag(&mut self, n: u8) -> bool {
+        let line = self.cycle / LINE_CYCLES;
+        match n {
+            1 => self.display_on && EF1_LINES.iter().any(|lines| lines.contains(&line)),
+            3 => self.key.key_is_pressed(self.key_latch),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interrupt_routine_feeds_the_display() {
+        let mut vip = Vip::new();
+        vip.load_interpreter(&[
+            0xF8, 0x00, 0xB1, 0xB2, 0xB3, // LDI 0; PHI R1; PHI R2; PHI R3
+            0xF8, 0x21, 0xA1, // R1 = 0x0021, the interrupt routine
+            0xF8, 0xFF, 0xA2, // R2 = 0x00FF
+            0xF8, 0x10, 0xA3, 0xD3, // R3 = 0x0010; SEP 3, leaving R0 to DMA
+        ]);
+        // SEX 2; INP 1; BR 12
+        vip.memory_mut()
+            .write_slice_at(0x10, &[0xE2, 0x69, 0x30, 0x12]);
+        // Point R0 at a 1 KiB buffer at 0x0100, so each of the 128 scanlines
+        // shows its own 8 bytes: RET; DEC R2; SAV; R0 = 0x0100; BR 20
+        vip.memory_mut().write_slice_at(
+            0x20,
+            &[
+                0x70, 0x22, 0x78, 0xF8, 0x01, 0xB0, 0xF8, 0x00, 0xA0, 0x30, 0x20,
+            ],
+        );
+        // The first byte of scanline 0 and the last byte of scanline 127
+        vip.memory_mut().set_byte(0x100, 0x80);
+        vip.memory_mut().set_byte(0x4FF, 0x01);
+        // A pixel lit on the third scanline of row 1 only
+        vip.memory_mut().set_byte(0x100 + 6 * 8 + 1, 0x40);
+
+        vip.run_frame();
+        vip.run_frame();
+        let display = vip.display();
+        assert_eq!(display.get_pixel(0, 0), 1);
+        assert_eq!(display.get_pixel(1, 0), 0);
+        assert_eq!(display.get_pixel(63, 31), 1);
+        assert_eq!(display.get_pixel(9, 1), 1);
+        assert_eq!(vip.cpu().pc(), 0x12);
+        assert!(vip.cpu().ie());
+    }
+
+    #[test]
+    fn keypad_is_read_through_ef3() {
+        let mut vip = Vip::new();
+        vip.load_interpreter(&[
+            0xF8, 0x80, 0xA2, 0xE2, // R2 = 0x0080; SEX 2
+            0xF8, 0x05, 0x52, 0x62, 0x22, // select key 5
+            0x36, 0x0D, // B3 0D
+            0x30, 0x04, // BR 04
+            0x7B, 0x30, 0x0D, // SEQ; BR 0D
+        ]);
+        vip.run_frame();
+        assert!(!vip.sound_active());
+        vip.key_mut().press_key(0x4);
+        vip.run_frame();
+        assert!(!vip.sound_active());
+        vip.key_mut().press_key(0x5);
+        vip.run_frame();
+        assert!(vip.sound_active());
+    }
+
+    #[test]
+    fn images_that_do_not_fit_are_rejected() {
+        let mut vip = Vip::new();
+        assert_eq!(
+            vip.try_load_interpreter(&[0; MEMORY_SIZE + 1]),
+            Err(InterpreterTooLarge {
+                len: MEMORY_SIZE + 1
+            })
+        );
+        assert!(vip.try_load_interpreter(&[0; MEMORY_SIZE]).is_ok());
+        assert_eq!(
+            vip.try_load_rom_bytes(&[0; 0xE01]),
+            Err(RomTooLarge { len: 0xE01 })
+        );
+        assert!(vip.try_load_rom_bytes(&[0; 0xE00]).is_ok());
+    }
+
+    #[test]
+    fn monitor_is_mirrored_at_zero_after_reset() {
+        let mut vip = Vip::new();
+        vip.load_interpreter(&[0x7B, 0x30, 0x01]); // SEQ; BR 01
+                                                   // LBR 8003; then at 8003 jump into RAM with LBR 0000
+        vip.load_monitor(&[0xC0, 0x80, 0x03, 0xC0, 0x00, 0x00]);
+        vip.run_frame();
+        assert!(vip.sound_active());
+        assert_eq!(vip.cpu().pc(), 0x0001);
+    }
+}
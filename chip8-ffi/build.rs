@@ -0,0 +1,11 @@
+// Regenerates include/chip8.h, which is checked in for C users who never run cargo
+fn main() {
+    let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    let config = cbindgen::Config::from_file(format!("{}/cbindgen.toml", crate_dir))
+        .expect("Failed to read cbindgen.toml");
+    cbindgen::generate_with_config(&crate_dir, config)
+        .expect("Failed to generate the C header")
+        .write_to_file(format!("{}/include/chip8.h", crate_dir));
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+}
@@ -0,0 +1,86 @@
+#ifndef CHIP8_H
+#define CHIP8_H
+
+/* Generated by cbindgen from chip8-ffi/src/lib.rs; edit that instead. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Result of every call that can fail
+typedef enum Chip8Status {
+  CHIP8_STATUS_OK = 0,
+  // A required pointer was NULL
+  CHIP8_STATUS_NULL_POINTER = 1,
+  // The ROM does not fit in memory above 0x200
+  CHIP8_STATUS_ROM_TOO_LARGE = 2,
+  // Keys are 0 to 15
+  CHIP8_STATUS_INVALID_KEY = 3,
+  // The buffer is smaller than `chip8_state_size`
+  CHIP8_STATUS_BUFFER_TOO_SMALL = 4,
+  // The buffer does not hold a save state this version can load
+  CHIP8_STATUS_BAD_STATE = 5,
+} Chip8Status;
+
+// An emulated machine. Only ever handled through a pointer from `chip8_new`.
+typedef struct Chip8 Chip8;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Create a machine with no ROM loaded. `seed` drives the random numbers of CXNN.
+// Free it with `chip8_free`.
+struct Chip8 *chip8_new(uint64_t seed);
+
+// Free a machine from `chip8_new`. NULL is ignored.
+void chip8_free(struct Chip8 *chip8);
+
+// Reset the machine, keeping its seed, and load the `len` bytes at `rom`
+// at 0x200. A ROM that does not fit leaves the machine as it was.
+enum Chip8Status chip8_load_rom(struct Chip8 *chip8, const uint8_t *rom, size_t len);
+
+// Execute the instruction at the program counter, leaving the timers alone
+enum Chip8Status chip8_step(struct Chip8 *chip8);
+
+// Run one 60 Hz frame: a frame's worth of instructions, then a timer tick
+enum Chip8Status chip8_run_frame(struct Chip8 *chip8);
+
+// The screen, `chip8_framebuffer_width` by `chip8_framebuffer_height` bytes
+// row by row, each 0 for a dark pixel and nonzero for a lit one. Valid until
+// the machine is freed; NULL if `chip8` is NULL.
+const uint8_t *chip8_framebuffer(const struct Chip8 *chip8);
+
+size_t chip8_framebuffer_width(void);
+
+size_t chip8_framebuffer_height(void);
+
+// Press or release keypad key 0 to 15
+enum Chip8Status chip8_set_key(struct Chip8 *chip8, uint8_t key, bool pressed);
+
+// Whether the beeper is sounding; false if `chip8` is NULL
+bool chip8_sound_active(const struct Chip8 *chip8);
+
+// Bytes needed to save the machine's current state; 0 if `chip8` is NULL
+size_t chip8_state_size(const struct Chip8 *chip8);
+
+// Save the machine's state into the `len` bytes at `buffer`, storing the
+// number of bytes used in `written` unless it is NULL
+enum Chip8Status chip8_save_state(const struct Chip8 *chip8,
+                                  uint8_t *buffer,
+                                  size_t len,
+                                  size_t *written);
+
+// Restore a state from `chip8_save_state`. On failure the machine is left as it was.
+enum Chip8Status chip8_load_state(struct Chip8 *chip8, const uint8_t *buffer, size_t len);
+
+// A static, NUL-terminated description of `status`
+const char *chip8_status_message(enum Chip8Status status);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* CHIP8_H */
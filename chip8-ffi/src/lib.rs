@@ -0,0 +1,262 @@
+//! C interface to the emulator, built as `libchip8_ffi` (shared and static).
+//! `include/chip8.h` is generated from this file by the build script.
+
+// Every entry point checks its pointers for null; the rest of what the
+// caller must uphold is in the header's comments
+#![allow(clippy::missing_safety_doc)]
+
+use std::ffi::c_char;
+use std::slice;
+
+use chip8_core::display::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use chip8_core::emu::Chip8 as Core;
+#[cfg(test)]
+use chip8_core::emu::MAX_ROM_SIZE;
+
+/// Result of every call that can fail
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chip8Status {
+    Ok = 0,
+    /// A required pointer was NULL
+    NullPointer = 1,
+    /// The ROM does not fit in memory above 0x200
+    RomTooLarge = 2,
+    /// Keys are 0 to 15
+    InvalidKey = 3,
+    /// The buffer is smaller than `chip8_state_size`
+    BufferTooSmall = 4,
+    /// The buffer does not hold a save state this version can load
+    BadState = 5,
+}
+
+/// An emulated machine. Only ever handled through a pointer from `chip8_new`.
+pub struct Chip8 {
+    core: Core,
+    // The display row by row, one byte per pixel, refreshed after each call
+    // that can draw
+    framebuffer: Vec<u8>,
+}
+
+impl Chip8 {
+    fn refresh_framebuffer(&mut self) {
+        let display = self.core.display();
+        for (n, pixel) in self.framebuffer.iter_mut().enumerate() {
+            *pixel = display.get_pixel(n % DISPLAY_WIDTH, n / DISPLAY_WIDTH);
+        }
+    }
+}
+
+/// Create a machine with no ROM loaded. `seed` drives the random numbers of CXNN.
+/// Free it with `chip8_free`.
+#[no_mangle]
+pub extern "C" fn chip8_new(seed: u64) -> *mut Chip8 {
+    let mut core = Core::new();
+    core.set_seed(seed);
+    Box::into_raw(Box::new(Chip8 {
+        core,
+        framebuffer: vec![0; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+    }))
+}
+
+/// Free a machine from `chip8_new`. NULL is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_free(chip8: *mut Chip8) {
+    if !chip8.is_null() {
+        drop(Box::from_raw(chip8));
+    }
+}
+
+/// Reset the machine, keeping its seed, and load the `len` bytes at `rom`
+/// at 0x200. A ROM that does not fit leaves the machine as it was.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_rom(
+    chip8: *mut Chip8,
+    rom: *const u8,
+    len: usize,
+) -> Chip8Status {
+    let (Some(chip8), false) = (chip8.as_mut(), rom.is_null() && len > 0) else {
+        return Chip8Status::NullPointer;
+    };
+    let rom = if len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(rom, len)
+    };
+    let mut core = chip8.core.clone();
+    core.reset();
+    if core.try_load_rom_bytes(rom).is_err() {
+        return Chip8Status::RomTooLarge;
+    }
+    chip8.core = core;
+    chip8.refresh_framebuffer();
+    Chip8Status::Ok
+}
+
+/// Execute the instruction at the program counter, leaving the timers alone
+#[no_mangle]
+pub unsafe extern "C" fn chip8_step(chip8: *mut Chip8) -> Chip8Status {
+    let Some(chip8) = chip8.as_mut() else {
+        return Chip8Status::NullPointer;
+    };
+    chip8.core.emulate_cycle();
+    chip8.refresh_framebuffer();
+    Chip8Status::Ok
+}
+
+/// Run one 60 Hz frame: a frame's worth of instructions, then a timer tick
+#[no_mangle]
+pub unsafe extern "C" fn chip8_run_frame(chip8: *mut Chip8) -> Chip8Status {
+    let Some(chip8) = chip8.as_mut() else {
+        return Chip8Status::NullPointer;
+    };
+    chip8.core.run_frame();
+    chip8.refresh_framebuffer();
+    Chip8Status::Ok
+}
+
+/// The screen, `chip8_framebuffer_width` by `chip8_framebuffer_height` bytes
+/// row by row, each 0 for a dark pixel and nonzero for a lit one. Valid until
+/// the machine is freed; NULL if `chip8` is NULL.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_framebuffer(chip8: *const Chip8) -> *const u8 {
+    match chip8.as_ref() {
+        Some(chip8) => chip8.framebuffer.as_ptr(),
+        None => std::ptr::null(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn chip8_framebuffer_width() -> usize {
+    DISPLAY_WIDTH
+}
+
+#[no_mangle]
+pub extern "C" fn chip8_framebuffer_height() -> usize {
+    DISPLAY_HEIGHT
+}
+
+/// Press or release keypad key 0 to 15
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_key(chip8: *mut Chip8, key: u8, pressed: bool) -> Chip8Status {
+    let Some(chip8) = chip8.as_mut() else {
+        return Chip8Status::NullPointer;
+    };
+    if key > 0xF {
+        return Chip8Status::InvalidKey;
+    }
+    chip8.core.key_mut().set_key(key as usize, pressed);
+    Chip8Status::Ok
+}
+
+/// Whether the beeper is sounding; false if `chip8` is NULL
+#[no_mangle]
+pub unsafe extern "C" fn chip8_sound_active(chip8: *const Chip8) -> bool {
+    chip8
+        .as_ref()
+        .is_some_and(|chip8| chip8.core.sound_active())
+}
+
+/// Bytes needed to save the machine's current state; 0 if `chip8` is NULL
+#[no_mangle]
+pub unsafe extern "C" fn chip8_state_size(chip8: *const Chip8) -> usize {
+    chip8
+        .as_ref()
+        .map_or(0, |chip8| chip8.core.save_state().len())
+}
+
+/// Save the machine's state into the `len` bytes at `buffer`, storing the
+/// number of bytes used in `written` unless it is NULL
+#[no_mangle]
+pub unsafe extern "C" fn chip8_save_state(
+    chip8: *const Chip8,
+    buffer: *mut u8,
+    len: usize,
+    written: *mut usize,
+) -> Chip8Status {
+    let (Some(chip8), false) = (chip8.as_ref(), buffer.is_null()) else {
+        return Chip8Status::NullPointer;
+    };
+    let state = chip8.core.save_state();
+    if state.len() > len {
+        return Chip8Status::BufferTooSmall;
+    }
+    slice::from_raw_parts_mut(buffer, state.len()).copy_from_slice(&state);
+    if let Some(written) = written.as_mut() {
+        *written = state.len();
+    }
+    Chip8Status::Ok
+}
+
+/// Restore a state from `chip8_save_state`. On failure the machine is left as it was.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_state(
+    chip8: *mut Chip8,
+    buffer: *const u8,
+    len: usize,
+) -> Chip8Status {
+    let (Some(chip8), false) = (chip8.as_mut(), buffer.is_null()) else {
+        return Chip8Status::NullPointer;
+    };
+    if chip8
+        .core
+        .load_state(slice::from_raw_parts(buffer, len))
+        .is_err()
+    {
+        return Chip8Status::BadState;
+    }
+    chip8.refresh_framebuffer();
+    Chip8Status::Ok
+}
+
+/// A static, NUL-terminated description of `status`
+#[no_mangle]
+pub extern "C" fn chip8_status_message(status: Chip8Status) -> *const c_char {
+    let message = match status {
+        Chip8Status::Ok => c"ok",
+        Chip8Status::NullPointer => c"a required pointer was NULL",
+        Chip8Status::RomTooLarge => c"ROM does not fit in memory",
+        Chip8Status::InvalidKey => c"key out of range 0 to 15",
+        Chip8Status::BufferTooSmall => c"buffer too small for the save state",
+        Chip8Status::BadState => c"not a save state this version can load",
+    };
+    message.as_ptr()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_bad_arguments() {
+        unsafe {
+            let chip8 = chip8_new(1);
+            let big = vec![0; MAX_ROM_SIZE + 1];
+            assert_eq!(
+                chip8_load_rom(chip8, big.as_ptr(), big.len()),
+                Chip8Status::RomTooLarge
+            );
+            assert_eq!(
+                chip8_load_rom(chip8, std::ptr::null(), 4),
+                Chip8Status::NullPointer
+            );
+            assert_eq!(chip8_load_rom(chip8, std::ptr::null(), 0), Chip8Status::Ok);
+            assert_eq!(chip8_set_key(chip8, 16, true), Chip8Status::InvalidKey);
+            assert_eq!(
+                chip8_run_frame(std::ptr::null_mut()),
+                Chip8Status::NullPointer
+            );
+
+            let mut small = [0; 8];
+            let status =
+                chip8_save_state(chip8, small.as_mut_ptr(), small.len(), std::ptr::null_mut());
+            assert_eq!(status, Chip8Status::BufferTooSmall);
+            assert_eq!(
+                chip8_load_state(chip8, small.as_ptr(), small.len()),
+                Chip8Status::BadState
+            );
+            chip8_free(chip8);
+            chip8_free(std::ptr::null_mut());
+        }
+    }
+}
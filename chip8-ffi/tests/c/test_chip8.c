@@ -0,0 +1,74 @@
+/* Exercises the C interface the way embedding code would. Built and run by
+ * tests/c_api.rs, which passes the path of roms/test/draw-row.ch8: it draws
+ * a row of four pixels at the top left, sounds the beeper, then loops. */
+#include <assert.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "chip8.h"
+
+/* Stores past the end of memory, then runs into whatever follows */
+static const uint8_t OFF_THE_END[] = {
+    0xAF, 0xFF, /* LD I, 0xFFF */
+    0xF1, 0x55, /* LD [I], V1 */
+};
+
+int main(int argc, char **argv) {
+    assert(argc == 2);
+    uint8_t rom[4096];
+    FILE *file = fopen(argv[1], "rb");
+    assert(file != NULL);
+    size_t rom_len = fread(rom, 1, sizeof rom, file);
+    fclose(file);
+    assert(rom_len > 0);
+
+    Chip8 *chip8 = chip8_new(42);
+    assert(chip8 != NULL);
+    assert(chip8_load_rom(chip8, rom, rom_len) == CHIP8_STATUS_OK);
+
+    size_t width = chip8_framebuffer_width();
+    assert(width == 64 && chip8_framebuffer_height() == 32);
+
+    /* One instruction at a time: nothing drawn until DRW runs */
+    assert(chip8_step(chip8) == CHIP8_STATUS_OK);
+    assert(chip8_framebuffer(chip8)[0] == 0);
+    assert(chip8_step(chip8) == CHIP8_STATUS_OK);
+    const uint8_t *pixels = chip8_framebuffer(chip8);
+    assert(pixels[0] && pixels[3] && !pixels[4] && !pixels[width]);
+
+    assert(chip8_run_frame(chip8) == CHIP8_STATUS_OK);
+    assert(chip8_sound_active(chip8));
+
+    /* Save, change the machine, and restore it */
+    uint8_t state[8192];
+    size_t size = chip8_state_size(chip8);
+    size_t written = 0;
+    assert(size > 0 && size <= sizeof state);
+    assert(chip8_save_state(chip8, state, sizeof state, &written) == CHIP8_STATUS_OK);
+    assert(written == size);
+    assert(chip8_save_state(chip8, state, 4, NULL) == CHIP8_STATUS_BUFFER_TOO_SMALL);
+
+    assert(chip8_load_rom(chip8, rom, 0) == CHIP8_STATUS_OK);
+    assert(chip8_framebuffer(chip8)[0] == 0);
+    assert(chip8_load_state(chip8, state, written) == CHIP8_STATUS_OK);
+    assert(chip8_framebuffer(chip8)[0] != 0);
+    assert(chip8_load_state(chip8, (const uint8_t *)"junk", 4) == CHIP8_STATUS_BAD_STATE);
+
+    /* Errors come back as codes with a message */
+    assert(chip8_set_key(chip8, 0xA, true) == CHIP8_STATUS_OK);
+    assert(chip8_set_key(chip8, 0xA, false) == CHIP8_STATUS_OK);
+    Chip8Status status = chip8_set_key(chip8, 16, true);
+    assert(status == CHIP8_STATUS_INVALID_KEY);
+    assert(strlen(chip8_status_message(status)) > 0);
+    assert(chip8_run_frame(NULL) == CHIP8_STATUS_NULL_POINTER);
+
+    /* Addresses wrap around instead of bringing the host down */
+    assert(chip8_load_rom(chip8, OFF_THE_END, sizeof OFF_THE_END) == CHIP8_STATUS_OK);
+    for (int frame = 0; frame < 600; frame++) {
+        assert(chip8_run_frame(chip8) == CHIP8_STATUS_OK);
+    }
+
+    chip8_free(chip8);
+    puts("ok");
+    return 0;
+}
@@ -0,0 +1,42 @@
+//! Compiles tests/c/test_chip8.c against the header and the shared library
+//! built for this test run, and runs it.
+
+use std::path::PathBuf;
+use std::process::Command;
+
+#[test]
+fn c_program_uses_the_library() {
+    let crate_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    // The test binary sits in target/<profile>/deps, the library one level up
+    let exe = std::env::current_exe().unwrap();
+    let lib_dir = exe.parent().unwrap().parent().unwrap();
+    let program = std::env::temp_dir().join(format!("chip8-ffi-test-{}", std::process::id()));
+
+    let compiler = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let status = Command::new(&compiler)
+        .arg(crate_dir.join("tests/c/test_chip8.c"))
+        .arg("-std=c99")
+        .arg("-Wall")
+        .arg("-Werror")
+        .arg("-I")
+        .arg(crate_dir.join("include"))
+        .arg("-L")
+        .arg(lib_dir)
+        .arg(format!("-Wl,-rpath,{}", lib_dir.display()))
+        .arg("-lchip8_ffi")
+        .arg("-o")
+        .arg(&program)
+        .status()
+        .unwrap_or_else(|e| panic!("Failed to run {}: {}", compiler, e));
+    assert!(status.success(), "the C test program failed to build");
+
+    let rom = crate_dir.join("../roms/test/draw-row.ch8");
+    let output = Command::new(&program).arg(rom).output().unwrap();
+    let _ = std::fs::remove_file(&program);
+    assert!(
+        output.status.success(),
+        "the C test program failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "ok\n");
+}
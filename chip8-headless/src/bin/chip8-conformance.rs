@@ -0,0 +1,80 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use chip8_core::platform::Platform;
+use chip8_headless::conformance::SELF_CHECKING;
+
+const USAGE: &str = "usage: chip8-conformance [--roms DIR] [--platform vip|schip] [--all]
+
+Runs the self-checking test ROMs and prints the checks that do not come out
+as the platform should, or every check with --all. A SUPER-CHIP check is
+expected to fail where SUPER-CHIP differs from the original CHIP-8. Exits
+with 1 if any check is not as expected.";
+
+fn main() -> ExitCode {
+    let mut roms = PathBuf::from("roms");
+    let mut platforms = Platform::ALL.to_vec();
+    let mut show_all = false;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let parsed = match arg.as_str() {
+            "--roms" => args.next().map(|dir| roms = dir.into()).ok_or(()),
+            "--platform" => args
+                .next()
+                .and_then(|p| p.parse().ok())
+                .map(|p| platforms = vec![p])
+                .ok_or(()),
+            "--all" => {
+                show_all = true;
+                Ok(())
+            }
+            _ => Err(()),
+        };
+        if parsed.is_err() {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    }
+
+    let mut failures = 0;
+    for platform in platforms {
+        for test in SELF_CHECKING {
+            let path = roms.join(test.file);
+            let rom = match std::fs::read(&path) {
+                Ok(rom) => rom,
+                Err(e) => {
+                    eprintln!("Failed to read {}: {}", path.display(), e);
+                    return ExitCode::from(2);
+                }
+            };
+            let chip8 = test.run(&rom, platform);
+            let results = test.results(chip8.display(), platform);
+            let matching = results.iter().filter(|r| r.as_expected()).count();
+            println!(
+                "{} on {}: {}/{} as expected",
+                test.name,
+                platform,
+                matching,
+                results.len()
+            );
+            for result in results {
+                if !result.as_expected() {
+                    failures += 1;
+                    println!(
+                        "  {:<16} {:?}, expected {:?}",
+                        result.label, result.verdict, result.expected
+                    );
+                } else if show_all {
+                    println!("  {:<16} {:?}", result.label, result.verdict);
+                }
+            }
+        }
+    }
+
+    if failures > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
@@ -0,0 +1,245 @@
+//! Running the bundled Timendus test ROMs and reading their verdicts back off the screen.
+//!
+//! The test ROMs report each check as a small tick or cross glyph next to a
+//! label. The tables below record where those marks are drawn so a run can be
+//! turned into a list of named pass/fail results.
+
+use chip8_core::display::Display;
+use chip8_core::emu::Chip8;
+use chip8_core::platform::Platform;
+
+use crate::{run, RunConfig, StopCondition};
+
+/// Memory address the Timendus ROMs read to skip their menus
+pub const AUTOSTART_ADDRESS: usize = 0x1FF;
+
+// 3x4 mark glyphs, one string per row
+const TICK: [&str; 4] = ["...", "#.#", "##.", "#.."];
+const CROSS: [&str; 4] = ["...", "#.#", ".#.", "#.#"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Pass,
+    Fail,
+    /// Neither a tick nor a cross is drawn where the mark should be
+    Missing,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckResult {
+    pub label: String,
+    pub verdict: Verdict,
+    /// What a correct emulation of the platform gets
+    pub expected: Verdict,
+}
+
+impl CheckResult {
+    /// Whether the platform behaved as it should, passing or failing
+    pub fn as_expected(&self) -> bool {
+        self.verdict == self.expected
+    }
+}
+
+/// One labelled group of marks: `count` marks, 4 pixels apart, starting at (`x`, `y`)
+struct MarkGroup {
+    label: &'static str,
+    x: usize,
+    y: usize,
+    count: usize,
+    /// What SUPER-CHIP gets; it passes everything the original CHIP-8 does
+    /// unless it is known to behave differently
+    superchip: Verdict,
+}
+
+const fn group(label: &'static str, x: usize, y: usize, count: usize) -> MarkGroup {
+    MarkGroup {
+        label,
+        x,
+        y,
+        count,
+        superchip: Verdict::Pass,
+    }
+}
+
+/// A check of original CHIP-8 behaviour that SUPER-CHIP does not share
+const fn vip_only(label: &'static str, x: usize, y: usize) -> MarkGroup {
+    MarkGroup {
+        superchip: Verdict::Fail,
+        ..group(label, x, y, 1)
+    }
+}
+
+/// A bundled test ROM and how to run it to completion
+pub struct TestRom {
+    pub name: &'static str,
+    pub file: &'static str,
+    /// Value written to `AUTOSTART_ADDRESS` to pick a test without the menu
+    pub autostart: Option<u8>,
+    /// Instructions per frame if the ROM needs something other than the default
+    pub cycles_per_frame: Option<usize>,
+    pub max_frames: u64,
+    pub stop_on_self_jump: bool,
+    marks: &'static [MarkGroup],
+}
+
+// Four columns of six opcodes, each followed by a single mark
+const CORAX_MARKS: &[MarkGroup] = &[
+    group("3XNN", 11, 1, 1),
+    group("4XNN", 11, 6, 1),
+    group("5XY0", 11, 11, 1),
+    group("7XNN", 11, 16, 1),
+    group("9XY0", 11, 21, 1),
+    group("1NNN", 11, 26, 1),
+    group("2NNN", 27, 1, 1),
+    group("00EE", 27, 6, 1),
+    group("8XY0", 27, 11, 1),
+    group("8XY1", 27, 16, 1),
+    group("8XY2", 27, 21, 1),
+    group("8XY3", 27, 26, 1),
+    group("8XY4", 43, 1, 1),
+    group("8XY5", 43, 6, 1),
+    group("8XY7", 43, 11, 1),
+    group("8XY6", 43, 16, 1),
+    group("8XYE", 43, 21, 1),
+    group("FX65", 43, 26, 1),
+    group("FX55", 59, 1, 1),
+    group("FX33", 59, 6, 1),
+    group("FX1E", 59, 11, 1),
+    group("vX", 59, 16, 1),
+];
+
+// The "happy path" block checks results and flags without overflow, the
+// "carry" block with it, "other" checks FX1E
+const FLAGS_MARKS: &[MarkGroup] = &[
+    group("happy 8XY1", 27, 0, 3),
+    group("happy 8XY2", 49, 0, 3),
+    group("happy 8XY3", 5, 5, 3),
+    group("happy 8XY4", 27, 5, 4),
+    group("happy 8XY5", 49, 5, 4),
+    group("happy 8XY6", 5, 10, 3),
+    group("happy 8XY7", 27, 10, 4),
+    group("happy 8XYE", 49, 10, 3),
+    group("carry 8XY4", 27, 16, 4),
+    group("carry 8XY5", 49, 16, 4),
+    group("carry 8XY6", 5, 21, 3),
+    group("carry 8XY7", 27, 21, 4),
+    group("carry 8XYE", 49, 21, 3),
+    group("other FX1E", 31, 27, 2),
+];
+
+// Verdicts for the original CHIP-8 target of the quirks ROM. Its SUPER-CHIP
+// targets need the high resolution mode, so SUPER-CHIP is graded against the
+// CHIP-8 target too, where all but clipping are known to differ.
+const QUIRKS_MARKS: &[MarkGroup] = &[
+    vip_only("vF reset", 59, 1),
+    vip_only("memory", 59, 6),
+    vip_only("display wait", 59, 11),
+    group("clipping", 59, 16, 1),
+    vip_only("shifting", 59, 21),
+    vip_only("jumping", 59, 26),
+];
+
+pub const CORAX: TestRom = TestRom {
+    name: "corax+",
+    file: "3-corax+.ch8",
+    autostart: None,
+    cycles_per_frame: None,
+    max_frames: 600,
+    stop_on_self_jump: true,
+    marks: CORAX_MARKS,
+};
+
+pub const FLAGS: TestRom = TestRom {
+    name: "flags",
+    file: "4-flags.ch8",
+    autostart: None,
+    cycles_per_frame: None,
+    max_frames: 600,
+    stop_on_self_jump: true,
+    marks: FLAGS_MARKS,
+};
+
+/// The quirks ROM loops forever, so it is sampled after a fixed number of frames.
+/// Below 10 instructions per frame it cannot measure the display wait and
+/// reports "SLOW" instead.
+pub const QUIRKS: TestRom = TestRom {
+    name: "quirks",
+    file: "5-quirks.ch8",
+    autostart: Some(1),
+    cycles_per_frame: Some(15),
+    max_frames: 400,
+    stop_on_self_jump: false,
+    marks: QUIRKS_MARKS,
+};
+
+/// The test ROMs that grade themselves on screen
+pub const SELF_CHECKING: [TestRom; 3] = [CORAX, FLAGS, QUIRKS];
+
+impl TestRom {
+    /// Boot the ROM under `platform` with its autostart byte in place
+    pub fn boot(&self, rom: &[u8], platform: Platform) -> Chip8 {
+        let mut chip8 = Chip8::new();
+        chip8.set_platform(platform);
+        if let Some(cycles) = self.cycles_per_frame {
+            chip8.set_cycles_per_frame(cycles);
+        }
+        chip8.load_rom_bytes(rom);
+        if let Some(test) = self.autostart {
+            chip8.memory_mut().set_byte(AUTOSTART_ADDRESS, test);
+        }
+        chip8
+    }
+
+    /// Boot and run the ROM until it finishes
+    pub fn run(&self, rom: &[u8], platform: Platform) -> Chip8 {
+        let mut chip8 = self.boot(rom, platform);
+        let mut config = RunConfig::new(self.max_frames);
+        if self.stop_on_self_jump {
+            config = config.stop_when(StopCondition::SelfJump);
+        }
+        run(&mut chip8, &config);
+        chip8
+    }
+
+    /// Read every check mark off the screen of a run under `platform`
+    pub fn results(&self, display: &Display, platform: Platform) -> Vec<CheckResult> {
+        let mut results = Vec::new();
+        for group in self.marks {
+            for n in 0..group.count {
+                let label = if group.count == 1 {
+                    group.label.to_string()
+                } else {
+                    format!("{} #{}", group.label, n + 1)
+                };
+                let expected = match platform {
+                    Platform::CosmacVip => Verdict::Pass,
+                    Platform::SuperChip => group.superchip,
+                };
+                results.push(CheckResult {
+                    label,
+                    verdict: read_mark(display, group.x + 4 * n, group.y),
+                    expected,
+                });
+            }
+        }
+        results
+    }
+}
+
+fn read_mark(display: &Display, x: usize, y: usize) -> Verdict {
+    let matches = |glyph: &[&str; 4]| {
+        glyph.iter().enumerate().all(|(row, pattern)| {
+            pattern
+                .bytes()
+                .enumerate()
+                .all(|(col, c)| (display.get_pixel(x + col, y + row) != 0) == (c == b'#'))
+        })
+    };
+    if matches(&TICK) {
+        Verdict::Pass
+    } else if matches(&CROSS) {
+        Verdict::Fail
+    } else {
+        Verdict::Missing
+    }
+}
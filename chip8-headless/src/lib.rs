@@ -0,0 +1,362 @@
+pub mod conformance;
+
+use std::fmt;
+use std::str::FromStr;
+
+use chip8_core::display::{Display, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use chip8_core::emu::Chip8;
+use chip8_core::machine::Machine;
+use chip8_core::memory::MEMORY_SIZE;
+use chip8_core::replay::InputEvent;
+use chip8_core::vip::Vip;
+use serde_json::json;
+
+/// Ends a headless run as soon as it holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopCondition {
+    /// The program counter reaches the address
+    PcReaches(u16),
+    /// The next instruction is a `1NNN` jump to itself, the usual way
+    /// test ROMs signal they are done
+    SelfJump,
+    /// The byte at `address` holds `value`
+    MemoryEquals { address: u16, value: u8 },
+}
+
+impl StopCondition {
+    pub fn is_met(&self, chip8: &Chip8) -> bool {
+        match *self {
+            StopCondition::PcReaches(address) => chip8.pc() == address,
+            StopCondition::SelfJump => chip8.current_opcode() == 0x1000 | chip8.pc(),
+            StopCondition::MemoryEquals { address, value } => {
+                chip8.memory().get_byte(address as usize) == value
+            }
+        }
+    }
+}
+
+impl fmt::Display for StopCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopCondition::PcReaches(address) => write!(f, "pc={:03X}", address),
+            StopCondition::SelfJump => write!(f, "self-jump"),
+            StopCondition::MemoryEquals { address, value } => {
+                write!(f, "mem[{:03X}]={:02X}", address, value)
+            }
+        }
+    }
+}
+
+impl FromStr for StopCondition {
+    type Err = String;
+
+    /// Parses `pc=ADDR`, `self-jump` or `mem[ADDR]=VALUE`, numbers in hex
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = |v: &str| {
+            u16::from_str_radix(v.trim_start_matches("0x"), 16)
+                .map_err(|_| format!("bad hex number '{}' in '{}'", v, s))
+        };
+        if s == "self-jump" {
+            Ok(StopCondition::SelfJump)
+        } else if let Some(address) = s.strip_prefix("pc=") {
+            Ok(StopCondition::PcReaches(hex(address)?))
+        } else if let Some((address, value)) = s
+            .strip_prefix("mem[")
+            .and_then(|rest| rest.split_once("]="))
+        {
+            let value = hex(value)?;
+            let address = hex(address)?;
+            if address as usize >= MEMORY_SIZE {
+                return Err(format!("address out of range in '{}'", s));
+            }
+            Ok(StopCondition::MemoryEquals {
+                address,
+                value: u8::try_from(value).map_err(|_| format!("value out of range in '{}'", s))?,
+            })
+        } else {
+            Err(format!("unknown stop condition '{}'", s))
+        }
+    }
+}
+
+/// How a headless run ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunOutcome {
+    /// A stop condition held during the given frame
+    Stopped {
+        condition: StopCondition,
+        frame: u64,
+    },
+    /// All frames ran without any stop condition holding
+    FrameLimit,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RunConfig {
+    pub max_frames: u64,
+    pub conditions: Vec<StopCondition>,
+    /// Key transitions to feed in, each applied before its frame runs
+    pub input: Vec<InputEvent>,
+}
+
+impl RunConfig {
+    pub fn new(max_frames: u64) -> Self {
+        RunConfig {
+            max_frames,
+            ..Default::default()
+        }
+    }
+
+    pub fn stop_when(mut self, condition: StopCondition) -> Self {
+        self.conditions.push(condition);
+        self
+    }
+
+    /// Press `key` before frame `frame` and release it `hold` frames later
+    pub fn press(mut self, frame: u64, key: u8, hold: u64) -> Self {
+        self.input.push(InputEvent {
+            frame,
+            key,
+            pressed: true,
+        });
+        self.input.push(InputEvent {
+            frame: frame + hold,
+            key,
+            pressed: false,
+        });
+        self
+    }
+}
+
+/// Run `chip8` until a stop condition holds or `max_frames` frames have passed
+pub fn run(chip8: &mut Chip8, config: &RunConfig) -> RunOutcome {
+    run_observed(chip8, config, |_| {})
+}
+
+/// Like `run`, but hands the machine to `observe` after every completed frame
+pub fn run_observed(
+    chip8: &mut Chip8,
+    config: &RunConfig,
+    mut observe: impl FnMut(&Chip8),
+) -> RunOutcome {
+    let start = chip8.frame();
+    while chip8.frame() - start < config.max_frames {
+        let frame = chip8.frame() - start;
+        apply_input(chip8, config, frame);
+
+        let mut met = None;
+        chip8.run_frame_until(|chip8| {
+            met = config.conditions.iter().find(|c| c.is_met(chip8)).copied();
+            met.is_some()
+        });
+        if let Some(condition) = met {
+            return RunOutcome::Stopped { condition, frame };
+        }
+        observe(chip8);
+    }
+    RunOutcome::FrameLimit
+}
+
+/// Run any `Machine` for `config.max_frames` frames with the scripted keys.
+/// Stop conditions look at CHIP-8 registers and memory, so they are not
+/// checked here.
+pub fn run_machine<M: Machine>(machine: &mut M, config: &RunConfig, mut observe: impl FnMut(&M)) {
+    let start = machine.frame();
+    while machine.frame() - start < config.max_frames {
+        apply_input(machine, config, machine.frame() - start);
+        machine.run_frame();
+        observe(machine);
+    }
+}
+
+/// Run two machines side by side on the same keys, returning the frames
+/// (counted from the start of the run) after which their screens differed
+pub fn compare(a: &mut impl Machine, b: &mut impl Machine, config: &RunConfig) -> Vec<u64> {
+    let mut differing = Vec::new();
+    for frame in 0..config.max_frames {
+        apply_input(a, config, frame);
+        apply_input(b, config, frame);
+        a.run_frame();
+        b.run_frame();
+        if a.display() != b.display() {
+            differing.push(frame);
+        }
+    }
+    differing
+}
+
+fn apply_input(machine: &mut (impl Machine + ?Sized), config: &RunConfig, frame: u64) {
+    for event in config.input.iter().filter(|e| e.frame == frame) {
+        if event.pressed {
+            machine.key_mut().press_key(event.key as usize);
+        } else {
+            machine.key_mut().release_key(event.key as usize);
+        }
+    }
+}
+
+/// Render the framebuffer as rows of `#` (lit) and `.` (dark)
+pub fn screen_text(display: &Display) -> String {
+    let mut out = String::with_capacity((DISPLAY_WIDTH + 1) * DISPLAY_HEIGHT);
+    for y in 0..DISPLAY_HEIGHT {
+        for x in 0..DISPLAY_WIDTH {
+            out.push(if display.get_pixel(x, y) != 0 {
+                '#'
+            } else {
+                '.'
+            });
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Render the framebuffer as a binary PBM image, one image pixel per CHIP-8 pixel
+pub fn screen_pbm(display: &Display) -> Vec<u8> {
+    let mut out = format!("P4\n{} {}\n", DISPLAY_WIDTH, DISPLAY_HEIGHT).into_bytes();
+    for y in 0..DISPLAY_HEIGHT {
+        for byte in 0..DISPLAY_WIDTH / 8 {
+            let bits = (0..8).fold(0u8, |bits, bit| {
+                bits | ((display.get_pixel(byte * 8 + bit, y) != 0) as u8) << (7 - bit)
+            });
+            out.push(bits);
+        }
+    }
+    out
+}
+
+/// Two framebuffers as text, next to each other
+pub fn screens_side_by_side(left: &Display, right: &Display) -> String {
+    let (left, right) = (screen_text(left), screen_text(right));
+    left.lines()
+        .zip(right.lines())
+        .map(|(l, r)| format!("{} | {}\n", l, r))
+        .collect()
+}
+
+/// Dump the CPU state as JSON
+pub fn registers_json(chip8: &Chip8) -> serde_json::Value {
+    json!({
+        "v": chip8.registers(),
+        "i": chip8.i(),
+        "pc": chip8.pc(),
+        "stack": chip8.stack(),
+        "delay_timer": chip8.delay_timer(),
+        "sound_timer": chip8.sound_timer(),
+        "frame": chip8.frame(),
+    })
+}
+
+/// Dump the CDP1802's state as JSON
+pub fn vip_registers_json(vip: &Vip) -> serde_json::Value {
+    let cpu = vip.cpu();
+    json!({
+        "r": cpu.r(),
+        "p": cpu.p(),
+        "x": cpu.x(),
+        "d": cpu.d(),
+        "df": cpu.df(),
+        "q": cpu.q(),
+        "ie": cpu.ie(),
+        "frame": vip.frame(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stops_on_self_jump() {
+        let mut chip8 = Chip8::new();
+        // 6005 - LD V0, 5; 7001 - ADD V0, 1; 1204 - JP 0x204
+        chip8.load_rom_bytes(&[0x60, 0x05, 0x70, 0x01, 0x12, 0x04]);
+        let outcome = run(
+            &mut chip8,
+            &RunConfig::new(10).stop_when(StopCondition::SelfJump),
+        );
+        assert_eq!(
+            outcome,
+            RunOutcome::Stopped {
+                condition: StopCondition::SelfJump,
+                frame: 0
+            }
+        );
+        assert_eq!(chip8.pc(), 0x204);
+        assert_eq!(chip8.registers()[0], 6);
+    }
+
+    #[test]
+    fn scripted_keys_reach_the_program() {
+        let mut chip8 = Chip8::new();
+        // F00A - LD V0, K; A300 - LD I, 0x300; F055 - LD [I], V0; 1208 - JP 0x208
+        chip8.load_rom_bytes(&[0xF0, 0x0A, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x08]);
+        chip8.memory_mut().set_byte(0x300, 0xFF);
+        let config = RunConfig::new(60)
+            .press(5, 0x7, 2)
+            .stop_when(StopCondition::MemoryEquals {
+                address: 0x300,
+                value: 0x07,
+            });
+        assert!(matches!(
+            run(&mut chip8, &config),
+            RunOutcome::Stopped { .. }
+        ));
+    }
+
+    #[test]
+    fn compare_reports_frames_whose_screens_differ() {
+        // 00E0 - CLS; A20A - LD I, 0x20A; D011 - DRW V0, V0, 1; 1206 - JP 0x206;
+        // the sprite row is the top bit of 0x20A
+        let rom = [
+            0x00, 0xE0, 0xA2, 0x0A, 0xD0, 0x01, 0x12, 0x06, 0x00, 0x00, 0x80,
+        ];
+        let mut a = Chip8::new();
+        a.load_rom_bytes(&rom);
+        let mut b = Chip8::new();
+        b.load_rom_bytes(&rom);
+        assert!(compare(&mut a, &mut b, &RunConfig::new(3)).is_empty());
+
+        // Delaying the draw by a frame only shows in that frame
+        let mut late = Chip8::new();
+        late.set_cycles_per_frame(2);
+        late.load_rom_bytes(&rom);
+        let mut b = Chip8::new();
+        b.load_rom_bytes(&rom);
+        assert_eq!(compare(&mut late, &mut b, &RunConfig::new(3)), [0]);
+        let row = format!("#{}", ".".repeat(63));
+        let screens = screens_side_by_side(late.display(), b.display());
+        assert_eq!(
+            screens.lines().next(),
+            Some(format!("{} | {}", row, row).as_str())
+        );
+    }
+
+    #[test]
+    fn pbm_packs_eight_pixels_a_byte() {
+        let mut display = Display::new();
+        display.update_pixel(0, 0, 1);
+        display.update_pixel(9, 1, 1);
+        let pbm = screen_pbm(&display);
+        let header = b"P4\n64 32\n";
+        assert_eq!(&pbm[..header.len()], header);
+        let rows = &pbm[header.len()..];
+        assert_eq!(rows.len(), 8 * 32);
+        assert_eq!((rows[0], rows[8 + 1]), (0x80, 0x40));
+    }
+
+    #[test]
+    fn parse_stop_conditions() {
+        assert_eq!("self-jump".parse(), Ok(StopCondition::SelfJump));
+        assert_eq!("pc=0x3DC".parse(), Ok(StopCondition::PcReaches(0x3DC)));
+        assert_eq!(
+            "mem[1FF]=02".parse(),
+            Ok(StopCondition::MemoryEquals {
+                address: 0x1FF,
+                value: 2
+            })
+        );
+        assert!("mem[1FF]=100".parse::<StopCondition>().is_err());
+        assert!("mem[1000]=01".parse::<StopCondition>().is_err());
+    }
+}
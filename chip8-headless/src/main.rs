@@ -0,0 +1,404 @@
+use std::path::Path;
+use std::process::ExitCode;
+
+use chip8_core::capture::Capture;
+use chip8_core::display::MAX_SCALE;
+use chip8_core::emu::Chip8;
+use chip8_core::machine::Machine;
+use chip8_core::palette::Palette;
+use chip8_core::platform::Platform;
+use chip8_core::replay::InputRecording;
+use chip8_core::timing::Timing;
+use chip8_core::vip::Vip;
+use chip8_headless::{
+    compare, registers_json, run_machine, run_observed, screen_pbm, screen_text,
+    screens_side_by_side, vip_registers_json, RunConfig, RunOutcome,
+};
+
+const USAGE: &str = "usage: chip8-headless ROM [options]
+
+Runs ROM without a window and dumps the final machine state.
+
+  --frames N              stop after N frames (default 600, or the length of
+                            the --input recording)
+  --until COND            stop early when COND holds, may be repeated:
+                            pc=ADDR          the PC reaches ADDR
+                            self-jump        a 1NNN jumps to itself
+                            mem[ADDR]=VALUE  the byte at ADDR equals VALUE
+  --press FRAME:KEY[:N]   press KEY before FRAME and hold it N frames (default 1)
+  --poke ADDR=VALUE       write VALUE to memory at ADDR after loading the ROM
+  --input FILE            replay the keys and settings of an input recording
+  --platform vip|schip    platform to emulate (default vip)
+  --seed N                seed for the random number generator (default 0)
+  --cycles-per-frame N    instructions executed per frame with fixed timing
+  --timing fixed|vip      fixed instructions per frame, or the COSMAC VIP's
+                            machine cycle budget (default fixed)
+  --screen text|pbm|ppm|png
+                          framebuffer format (default text); pbm is one bit per
+                            CHIP-8 pixel and ignores --scale and --palette
+  --scale N               image pixels per CHIP-8 pixel for ppm and png, 1 to 1023
+                            (default 10)
+  --palette NAME          colours for ppm, png and captures: default, green-phosphor,
+                            amber, lcd, high-contrast or octo
+  --screen-out FILE       write the framebuffer to FILE instead of stdout
+  --regs-out FILE         write the registers as JSON to FILE instead of stdout;
+                            without it they are left out when a pbm, ppm or
+                            png screen goes to stdout
+  --capture BASE          record the run to BASE.gif, BASE.y4m and BASE.wav at --scale
+  --vip-interpreter FILE  emulate the COSMAC VIP hardware, running the CHIP-8
+                            interpreter image in FILE instead of interpreting
+                            CHIP-8 directly; --until and --input do not apply
+  --vip-monitor FILE      VIP monitor ROM to boot through, which the stock
+                            interpreter needs
+  --compare               run the ROM both ways and show the two final screens
+                            side by side
+
+Exits with 1 if stop conditions were given and none held within the frame limit,
+or if --compare found the screens differing in any frame.";
+
+enum ScreenFormat {
+    Text,
+    Pbm,
+    Ppm,
+    Png,
+}
+
+// Frames run when neither --frames nor an --input recording says otherwise
+const DEFAULT_FRAMES: u64 = 600;
+
+struct Options {
+    rom: String,
+    frames: Option<u64>,
+    config: RunConfig,
+    input: Option<String>,
+    platform: Platform,
+    seed: u64,
+    cycles_per_frame: Option<usize>,
+    timing: Option<Timing>,
+    pokes: Vec<(u16, u8)>,
+    screen: ScreenFormat,
+    scale: usize,
+    palette: Palette,
+    screen_out: Option<String>,
+    regs_out: Option<String>,
+    capture: Option<String>,
+    vip_interpreter: Option<String>,
+    vip_monitor: Option<String>,
+    compare: bool,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut args = std::env::args().skip(1);
+    let mut rom = None;
+    let mut options = Options {
+        rom: String::new(),
+        frames: None,
+        config: RunConfig::new(DEFAULT_FRAMES),
+        input: None,
+        platform: Platform::default(),
+        seed: 0,
+        cycles_per_frame: None,
+        timing: None,
+        pokes: Vec::new(),
+        screen: ScreenFormat::Text,
+        scale: 10,
+        palette: Palette::default(),
+        screen_out: None,
+        regs_out: None,
+        capture: None,
+        vip_interpreter: None,
+        vip_monitor: None,
+        compare: false,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--frames" => options.frames = Some(value()?.parse().map_err(|_| "bad frame count")?),
+            "--until" => options.config.conditions.push(value()?.parse()?),
+            "--press" => {
+                let spec = value()?;
+                let (frame, key, hold) =
+                    parse_press(&spec).ok_or(format!("bad key press '{}'", spec))?;
+                options.config = options.config.press(frame, key, hold);
+            }
+            "--poke" => {
+                let spec = value()?;
+                let poke = parse_poke(&spec).ok_or(format!("bad poke '{}'", spec))?;
+                options.pokes.push(poke);
+            }
+            "--input" => options.input = Some(value()?),
+            "--platform" => options.platform = value()?.parse()?,
+            "--seed" => options.seed = value()?.parse().map_err(|_| "bad seed")?,
+            "--cycles-per-frame" => {
+                options.cycles_per_frame = Some(value()?.parse().map_err(|_| "bad cycle count")?)
+            }
+            "--timing" => options.timing = Some(value()?.parse()?),
+            "--screen" => {
+                options.screen = match value()?.as_str() {
+                    "text" => ScreenFormat::Text,
+                    "pbm" => ScreenFormat::Pbm,
+                    "ppm" => ScreenFormat::Ppm,
+                    "png" => ScreenFormat::Png,
+                    other => return Err(format!("unknown screen format '{}'", other)),
+                }
+            }
+            "--scale" => {
+                options.scale = value()?
+                    .parse()
+                    .ok()
+                    .filter(|s| (1..=MAX_SCALE).contains(s))
+                    .ok_or(format!("--scale must be 1 to {}", MAX_SCALE))?
+            }
+            "--palette" => {
+                let name = value()?;
+                options.palette =
+                    Palette::builtin(&name).ok_or(format!("unknown palette '{}'", name))?
+            }
+            "--screen-out" => options.screen_out = Some(value()?),
+            "--regs-out" => options.regs_out = Some(value()?),
+            "--capture" => options.capture = Some(value()?),
+            "--vip-interpreter" => options.vip_interpreter = Some(value()?),
+            "--vip-monitor" => options.vip_monitor = Some(value()?),
+            "--compare" => options.compare = true,
+            "-h" | "--help" => return Err(String::new()),
+            _ if !arg.starts_with("--") && rom.is_none() => rom = Some(arg),
+            _ => return Err(format!("unexpected argument {}", arg)),
+        }
+    }
+    options.rom = rom.ok_or("missing ROM")?;
+    options.config.max_frames = options.frames.unwrap_or(DEFAULT_FRAMES);
+    if options.vip_interpreter.is_none() {
+        if options.vip_monitor.is_some() || options.compare {
+            return Err("--vip-monitor and --compare need --vip-interpreter".to_string());
+        }
+    } else if !options.config.conditions.is_empty() || options.input.is_some() {
+        return Err("--until and --input only work with the CHIP-8 interpreter".to_string());
+    }
+    Ok(options)
+}
+
+// FRAME:KEY[:HOLD] with KEY as a hex digit
+fn parse_press(spec: &str) -> Option<(u64, u8, u64)> {
+    let mut parts = spec.split(':');
+    let frame = parts.next()?.parse().ok()?;
+    let key = u8::from_str_radix(parts.next()?, 16)
+        .ok()
+        .filter(|&k| k <= 0x0F)?;
+    let hold = match parts.next() {
+        Some(hold) => hold.parse().ok()?,
+        None => 1,
+    };
+    parts.next().is_none().then_some((frame, key, hold))
+}
+
+// ADDR=VALUE, both in hex
+fn parse_poke(spec: &str) -> Option<(u16, u8)> {
+    let (address, value) = spec.split_once('=')?;
+    let address = u16::from_str_radix(address.trim_start_matches("0x"), 16).ok()?;
+    let value = u8::from_str_radix(value.trim_start_matches("0x"), 16).ok()?;
+    (address < 0x1000).then_some((address, value))
+}
+
+fn main() -> ExitCode {
+    let mut options = match parse_args() {
+        Ok(options) => options,
+        Err(e) => {
+            if !e.is_empty() {
+                eprintln!("{}\n", e);
+            }
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    let rom = match std::fs::read(&options.rom) {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", options.rom, e);
+            return ExitCode::from(2);
+        }
+    };
+
+    let mut chip8 = match &options.input {
+        Some(path) => {
+            let recording = match InputRecording::load(path) {
+                Ok(recording) => recording,
+                Err(e) => {
+                    eprintln!("Failed to load {}: {}", path, e);
+                    return ExitCode::from(2);
+                }
+            };
+            options
+                .config
+                .input
+                .extend(recording.events.iter().copied());
+            // Play the recording to its end unless told otherwise
+            options.config.max_frames = options.frames.unwrap_or(recording.frames);
+            match recording.boot(&rom) {
+                Ok(chip8) => chip8,
+                Err(e) => {
+                    eprintln!("{}: {}", path, e);
+                    return ExitCode::from(2);
+                }
+            }
+        }
+        None => {
+            let mut chip8 = Chip8::new();
+            chip8.set_platform(options.platform);
+            chip8.set_seed(options.seed);
+            if let Err(e) = chip8.try_load_rom_bytes(&rom) {
+                eprintln!("Failed to load {}: {}", options.rom, e);
+                return ExitCode::from(2);
+            }
+            chip8
+        }
+    };
+    if let Some(cycles) = options.cycles_per_frame {
+        chip8.set_cycles_per_frame(cycles);
+    }
+    if let Some(timing) = options.timing {
+        chip8.set_timing(timing);
+    }
+    for &(address, value) in &options.pokes {
+        chip8.memory_mut().set_byte(address as usize, value);
+    }
+    let mut vip = match &options.vip_interpreter {
+        Some(interpreter) => match boot_vip(interpreter, options.vip_monitor.as_deref(), &rom) {
+            Ok(mut vip) => {
+                for &(address, value) in &options.pokes {
+                    vip.memory_mut().set_byte(address as usize, value);
+                }
+                Some(vip)
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                return ExitCode::from(2);
+            }
+        },
+        None => None,
+    };
+
+    if let (Some(vip), true) = (vip.as_mut(), options.compare) {
+        let differing = compare(&mut chip8, vip, &options.config);
+        match differing.first() {
+            Some(first) => eprintln!(
+                "Screens differed in {} of {} frames, first after frame {}",
+                differing.len(),
+                options.config.max_frames,
+                first
+            ),
+            None => eprintln!("Screens matched for {} frames", options.config.max_frames),
+        }
+        let screens = screens_side_by_side(chip8.display(), vip.display());
+        if let Err(e) = write_output(options.screen_out.as_deref(), screens.as_bytes()) {
+            eprintln!("Failed to write output: {}", e);
+            return ExitCode::from(2);
+        }
+        return if differing.is_empty() {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        };
+    }
+
+    let mut capture = match &options.capture {
+        Some(base) => {
+            match Capture::start(Path::new(base), options.scale, options.palette.colors()) {
+                Ok(capture) => Some(capture),
+                Err(e) => {
+                    eprintln!("Failed to start capture at {}: {}", base, e);
+                    return ExitCode::from(2);
+                }
+            }
+        }
+        None => None,
+    };
+    let mut captured = Ok(());
+    let mut observe = |machine: &dyn Machine| {
+        if let (Some(capture), Ok(())) = (capture.as_mut(), &captured) {
+            captured = capture.frame(machine);
+        }
+    };
+    let outcome = match vip.as_mut() {
+        Some(vip) => {
+            run_machine(vip, &options.config, |vip| observe(vip));
+            RunOutcome::FrameLimit
+        }
+        None => run_observed(&mut chip8, &options.config, |chip8| observe(chip8)),
+    };
+    if let Some(capture) = capture {
+        if let Err(e) = captured.and_then(|_| capture.finish()) {
+            eprintln!("Failed to write capture: {}", e);
+            return ExitCode::from(2);
+        }
+    }
+    match outcome {
+        RunOutcome::Stopped { condition, frame } => {
+            eprintln!("Stopped on {} in frame {}", condition, frame)
+        }
+        RunOutcome::FrameLimit => eprintln!("Ran {} frames", options.config.max_frames),
+    }
+
+    let display = match &vip {
+        Some(vip) => vip.display(),
+        None => chip8.display(),
+    };
+    let screen = match options.screen {
+        ScreenFormat::Text => Ok(screen_text(display).into_bytes()),
+        ScreenFormat::Pbm => Ok(screen_pbm(display)),
+        ScreenFormat::Ppm => display.to_ppm(options.scale, options.palette.colors()),
+        ScreenFormat::Png => display.to_png(options.scale, options.palette.colors()),
+    };
+    let screen = match screen {
+        Ok(screen) => screen,
+        Err(e) => {
+            eprintln!("Failed to render the screen: {}", e);
+            return ExitCode::from(2);
+        }
+    };
+    let regs = match &vip {
+        Some(vip) => vip_registers_json(vip),
+        None => registers_json(&chip8),
+    };
+    let regs = format!("{:#}\n", regs);
+    // JSON after an image on stdout would leave neither readable
+    let binary_on_stdout =
+        !matches!(options.screen, ScreenFormat::Text) && options.screen_out.is_none();
+    let written = write_output(options.screen_out.as_deref(), &screen).and_then(|_| {
+        if binary_on_stdout && options.regs_out.is_none() {
+            return Ok(());
+        }
+        write_output(options.regs_out.as_deref(), regs.as_bytes())
+    });
+    if let Err(e) = written {
+        eprintln!("Failed to write output: {}", e);
+        return ExitCode::from(2);
+    }
+
+    if outcome == RunOutcome::FrameLimit && !options.config.conditions.is_empty() {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn boot_vip(interpreter: &str, monitor: Option<&str>, rom: &[u8]) -> Result<Vip, String> {
+    let read =
+        |path: &str| std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path, e));
+    let mut vip = Vip::new();
+    if let Some(monitor) = monitor {
+        vip.load_monitor(&read(monitor)?);
+    }
+    vip.try_load_interpreter(&read(interpreter)?)
+        .map_err(|e| format!("Failed to load {}: {}", interpreter, e))?;
+    vip.try_load_rom_bytes(rom).map_err(|e| e.to_string())?;
+    Ok(vip)
+}
+
+fn write_output(path: Option<&str>, bytes: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+    match path {
+        Some(path) => std::fs::write(path, bytes),
+        None => std::io::stdout().write_all(bytes),
+    }
+}
@@ -0,0 +1,129 @@
+//! Runs the bundled Timendus test ROMs and compares the final screen against
+//! the golden framebuffers in `tests/golden`. Run with `UPDATE_GOLDEN=1` to
+//! rewrite the golden files after an intended change in behaviour.
+
+use std::path::{Path, PathBuf};
+
+use chip8_core::emu::Chip8;
+use chip8_core::platform::Platform;
+use chip8_headless::conformance::{TestRom, AUTOSTART_ADDRESS, CORAX, FLAGS, QUIRKS};
+use chip8_headless::{run, screen_text, RunConfig, StopCondition};
+
+fn rom(file: &str) -> Vec<u8> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../roms")
+        .join(file);
+    std::fs::read(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+}
+
+fn assert_golden(name: &str, chip8: &Chip8) {
+    let path: PathBuf = [
+        env!("CARGO_MANIFEST_DIR"),
+        "tests",
+        "golden",
+        &format!("{}.txt", name),
+    ]
+    .iter()
+    .collect();
+    let actual = screen_text(chip8.display());
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(&path, &actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("{}: {} (run with UPDATE_GOLDEN=1)", path.display(), e));
+    assert!(
+        actual == expected,
+        "{} does not match {}\nexpected:\n{}\nactual:\n{}",
+        name,
+        path.display(),
+        expected,
+        actual
+    );
+}
+
+fn check_self_checking(test: &TestRom, platform: Platform) -> Chip8 {
+    let chip8 = test.run(&rom(test.file), platform);
+    assert_golden(&format!("{}-{}", test.name, platform), &chip8);
+    chip8
+}
+
+fn failed_checks(test: &TestRom, chip8: &Chip8, platform: Platform) -> Vec<String> {
+    test.results(chip8.display(), platform)
+        .into_iter()
+        .filter(|r| !r.as_expected())
+        .map(|r| r.label)
+        .collect()
+}
+
+#[test]
+fn chip8_logo() {
+    for platform in Platform::ALL {
+        let mut chip8 = Chip8::new();
+        chip8.set_platform(platform);
+        chip8.load_rom_bytes(&rom("1-chip8-logo.ch8"));
+        run(
+            &mut chip8,
+            &RunConfig::new(600).stop_when(StopCondition::SelfJump),
+        );
+        assert_golden(&format!("chip8-logo-{}", platform), &chip8);
+    }
+}
+
+#[test]
+fn corax_plus() {
+    for platform in Platform::ALL {
+        let chip8 = check_self_checking(&CORAX, platform);
+        assert_eq!(
+            failed_checks(&CORAX, &chip8, platform),
+            Vec::<String>::new()
+        );
+    }
+}
+
+#[test]
+fn flags() {
+    for platform in Platform::ALL {
+        let chip8 = check_self_checking(&FLAGS, platform);
+        assert_eq!(
+            failed_checks(&FLAGS, &chip8, platform),
+            Vec::<String>::new()
+        );
+    }
+}
+
+#[test]
+fn quirks() {
+    // The ROM grades against the original CHIP-8; SUPER-CHIP is expected to
+    // fail where it differs
+    for platform in Platform::ALL {
+        let chip8 = check_self_checking(&QUIRKS, platform);
+        assert_eq!(
+            failed_checks(&QUIRKS, &chip8, platform),
+            Vec::<String>::new()
+        );
+    }
+}
+
+#[test]
+fn keypad() {
+    // 1: EX9E held, 2: EXA1 released, 3: FX0A
+    for (test, name) in [(1, "down"), (2, "up"), (3, "getkey")] {
+        for platform in Platform::ALL {
+            let mut chip8 = Chip8::new();
+            chip8.set_platform(platform);
+            chip8.load_rom_bytes(&rom("6-keypad.ch8"));
+            chip8.memory_mut().set_byte(AUTOSTART_ADDRESS, test);
+            let config = RunConfig::new(120);
+            // EX9E and EXA1 show the keys held at the snapshot, FX0A the last
+            // key pressed and released
+            let config = if test == 3 {
+                config.press(20, 0x5, 5).press(40, 0xA, 30)
+            } else {
+                config.press(20, 0x5, 110).press(40, 0xA, 90)
+            };
+            run(&mut chip8, &config);
+            assert_golden(&format!("keypad-{}-{}", name, platform), &chip8);
+        }
+    }
+}
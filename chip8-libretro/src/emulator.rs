@@ -0,0 +1,398 @@
+//! The emulator as a libretro frontend drives it, free of the C interface so
+//! it can be tested directly.
+
+use libretro_sys::{
+    DEVICE_ID_JOYPAD_A, DEVICE_ID_JOYPAD_B, DEVICE_ID_JOYPAD_DOWN, DEVICE_ID_JOYPAD_LEFT,
+    DEVICE_ID_JOYPAD_RIGHT, DEVICE_ID_JOYPAD_UP, DEVICE_ID_JOYPAD_X, DEVICE_ID_JOYPAD_Y,
+};
+
+use chip8_core::beeper::{Beeper, SAMPLES_PER_FRAME};
+use chip8_core::display::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use chip8_core::emu::{Chip8, RomTooLarge, MAX_ROM_SIZE, STACK_SIZE};
+use chip8_core::gamepad::{PadControl, Preset, PRESETS};
+use chip8_core::palette::Palette;
+use chip8_core::platform::{Platform, Quirks};
+
+/// RetroPad buttons, numbered as libretro's joypad IDs
+pub const BUTTONS: usize = 16;
+
+const BUTTON_NAMES: [&str; BUTTONS] = [
+    "B", "Y", "Select", "Start", "Up", "Down", "Left", "Right", "A", "X", "L", "R", "L2", "R2",
+    "L3", "R3",
+];
+
+// The RetroPad button each preset control is; A is the right face button
+fn button(control: PadControl) -> u32 {
+    match control {
+        PadControl::Up => DEVICE_ID_JOYPAD_UP,
+        PadControl::Down => DEVICE_ID_JOYPAD_DOWN,
+        PadControl::Left => DEVICE_ID_JOYPAD_LEFT,
+        PadControl::Right => DEVICE_ID_JOYPAD_RIGHT,
+        PadControl::Action => DEVICE_ID_JOYPAD_A,
+        PadControl::Secondary => DEVICE_ID_JOYPAD_B,
+    }
+}
+
+/// The key of every button under `preset`. Buttons the preset leaves free
+/// take the keys it leaves free, in order, so the whole keypad is always in reach.
+pub fn keymap(preset: &Preset) -> [usize; BUTTONS] {
+    let mut keys = [None; BUTTONS];
+    for &(control, key) in preset.bindings {
+        keys[button(control) as usize] = Some(key);
+    }
+    let mut free = (0..16).filter(|key| !preset.bindings.iter().any(|&(_, k)| k == *key));
+    // Face and shoulder buttons before the rest
+    let order = [DEVICE_ID_JOYPAD_X, DEVICE_ID_JOYPAD_Y]
+        .into_iter()
+        .chain(0..BUTTONS as u32);
+    for button in order {
+        if keys[button as usize].is_none() {
+            keys[button as usize] = free.next();
+        }
+    }
+    keys.map(|key| key.unwrap())
+}
+
+/// How the libretro core options set up the machine
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Settings {
+    pub platform: Platform,
+    /// Per quirk of `QUIRKS`, forced on or off, or `None` for the platform's own
+    pub quirks: [Option<bool>; QUIRKS.len()],
+    pub palette: Palette,
+    /// Index into `PRESETS`
+    pub layout: usize,
+}
+
+type QuirkField = fn(&mut Quirks) -> &mut bool;
+
+/// Option key, description and field of every quirk that can be overridden
+pub const QUIRKS: [(&str, &str, QuirkField); 7] = [
+    ("chip8_quirk_shift", "8XY6/8XYE shift VY", |q| {
+        &mut q.shift_uses_vy
+    }),
+    ("chip8_quirk_load_store", "FX55/FX65 advance I", |q| {
+        &mut q.load_store_increments_i
+    }),
+    ("chip8_quirk_vf_reset", "Logic ops reset VF", |q| {
+        &mut q.vf_reset
+    }),
+    ("chip8_quirk_clip", "Clip sprites at the edges", |q| {
+        &mut q.clip_sprites
+    }),
+    ("chip8_quirk_jump", "BNNN jumps to XNN + VX", |q| {
+        &mut q.jump_uses_vx
+    }),
+    ("chip8_quirk_key_wait", "FX0A waits for key release", |q| {
+        &mut q.key_wait_on_release
+    }),
+    (
+        "chip8_quirk_display_wait",
+        "Drawing waits for vblank",
+        |q| &mut q.display_wait,
+    ),
+];
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            platform: Platform::default(),
+            quirks: [None; QUIRKS.len()],
+            palette: Palette::default(),
+            layout: 0,
+        }
+    }
+}
+
+impl Settings {
+    /// Every core option as `(key, "Description; first|second|...")`, the
+    /// first value being the default
+    pub fn variables() -> Vec<(String, String)> {
+        let values = |names: Vec<&str>| names.join("|");
+        let mut variables = vec![
+            (
+                "chip8_platform".to_string(),
+                format!(
+                    "Platform; {}",
+                    values(Platform::ALL.iter().map(|p| p.name()).collect())
+                ),
+            ),
+            (
+                "chip8_palette".to_string(),
+                format!(
+                    "Palette; {}",
+                    values(Palette::builtins().iter().map(|p| p.name()).collect())
+                ),
+            ),
+            (
+                "chip8_layout".to_string(),
+                format!(
+                    "Joypad layout; {}",
+                    values(PRESETS.iter().map(|p| p.name).collect())
+                ),
+            ),
+        ];
+        for (key, description, _) in QUIRKS {
+            variables.push((key.to_string(), format!("{}; platform|on|off", description)));
+        }
+        variables
+    }
+
+    /// Take the value of one core option; false if the key or value is unknown
+    pub fn set(&mut self, key: &str, value: &str) -> bool {
+        match key {
+            "chip8_platform" => match value.parse() {
+                Ok(platform) => self.platform = platform,
+                Err(_) => return false,
+            },
+            "chip8_palette" => match Palette::builtin(value) {
+                Some(palette) => self.palette = palette,
+                None => return false,
+            },
+            "chip8_layout" => match PRESETS.iter().position(|p| p.name == value) {
+                Some(layout) => self.layout = layout,
+                None => return false,
+            },
+            _ => {
+                let Some(n) = QUIRKS.iter().position(|(k, _, _)| *k == key) else {
+                    return false;
+                };
+                self.quirks[n] = match value {
+                    "platform" => None,
+                    "on" => Some(true),
+                    "off" => Some(false),
+                    _ => return false,
+                };
+            }
+        }
+        true
+    }
+
+    /// The platform's quirks with the overrides applied
+    pub fn quirks(&self) -> Quirks {
+        let mut quirks = self.platform.quirks();
+        for ((_, _, field), value) in QUIRKS.iter().zip(self.quirks) {
+            if let Some(value) = value {
+                *field(&mut quirks) = value;
+            }
+        }
+        quirks
+    }
+}
+
+pub struct Emulator {
+    chip8: Chip8,
+    rom: Vec<u8>,
+    settings: Settings,
+    keymap: [usize; BUTTONS],
+    beeper: Beeper,
+    // The last frame as XRGB8888
+    video: Vec<u32>,
+    // The last frame's audio, interleaved stereo
+    audio: Vec<i16>,
+}
+
+impl Emulator {
+    /// Power on with `rom`, which has to fit in memory
+    pub fn new(rom: &[u8], settings: Settings) -> Result<Self, RomTooLarge> {
+        if rom.len() > MAX_ROM_SIZE {
+            return Err(RomTooLarge { len: rom.len() });
+        }
+        let mut core = Emulator {
+            chip8: Chip8::new(),
+            rom: rom.to_vec(),
+            keymap: keymap(&PRESETS[settings.layout]),
+            settings,
+            beeper: Beeper::new(),
+            video: vec![0; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+            audio: vec![0; SAMPLES_PER_FRAME * 2],
+        };
+        core.reset();
+        Ok(core)
+    }
+
+    /// Power on afresh with the same ROM
+    pub fn reset(&mut self) {
+        let mut chip8 = Chip8::new();
+        chip8.set_platform(self.settings.platform);
+        chip8.set_quirks(self.settings.quirks());
+        chip8.load_rom_bytes(&self.rom);
+        self.chip8 = chip8;
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// Switch to new core options while running
+    pub fn apply(&mut self, settings: Settings) {
+        self.chip8.set_platform(settings.platform);
+        self.chip8.set_quirks(settings.quirks());
+        self.keymap = keymap(&PRESETS[settings.layout]);
+        self.settings = settings;
+    }
+
+    /// What each RetroPad button does, as libretro input descriptions
+    pub fn button_descriptions(&self) -> Vec<String> {
+        (0..BUTTONS)
+            .map(|button| format!("Key {:X} ({})", self.keymap[button], BUTTON_NAMES[button]))
+            .collect()
+    }
+
+    /// Run one frame with the buttons `pressed` reports held
+    pub fn run_frame(&mut self, pressed: impl Fn(u32) -> bool) {
+        let mut held = [false; 16];
+        for button in 0..BUTTONS {
+            if pressed(button as u32) {
+                held[self.keymap[button]] = true;
+            }
+        }
+        let keyboard = self.chip8.key_mut();
+        for (key, &down) in held.iter().enumerate() {
+            keyboard.set_key(key, down);
+        }
+
+        self.chip8.run_frame();
+
+        let colors = self.settings.palette.colors();
+        let display = self.chip8.display();
+        for (n, pixel) in self.video.iter_mut().enumerate() {
+            let value = display.get_pixel(n % DISPLAY_WIDTH, n / DISPLAY_WIDTH) as usize;
+            let [r, g, b] = colors[value.min(colors.len() - 1)];
+            *pixel = u32::from_be_bytes([0, r, g, b]);
+        }
+        let samples = self.beeper.frame(self.chip8.sound_active());
+        for (frame, sample) in self.audio.chunks_mut(2).zip(samples) {
+            frame.fill(sample);
+        }
+    }
+
+    /// The last frame as XRGB8888 pixels, row by row
+    pub fn video(&self) -> &[u32] {
+        &self.video
+    }
+
+    /// The last frame's audio as interleaved stereo samples
+    pub fn audio(&self) -> &[i16] {
+        &self.audio
+    }
+
+    /// Size of every save state. libretro wants it fixed, so there is room
+    /// for the deepest stack allowed.
+    pub fn state_size() -> usize {
+        let new = Chip8::new();
+        new.save_state().len() + (STACK_SIZE - new.stack().len()) * 2
+    }
+
+    /// Write a save state into `out`, zero padded; false if it does not fit
+    pub fn serialize(&self, out: &mut [u8]) -> bool {
+        let state = self.chip8.save_state();
+        if state.len() > out.len() {
+            return false;
+        }
+        out[..state.len()].copy_from_slice(&state);
+        out[state.len()..].fill(0);
+        true
+    }
+
+    /// Restore a save state written by `serialize`; the padding is ignored
+    pub fn unserialize(&mut self, state: &[u8]) -> bool {
+        self.chip8.load_state(state).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layouts_reach_every_key() {
+        for preset in &PRESETS {
+            let mut keys = keymap(preset).to_vec();
+            keys.sort();
+            assert_eq!(keys, (0..16).collect::<Vec<_>>(), "{}", preset.name);
+        }
+        let keymap = keymap(&PRESETS[0]);
+        assert_eq!(keymap[DEVICE_ID_JOYPAD_UP as usize], 0x2);
+        assert_eq!(keymap[DEVICE_ID_JOYPAD_A as usize], 0x5);
+        // The first free key goes to X
+        assert_eq!(keymap[DEVICE_ID_JOYPAD_X as usize], 0x1);
+    }
+
+    #[test]
+    fn options_set_platform_and_quirks() {
+        let mut settings = Settings::default();
+        assert!(settings.set("chip8_platform", "schip"));
+        assert!(settings.set("chip8_quirk_clip", "off"));
+        assert!(settings.set("chip8_layout", "paddle-14"));
+        assert!(!settings.set("chip8_platform", "eti660"));
+        assert!(!settings.set("chip8_quirk_clip", "maybe"));
+        assert!(!settings.set("chip8_nope", "on"));
+
+        let quirks = settings.quirks();
+        assert!(!quirks.clip_sprites);
+        assert!(quirks.jump_uses_vx);
+        assert!(settings.set("chip8_quirk_clip", "platform"));
+        assert_eq!(settings.quirks(), Platform::SuperChip.quirks());
+
+        // Every option's default value is accepted
+        for (key, value) in Settings::variables() {
+            let first = value.split("; ").nth(1).unwrap().split('|').next().unwrap();
+            assert!(Settings::default().set(&key, first), "{}", key);
+        }
+    }
+
+    #[test]
+    fn frames_produce_video_audio_and_key_presses() {
+        // Sound for a while, then wait for key 5 and draw a pixel row once it comes
+        let rom = [
+            0x60, 0x10, 0xF0, 0x18, 0xF1, 0x0A, 0xA2, 0x0E, 0xD2, 0x21, 0x12, 0x0A, 0x00, 0x00,
+            0xF0,
+        ];
+        let mut core = Emulator::new(&rom, Settings::default()).unwrap();
+        core.run_frame(|_| false);
+        assert!(core.audio().iter().any(|&s| s != 0));
+        assert_eq!(core.audio().len(), SAMPLES_PER_FRAME * 2);
+        let [r, g, b] = Palette::default().colors()[0];
+        assert_eq!(core.video()[0], u32::from_be_bytes([0, r, g, b]));
+
+        // A on the default layout is key 5; FX0A finishes on its release
+        core.run_frame(|button| button == DEVICE_ID_JOYPAD_A);
+        for _ in 0..20 {
+            core.run_frame(|_| false);
+        }
+        let [r, g, b] = Palette::default().colors()[1];
+        assert_eq!(core.video()[0], u32::from_be_bytes([0, r, g, b]));
+        assert!(core.audio().iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn roms_that_do_not_fit_are_refused() {
+        let rom = vec![0; MAX_ROM_SIZE + 1];
+        assert_eq!(
+            Emulator::new(&rom, Settings::default()).err(),
+            Some(RomTooLarge { len: rom.len() })
+        );
+        assert!(Emulator::new(&rom[1..], Settings::default()).is_ok());
+    }
+
+    #[test]
+    fn save_states_fit_and_restore() {
+        let mut core = Emulator::new(&[0x12, 0x00], Settings::default()).unwrap();
+        core.run_frame(|_| false);
+        let mut state = vec![0xAA; Emulator::state_size()];
+        assert!(core.serialize(&mut state));
+        core.reset();
+        assert!(core.unserialize(&state));
+        assert_eq!(core.chip8.frame(), 1);
+        assert!(!core.unserialize(&state[..10]));
+
+        // Calls nested as deep as they go still fit
+        let mut core = Emulator::new(&[0x22, 0x00], Settings::default()).unwrap();
+        for _ in 0..10 {
+            core.run_frame(|_| false);
+        }
+        assert_eq!(core.chip8.stack().len(), STACK_SIZE);
+        assert!(core.serialize(&mut state));
+    }
+}
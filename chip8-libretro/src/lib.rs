@@ -0,0 +1,432 @@
+//! A libretro core, so RetroArch and other libretro frontends can run CHIP-8
+//! games. Build the `cdylib` and load it as `chip8_libretro.so` (or `.dll`,
+//! `.dylib`); the platform, quirks, palette and joypad layout are core options.
+
+// The entry points take whatever the libretro API says a frontend passes them
+#![allow(clippy::missing_safety_doc)]
+
+mod emulator;
+
+use std::ffi::{c_char, c_uint, c_void, CString};
+use std::ptr;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+use libretro_sys::{
+    AudioSampleBatchFn, AudioSampleFn, EnvironmentFn, GameGeometry, GameInfo, InputDescriptor,
+    InputPollFn, InputStateFn, PixelFormat, Region, SystemAvInfo, SystemInfo, SystemTiming,
+    Variable, VideoRefreshFn, API_VERSION, DEVICE_JOYPAD, ENVIRONMENT_GET_VARIABLE,
+    ENVIRONMENT_GET_VARIABLE_UPDATE, ENVIRONMENT_SET_INPUT_DESCRIPTORS,
+    ENVIRONMENT_SET_PIXEL_FORMAT, ENVIRONMENT_SET_VARIABLES,
+};
+
+use chip8_core::beeper::SAMPLE_RATE;
+use chip8_core::display::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+use crate::emulator::{Emulator, Settings};
+
+/// Callbacks handed over by the frontend
+struct Frontend {
+    environment: Option<EnvironmentFn>,
+    video: Option<VideoRefreshFn>,
+    audio_batch: Option<AudioSampleBatchFn>,
+    input_poll: Option<InputPollFn>,
+    input_state: Option<InputStateFn>,
+}
+
+struct Game {
+    emulator: Emulator,
+    // Kept alive for as long as the frontend may look at the descriptors
+    descriptions: Vec<CString>,
+}
+
+// libretro calls a core from one thread only; the locks just make the
+// globals safe to hold
+static FRONTEND: Mutex<Frontend> = Mutex::new(Frontend {
+    environment: None,
+    video: None,
+    audio_batch: None,
+    input_poll: None,
+    input_state: None,
+});
+static GAME: Mutex<Option<Game>> = Mutex::new(None);
+
+fn frontend() -> MutexGuard<'static, Frontend> {
+    FRONTEND.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn game() -> MutexGuard<'static, Option<Game>> {
+    GAME.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn environment(cmd: c_uint, data: *mut c_void) -> bool {
+    match frontend().environment {
+        Some(environment) => unsafe { environment(cmd, data) },
+        None => false,
+    }
+}
+
+/// The core options as the frontend has them set now
+fn read_settings() -> Settings {
+    let mut settings = Settings::default();
+    for (key, _) in Settings::variables() {
+        let key = CString::new(key).unwrap();
+        let mut variable = Variable {
+            key: key.as_ptr(),
+            value: ptr::null(),
+        };
+        let found = environment(
+            ENVIRONMENT_GET_VARIABLE,
+            &mut variable as *mut _ as *mut c_void,
+        );
+        if found && !variable.value.is_null() {
+            let value = unsafe { std::ffi::CStr::from_ptr(variable.value) };
+            settings.set(&key.to_string_lossy(), &value.to_string_lossy());
+        }
+    }
+    settings
+}
+
+fn set_input_descriptors(game: &mut Game) {
+    game.descriptions = game
+        .emulator
+        .button_descriptions()
+        .into_iter()
+        .map(|d| CString::new(d).unwrap())
+        .collect();
+    let mut descriptors: Vec<InputDescriptor> = game
+        .descriptions
+        .iter()
+        .enumerate()
+        .map(|(button, description)| InputDescriptor {
+            port: 0,
+            device: DEVICE_JOYPAD,
+            index: 0,
+            id: button as c_uint,
+            description: description.as_ptr(),
+        })
+        .collect();
+    // Terminated by an entry without a description
+    descriptors.push(InputDescriptor {
+        port: 0,
+        device: 0,
+        index: 0,
+        id: 0,
+        description: ptr::null(),
+    });
+    environment(
+        ENVIRONMENT_SET_INPUT_DESCRIPTORS,
+        descriptors.as_mut_ptr() as *mut c_void,
+    );
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    API_VERSION
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    *game() = None;
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut SystemInfo) {
+    *info = SystemInfo {
+        library_name: c"CHIP-8".as_ptr(),
+        library_version: c"0.1.0".as_ptr(),
+        valid_extensions: c"ch8|c8".as_ptr(),
+        need_fullpath: false,
+        block_extract: false,
+    };
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut SystemAvInfo) {
+    *info = SystemAvInfo {
+        geometry: GameGeometry {
+            base_width: DISPLAY_WIDTH as c_uint,
+            base_height: DISPLAY_HEIGHT as c_uint,
+            max_width: DISPLAY_WIDTH as c_uint,
+            max_height: DISPLAY_HEIGHT as c_uint,
+            aspect_ratio: DISPLAY_WIDTH as f32 / DISPLAY_HEIGHT as f32,
+        },
+        timing: SystemTiming {
+            fps: 60.0,
+            sample_rate: SAMPLE_RATE as f64,
+        },
+    };
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_environment(callback: EnvironmentFn) {
+    frontend().environment = Some(callback);
+
+    // Built once; the strings must outlive the call
+    static VARIABLES: OnceLock<Vec<(CString, CString)>> = OnceLock::new();
+    let variables = VARIABLES.get_or_init(|| {
+        Settings::variables()
+            .into_iter()
+            .map(|(key, value)| (CString::new(key).unwrap(), CString::new(value).unwrap()))
+            .collect()
+    });
+    let mut table: Vec<Variable> = variables
+        .iter()
+        .map(|(key, value)| Variable {
+            key: key.as_ptr(),
+            value: value.as_ptr(),
+        })
+        .chain(std::iter::once(Variable {
+            key: ptr::null(),
+            value: ptr::null(),
+        }))
+        .collect();
+    environment(ENVIRONMENT_SET_VARIABLES, table.as_mut_ptr() as *mut c_void);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(callback: VideoRefreshFn) {
+    frontend().video = Some(callback);
+}
+
+// Audio goes out a frame at a time through the batch callback
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_callback: AudioSampleFn) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(callback: AudioSampleBatchFn) {
+    frontend().audio_batch = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(callback: InputPollFn) {
+    frontend().input_poll = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(callback: InputStateFn) {
+    frontend().input_state = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    if let Some(game) = game().as_mut() {
+        game.emulator.reset();
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_run() {
+    let mut game = game();
+    let Some(game) = game.as_mut() else {
+        return;
+    };
+
+    let mut updated = false;
+    environment(
+        ENVIRONMENT_GET_VARIABLE_UPDATE,
+        &mut updated as *mut bool as *mut c_void,
+    );
+    if updated {
+        let settings = read_settings();
+        let layout_changed = settings.layout != game.emulator.settings().layout;
+        game.emulator.apply(settings);
+        if layout_changed {
+            set_input_descriptors(game);
+        }
+    }
+
+    let frontend = frontend();
+    if let Some(poll) = frontend.input_poll {
+        unsafe { poll() };
+    }
+    let input_state = frontend.input_state;
+    game.emulator.run_frame(|button| match input_state {
+        Some(state) => unsafe { state(0, DEVICE_JOYPAD, 0, button) != 0 },
+        None => false,
+    });
+
+    if let Some(video) = frontend.video {
+        let pitch = DISPLAY_WIDTH * 4;
+        let pixels = game.emulator.video().as_ptr() as *const c_void;
+        unsafe {
+            video(
+                pixels,
+                DISPLAY_WIDTH as c_uint,
+                DISPLAY_HEIGHT as c_uint,
+                pitch,
+            )
+        };
+    }
+    if let Some(audio_batch) = frontend.audio_batch {
+        let audio = game.emulator.audio();
+        unsafe { audio_batch(audio.as_ptr(), audio.len() / 2) };
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    Emulator::state_size()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    let game = game();
+    let Some(game) = game.as_ref() else {
+        return false;
+    };
+    let out = std::slice::from_raw_parts_mut(data as *mut u8, size);
+    game.emulator.serialize(out)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    let mut game = game();
+    let Some(game) = game.as_mut() else {
+        return false;
+    };
+    let state = std::slice::from_raw_parts(data as *const u8, size);
+    game.emulator.unserialize(state)
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_set(_index: c_uint, _enabled: bool, _code: *const c_char) {}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(info: *const GameInfo) -> bool {
+    if info.is_null() || (*info).data.is_null() {
+        return false;
+    }
+    let mut format = PixelFormat::ARGB8888;
+    if !environment(
+        ENVIRONMENT_SET_PIXEL_FORMAT,
+        &mut format as *mut PixelFormat as *mut c_void,
+    ) {
+        return false;
+    }
+    let rom = std::slice::from_raw_parts((*info).data as *const u8, (*info).size);
+    let Ok(emulator) = Emulator::new(rom, read_settings()) else {
+        return false;
+    };
+    let mut loaded = Game {
+        emulator,
+        descriptions: Vec::new(),
+    };
+    set_input_descriptors(&mut loaded);
+    *game() = Some(loaded);
+    true
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(
+    _game_type: c_uint,
+    _info: *const GameInfo,
+    _num_info: usize,
+) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    *game() = None;
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    Region::NTSC.to_uint()
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(_id: c_uint) -> *mut c_void {
+    ptr::null_mut()
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(_id: c_uint) -> usize {
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static FRAMES_SHOWN: AtomicUsize = AtomicUsize::new(0);
+    static SAMPLES_PLAYED: AtomicUsize = AtomicUsize::new(0);
+
+    // A frontend that sets the SUPER-CHIP platform and accepts everything else
+    unsafe extern "C" fn environment(cmd: c_uint, data: *mut c_void) -> bool {
+        if cmd == ENVIRONMENT_GET_VARIABLE {
+            let variable = &mut *(data as *mut Variable);
+            if std::ffi::CStr::from_ptr(variable.key) == c"chip8_platform" {
+                variable.value = c"schip".as_ptr();
+                return true;
+            }
+            return false;
+        }
+        true
+    }
+
+    unsafe extern "C" fn video(_data: *const c_void, width: c_uint, height: c_uint, pitch: usize) {
+        assert_eq!((width, height, pitch), (64, 32, 256));
+        FRAMES_SHOWN.fetch_add(1, Ordering::SeqCst);
+    }
+
+    unsafe extern "C" fn audio_batch(_data: *const i16, frames: usize) -> usize {
+        SAMPLES_PLAYED.fetch_add(frames, Ordering::SeqCst);
+        frames
+    }
+
+    unsafe extern "C" fn input_state(
+        _port: c_uint,
+        _device: c_uint,
+        _index: c_uint,
+        _id: c_uint,
+    ) -> i16 {
+        0
+    }
+
+    unsafe extern "C" fn input_poll() {}
+
+    #[test]
+    fn runs_as_a_libretro_core() {
+        retro_set_environment(environment);
+        retro_set_video_refresh(video);
+        retro_set_audio_sample_batch(audio_batch);
+        retro_set_input_poll(input_poll);
+        retro_set_input_state(input_state);
+        retro_init();
+
+        let rom = [0x12, 0x00];
+        let info = GameInfo {
+            path: ptr::null(),
+            data: rom.as_ptr() as *const c_void,
+            size: rom.len(),
+            meta: ptr::null(),
+        };
+        assert!(unsafe { retro_load_game(&info) });
+        let platform = game().as_ref().unwrap().emulator.settings().platform;
+        assert_eq!(platform.name(), "schip");
+
+        retro_run();
+        retro_run();
+        assert_eq!(FRAMES_SHOWN.load(Ordering::SeqCst), 2);
+        assert_eq!(SAMPLES_PLAYED.load(Ordering::SeqCst), 2 * 735);
+
+        let mut state = vec![0; retro_serialize_size()];
+        assert!(unsafe { retro_serialize(state.as_mut_ptr() as *mut c_void, state.len()) });
+        retro_reset();
+        assert!(unsafe { retro_unserialize(state.as_ptr() as *const c_void, state.len()) });
+
+        retro_unload_game();
+        retro_deinit();
+    }
+}
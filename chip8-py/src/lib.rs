@@ -0,0 +1,391 @@
+//! Python bindings, built into the `chip8` module with maturin (`maturin develop`
+//! from this directory installs it into the active virtualenv):
+//!
+//! ```python
+//! import chip8
+//! machine = chip8.Chip8(seed=1)
+//! machine.load_rom(open("IBM Logo.ch8", "rb").read())
+//! machine.run_frame()
+//! screen = machine.framebuffer()  # numpy uint8 array, 32 rows of 64
+//! ```
+
+use numpy::ndarray::Array2;
+use numpy::{IntoPyArray, PyArray2};
+use pyo3::exceptions::{PyIndexError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict};
+
+use chip8_core::display::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use chip8_core::emu::Chip8 as Core;
+use chip8_core::env::{Env as CoreEnv, EnvConfig, EnvState as CoreEnvState, MemoryExtractor};
+use chip8_core::memory::MEMORY_SIZE;
+use chip8_core::platform::Platform;
+
+/// An emulated machine
+#[pyclass(module = "chip8")]
+pub struct Chip8 {
+    core: Core,
+}
+
+#[pymethods]
+impl Chip8 {
+    /// A machine with no ROM loaded. `seed` drives the random numbers of CXNN
+    /// and `platform` is `vip` or `schip`.
+    #[new]
+    #[pyo3(signature = (seed = 0, platform = "vip"))]
+    fn new(seed: u64, platform: &str) -> PyResult<Self> {
+        let platform: Platform = platform.parse().map_err(PyValueError::new_err)?;
+        let mut core = Core::new();
+        core.set_platform(platform);
+        core.set_seed(seed);
+        Ok(Chip8 { core })
+    }
+
+    /// Reset the machine and load `rom` at 0x200. Raises `ValueError`,
+    /// leaving the machine alone, if the ROM does not fit.
+    fn load_rom(&mut self, rom: &[u8]) -> PyResult<()> {
+        let mut core = self.core.clone();
+        core.reset();
+        core.try_load_rom_bytes(rom)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        self.core = core;
+        Ok(())
+    }
+
+    /// Execute one instruction; the timers are left for `run_frame`
+    fn step(&mut self) {
+        self.core.emulate_cycle();
+    }
+
+    /// Run one 60 Hz frame: a frame's worth of instructions, then a timer tick
+    fn run_frame(&mut self) {
+        self.core.run_frame();
+    }
+
+    /// Frames run since power on
+    #[getter]
+    fn frame(&self) -> u64 {
+        self.core.frame()
+    }
+
+    #[getter]
+    fn platform(&self) -> &'static str {
+        self.core.platform().name()
+    }
+
+    /// `length` bytes of memory from `address`
+    fn read_memory<'py>(
+        &self,
+        py: Python<'py>,
+        address: usize,
+        length: usize,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        check_range(address, length)?;
+        Ok(PyBytes::new(
+            py,
+            self.core.memory().read_slice_at(address, length),
+        ))
+    }
+
+    /// Overwrite memory from `address` with `data`
+    fn write_memory(&mut self, address: usize, data: &[u8]) -> PyResult<()> {
+        check_range(address, data.len())?;
+        self.core.memory_mut().write_slice_at(address, data);
+        Ok(())
+    }
+
+    /// V0 to VF, as bytes
+    #[getter]
+    fn registers(&self) -> [u8; 16] {
+        *self.core.registers()
+    }
+
+    #[setter]
+    fn set_registers(&mut self, registers: [u8; 16]) {
+        *self.core.registers_mut() = registers;
+    }
+
+    /// Register VX, `x` from 0 to 15
+    fn get_register(&self, x: usize) -> PyResult<u8> {
+        check_register(x)?;
+        Ok(self.core.registers()[x])
+    }
+
+    fn set_register(&mut self, x: usize, value: u8) -> PyResult<()> {
+        check_register(x)?;
+        self.core.registers_mut()[x] = value;
+        Ok(())
+    }
+
+    #[getter]
+    fn pc(&self) -> u16 {
+        self.core.pc()
+    }
+
+    #[setter]
+    fn set_pc(&mut self, pc: u16) -> PyResult<()> {
+        // The whole instruction has to be in memory
+        check_range(pc as usize, 2)?;
+        self.core.set_pc(pc);
+        Ok(())
+    }
+
+    #[getter]
+    fn i(&self) -> u16 {
+        self.core.i()
+    }
+
+    #[setter]
+    fn set_i(&mut self, i: u16) {
+        self.core.set_i(i);
+    }
+
+    #[getter]
+    fn delay_timer(&self) -> u8 {
+        self.core.delay_timer()
+    }
+
+    #[setter]
+    fn set_delay_timer(&mut self, value: u8) {
+        self.core.set_delay_timer(value);
+    }
+
+    #[getter]
+    fn sound_timer(&self) -> u8 {
+        self.core.sound_timer()
+    }
+
+    #[setter]
+    fn set_sound_timer(&mut self, value: u8) {
+        self.core.set_sound_timer(value);
+    }
+
+    /// The call stack, innermost return address last
+    #[getter]
+    fn stack(&self) -> Vec<u16> {
+        self.core.stack().to_vec()
+    }
+
+    /// The screen as a (height, width) uint8 array, 1 for a lit pixel
+    fn framebuffer<'py>(&self, py: Python<'py>) -> Bound<'py, PyArray2<u8>> {
+        screen(&self.core).into_pyarray(py)
+    }
+
+    /// Press or release keypad key 0 to 15
+    fn set_key(&mut self, key: usize, pressed: bool) -> PyResult<()> {
+        if key > 0xF {
+            return Err(PyValueError::new_err("key out of range 0 to 15"));
+        }
+        self.core.key_mut().set_key(key, pressed);
+        Ok(())
+    }
+
+    /// Whether the beeper is sounding
+    #[getter]
+    fn sound_active(&self) -> bool {
+        self.core.sound_active()
+    }
+
+    fn save_state<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.core.save_state())
+    }
+
+    /// Restore a state from `save_state`. On failure the machine is left as it was.
+    fn load_state(&mut self, state: &[u8]) -> PyResult<()> {
+        self.core
+            .load_state(state)
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+}
+
+/// A Gym-style environment for training agents on one ROM. `score` is a
+/// value in memory such as `bcd:0x2F0:3` whose increase is the reward, and
+/// `done` conditions such as `bin:0x1E0 == 0` end an episode. Each action
+/// holds the keys set in a bit mask, by default none and then each key alone.
+#[pyclass(module = "chip8")]
+pub struct Env {
+    env: CoreEnv,
+    // Seed of the next episode when `reset` is not given one
+    next_seed: u64,
+}
+
+#[pymethods]
+impl Env {
+    #[new]
+    #[pyo3(signature = (
+        rom, *, score = None, done = Vec::new(), frame_skip = 4, max_frames = None,
+        actions = None, platform = "vip",
+    ))]
+    fn new(
+        rom: &[u8],
+        score: Option<&str>,
+        done: Vec<String>,
+        frame_skip: usize,
+        max_frames: Option<u64>,
+        actions: Option<Vec<u16>>,
+        platform: &str,
+    ) -> PyResult<Self> {
+        let extractor = MemoryExtractor {
+            score: score
+                .map(str::parse)
+                .transpose()
+                .map_err(PyValueError::new_err)?,
+            done: done
+                .iter()
+                .map(|c| c.parse())
+                .collect::<Result<_, _>>()
+                .map_err(PyValueError::new_err)?,
+        };
+        let mut config = EnvConfig {
+            platform: platform.parse().map_err(PyValueError::new_err)?,
+            frame_skip,
+            max_frames,
+            ..EnvConfig::default()
+        };
+        if let Some(actions) = actions {
+            config.actions = actions;
+        }
+        let env = CoreEnv::new(rom, config, Box::new(extractor))
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(Env { env, next_seed: 0 })
+    }
+
+    #[getter]
+    fn action_count(&self) -> usize {
+        self.env.action_count()
+    }
+
+    /// Start a new episode, returning `(observation, info)`. Without a seed
+    /// each episode takes the one after the last.
+    #[pyo3(signature = (seed = None))]
+    fn reset<'py>(
+        &mut self,
+        py: Python<'py>,
+        seed: Option<u64>,
+    ) -> (Bound<'py, PyArray2<u8>>, Bound<'py, PyDict>) {
+        let seed = seed.unwrap_or(self.next_seed);
+        self.next_seed = seed.wrapping_add(1);
+        self.env.reset(seed);
+        (self.observation(py), PyDict::new(py))
+    }
+
+    /// Take an action, returning `(observation, reward, terminated, truncated, info)`
+    #[allow(clippy::type_complexity)]
+    fn step<'py>(
+        &mut self,
+        py: Python<'py>,
+        action: usize,
+    ) -> PyResult<(
+        Bound<'py, PyArray2<u8>>,
+        f64,
+        bool,
+        bool,
+        Bound<'py, PyDict>,
+    )> {
+        let step = self
+            .env
+            .step(action)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let info = PyDict::new(py);
+        info.set_item("frame", self.env.chip8().frame())?;
+        Ok((
+            self.observation(py),
+            step.reward,
+            step.terminated,
+            step.truncated,
+            info,
+        ))
+    }
+
+    /// The screen as a (height, width) uint8 array, 1 for a lit pixel
+    fn observation<'py>(&self, py: Python<'py>) -> Bound<'py, PyArray2<u8>> {
+        screen(self.env.chip8()).into_pyarray(py)
+    }
+
+    /// The whole machine, to come back to with `restore_state`
+    fn clone_state(&self) -> EnvState {
+        EnvState(self.env.clone_state())
+    }
+
+    fn restore_state(&mut self, state: &EnvState) {
+        self.env.restore_state(&state.0);
+    }
+}
+
+/// A snapshot from `Env.clone_state`
+#[pyclass(module = "chip8", frozen)]
+pub struct EnvState(CoreEnvState);
+
+fn check_range(address: usize, length: usize) -> PyResult<()> {
+    if address
+        .checked_add(length)
+        .is_none_or(|end| end > MEMORY_SIZE)
+    {
+        return Err(PyIndexError::new_err(format!(
+            "{} bytes at {:#05X} run past the end of memory",
+            length, address
+        )));
+    }
+    Ok(())
+}
+
+fn check_register(x: usize) -> PyResult<()> {
+    if x > 0xF {
+        return Err(PyIndexError::new_err("register out of range 0 to 15"));
+    }
+    Ok(())
+}
+
+fn screen(core: &Core) -> Array2<u8> {
+    let display = core.display();
+    Array2::from_shape_fn((DISPLAY_HEIGHT, DISPLAY_WIDTH), |(y, x)| {
+        display.get_pixel(x, y)
+    })
+}
+
+#[pymodule]
+#[pyo3(name = "chip8")]
+fn chip8_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Chip8>()?;
+    m.add_class::<Env>()?;
+    m.add_class::<EnvState>()?;
+    m.add("DISPLAY_WIDTH", DISPLAY_WIDTH)?;
+    m.add("DISPLAY_HEIGHT", DISPLAY_HEIGHT)?;
+    m.add("MEMORY_SIZE", MEMORY_SIZE)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8_core::emu::MAX_ROM_SIZE;
+
+    #[test]
+    fn checks_arguments() {
+        let mut chip8 = Chip8::new(1, "schip").unwrap();
+        assert!(Chip8::new(1, "nes").is_err());
+        assert!(chip8.load_rom(&vec![0; MAX_ROM_SIZE + 1]).is_err());
+        assert!(chip8.write_memory(MEMORY_SIZE - 1, &[0, 0]).is_err());
+        assert!(chip8.write_memory(MEMORY_SIZE - 2, &[0, 0]).is_ok());
+        assert!(chip8.set_pc(MEMORY_SIZE as u16 - 1).is_err());
+        assert!(chip8.set_key(16, true).is_err());
+        assert!(chip8.set_register(16, 0).is_err());
+        assert!(chip8.load_state(&[0; 8]).is_err());
+    }
+
+    #[test]
+    fn draws_to_the_screen() {
+        let mut chip8 = Chip8::new(1, "vip").unwrap();
+        chip8
+            .load_rom(include_bytes!("../../roms/test/draw-row.ch8"))
+            .unwrap();
+        chip8.run_frame();
+        assert_eq!(chip8.platform(), "vip");
+        let screen = screen(&chip8.core);
+        assert_eq!(screen.dim(), (DISPLAY_HEIGHT, DISPLAY_WIDTH));
+        assert_eq!(
+            screen.row(0).iter().take(5).collect::<Vec<_>>(),
+            [&1, &1, &1, &1, &0]
+        );
+    }
+}
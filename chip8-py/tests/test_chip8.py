@@ -0,0 +1,95 @@
+"""Run with `maturin develop && pytest` from chip8-py/."""
+
+from pathlib import Path
+
+import numpy as np
+import pytest
+
+import chip8
+
+# Draws a row of four pixels at the top left, sounds the beeper, then loops
+ROM = (Path(__file__).parents[2] / "roms" / "test" / "draw-row.ch8").read_bytes()
+
+
+def test_runs_a_rom_into_the_framebuffer():
+    machine = chip8.Chip8(seed=1)
+    machine.load_rom(ROM)
+    machine.run_frame()
+    assert machine.frame == 1
+    assert machine.pc == 0x208
+    assert machine.i == 0x20C
+    assert machine.sound_active
+
+    screen = machine.framebuffer()
+    assert screen.dtype == np.uint8
+    assert screen.shape == (chip8.DISPLAY_HEIGHT, chip8.DISPLAY_WIDTH)
+    assert screen[0, :5].tolist() == [1, 1, 1, 1, 0]
+    assert screen.sum() == 4
+
+
+def test_step_runs_one_instruction():
+    machine = chip8.Chip8()
+    machine.load_rom(ROM)
+    machine.step()
+    assert machine.pc == 0x202
+    assert machine.frame == 0
+
+
+def test_memory_and_registers():
+    machine = chip8.Chip8(platform="schip")
+    assert machine.platform == "schip"
+    machine.write_memory(0x300, b"\x12\x34")
+    assert machine.read_memory(0x300, 2) == b"\x12\x34"
+    with pytest.raises(IndexError):
+        machine.read_memory(chip8.MEMORY_SIZE - 1, 2)
+
+    machine.set_register(0xA, 0x42)
+    assert machine.get_register(0xA) == 0x42
+    machine.registers = list(range(16))
+    assert machine.registers == bytes(range(16))
+    with pytest.raises(IndexError):
+        machine.set_register(16, 0)
+
+    machine.pc = 0x300
+    machine.i = 0x123
+    machine.delay_timer = 5
+    machine.sound_timer = 3
+    assert (machine.pc, machine.i, machine.delay_timer) == (0x300, 0x123, 5)
+    assert machine.sound_active
+
+
+def test_keys():
+    machine = chip8.Chip8()
+    # SKP V0 then SKNP V0, with V0 = 5
+    machine.load_rom(bytes([0x60, 0x05, 0xE0, 0x9E, 0x00, 0x00, 0xE0, 0xA1]))
+    machine.set_key(5, True)
+    for _ in range(2):
+        machine.step()
+    assert machine.pc == 0x206
+    machine.set_key(5, False)
+    machine.step()
+    assert machine.pc == 0x20A
+    with pytest.raises(ValueError):
+        machine.set_key(16, True)
+
+
+def test_state_roundtrips():
+    machine = chip8.Chip8(seed=7)
+    machine.load_rom(ROM)
+    machine.run_frame()
+    state = machine.save_state()
+
+    machine.load_rom(b"")
+    assert machine.frame == 0
+    machine.load_state(state)
+    assert machine.frame == 1
+    assert machine.framebuffer().sum() == 4
+    with pytest.raises(ValueError):
+        machine.load_state(b"nonsense")
+
+
+def test_bad_arguments():
+    with pytest.raises(ValueError):
+        chip8.Chip8(platform="nes")
+    with pytest.raises(ValueError):
+        chip8.Chip8().load_rom(bytes(4096))
@@ -0,0 +1,57 @@
+import pytest
+
+import chip8
+
+# Counts up in V1 while key 0 is held, keeping the count as BCD at 0x300
+ROM = bytes([0xA3, 0x00, 0xE3, 0xA1, 0x71, 0x01, 0xF1, 0x33, 0x12, 0x02])
+
+
+def make_env(**kwargs):
+    return chip8.Env(ROM, score="bcd:0x300:3", done=["bcd:0x300:3 >= 10"], **kwargs)
+
+
+def test_episode():
+    env = make_env()
+    assert env.action_count == 17
+    observation, info = env.reset(seed=1)
+    assert observation.shape == (chip8.DISPLAY_HEIGHT, chip8.DISPLAY_WIDTH)
+    assert info == {}
+
+    # Action 1 holds key 0; the count goes up twice a frame
+    _, reward, terminated, truncated, info = env.step(1)
+    assert (reward, terminated, truncated) == (8.0, False, False)
+    assert info["frame"] == 4
+    _, reward, terminated, _, info = env.step(1)
+    assert (reward, terminated) == (2.0, True)
+    assert info["frame"] == 5
+
+
+def test_truncation_and_custom_actions():
+    env = make_env(frame_skip=2, max_frames=4, actions=[0b0, 0b1])
+    env.reset()
+    assert env.action_count == 2
+    assert env.step(0)[1:4] == (0.0, False, False)
+    assert env.step(1)[1:4] == (4.0, False, True)
+    with pytest.raises(ValueError):
+        env.step(2)
+
+
+def test_clone_and_restore():
+    env = make_env()
+    env.reset()
+    env.step(0)
+    state = env.clone_state()
+    first = env.step(1)
+    env.restore_state(state)
+    second = env.step(1)
+    assert first[1:] == second[1:]
+    assert (first[0] == second[0]).all()
+
+
+def test_bad_specs():
+    with pytest.raises(ValueError):
+        chip8.Env(ROM, score="bcd:0x2000")
+    with pytest.raises(ValueError):
+        chip8.Env(ROM, done=["bin:0x300 ~ 1"])
+    with pytest.raises(ValueError):
+        chip8.Env(ROM, frame_skip=0)
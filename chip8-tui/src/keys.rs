@@ -0,0 +1,113 @@
+use std::time::{Duration, Instant};
+
+// The keypad on the left of a QWERTY keyboard, as in the window frontend
+const LAYOUT: [(char, usize); 16] = [
+    ('1', 0x1),
+    ('2', 0x2),
+    ('3', 0x3),
+    ('4', 0xC),
+    ('q', 0x4),
+    ('w', 0x5),
+    ('e', 0x6),
+    ('r', 0xD),
+    ('a', 0x7),
+    ('s', 0x8),
+    ('d', 0x9),
+    ('f', 0xE),
+    ('z', 0xA),
+    ('x', 0x0),
+    ('c', 0xB),
+    ('v', 0xF),
+];
+
+/// The CHIP-8 key a typed character stands for
+pub fn keypad_key(c: char) -> Option<usize> {
+    let c = c.to_ascii_lowercase();
+    LAYOUT.iter().find(|(k, _)| *k == c).map(|&(_, key)| key)
+}
+
+/// Most terminals only report key presses, repeated while a key is held.
+/// This keeps a key down for a while after each report and lets it go when
+/// the reports stop.
+pub struct HeldKeys {
+    held: [Option<Hold>; 16],
+    first_hold: Duration,
+    repeat_hold: Duration,
+}
+
+#[derive(Clone, Copy)]
+struct Hold {
+    until: Instant,
+}
+
+impl HeldKeys {
+    /// `first_hold` has to outlast the keyboard's delay before it starts
+    /// repeating, `repeat_hold` only the interval between repeats
+    pub fn new(first_hold: Duration, repeat_hold: Duration) -> Self {
+        HeldKeys {
+            held: [None; 16],
+            first_hold,
+            repeat_hold,
+        }
+    }
+
+    /// A key was reported down; true if it was not held already
+    pub fn press(&mut self, key: usize, now: Instant) -> bool {
+        let hold = match self.held[key] {
+            Some(_) => self.repeat_hold,
+            None => self.first_hold,
+        };
+        let pressed = self.held[key].is_none();
+        self.held[key] = Some(Hold { until: now + hold });
+        pressed
+    }
+
+    /// A key was reported up, by a terminal that does so; true if it was held
+    pub fn release(&mut self, key: usize) -> bool {
+        self.held[key].take().is_some()
+    }
+
+    /// Let go of the keys whose reports stopped, returning them
+    pub fn expire(&mut self, now: Instant) -> Vec<usize> {
+        let mut released = Vec::new();
+        for (key, hold) in self.held.iter_mut().enumerate() {
+            if hold.is_some_and(|hold| hold.until <= now) {
+                *hold = None;
+                released.push(key);
+            }
+        }
+        released
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layout_matches_the_keypad() {
+        assert_eq!(keypad_key('4'), Some(0xC));
+        assert_eq!(keypad_key('X'), Some(0x0));
+        assert_eq!(keypad_key('v'), Some(0xF));
+        assert_eq!(keypad_key(' '), None);
+    }
+
+    #[test]
+    fn keys_are_released_when_reports_stop() {
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        let mut keys = HeldKeys::new(ms(500), ms(100));
+        assert!(keys.press(0x5, start));
+        // Held through the delay before the keyboard starts repeating
+        assert!(keys.expire(start + ms(400)).is_empty());
+        assert!(!keys.press(0x5, start + ms(450)));
+        assert!(keys.expire(start + ms(520)).is_empty());
+        // The repeats stopped: let go soon after the last one
+        assert_eq!(keys.expire(start + ms(550)), [0x5]);
+        assert!(!keys.release(0x5));
+
+        keys.press(0x1, start);
+        assert!(keys.release(0x1));
+        assert!(keys.expire(start + ms(600)).is_empty());
+    }
+}
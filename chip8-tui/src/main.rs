@@ -0,0 +1,255 @@
+//! Terminal frontend, for machines reached over SSH without a display.
+
+mod keys;
+mod panel;
+mod screen;
+
+use std::io::{self, Write};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{
+    self, Event, KeyCode, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::style::Print;
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+
+use chip8_core::emu::Chip8;
+use chip8_core::palette::Palette;
+use chip8_core::platform::Platform;
+use chip8_core::timing::Timing;
+
+use keys::{keypad_key, HeldKeys};
+use panel::panel_lines;
+use screen::Glyphs;
+
+const USAGE: &str = "usage: chip8-tui ROM [options]
+
+Plays ROM in the terminal. Keys 1-4, Q-R, A-F and Z-V are the keypad, Tab
+shows or hides the register panel, Space pauses and Esc quits.
+
+  --platform vip|schip    platform to emulate (default vip)
+  --timing fixed|vip      fixed instructions per frame, or the COSMAC VIP's
+                            machine cycle budget (default fixed)
+  --seed N                seed for the random number generator (default: the clock)
+  --braille               draw 2x4 pixels per character with braille patterns
+                            instead of 1x2 with half blocks
+  --palette NAME          default, green-phosphor, amber, lcd, high-contrast or octo
+  --panel                 start with the register and disassembly panel shown
+  --hold-ms N             how long a key stays down after the terminal reports
+                            it, when it does not report releases (default 500)";
+
+// Once a key repeats, it is let go this long after the last repeat
+const REPEAT_HOLD: Duration = Duration::from_millis(100);
+
+const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+struct Options {
+    rom: String,
+    platform: Platform,
+    timing: Timing,
+    seed: Option<u64>,
+    glyphs: Glyphs,
+    palette: Palette,
+    panel: bool,
+    hold: Duration,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut rom = None;
+    let mut options = Options {
+        rom: String::new(),
+        platform: Platform::default(),
+        timing: Timing::default(),
+        seed: None,
+        glyphs: Glyphs::HalfBlock,
+        palette: Palette::default(),
+        panel: false,
+        hold: Duration::from_millis(500),
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--platform" => options.platform = value()?.parse()?,
+            "--timing" => options.timing = value()?.parse()?,
+            "--seed" => options.seed = Some(value()?.parse().map_err(|_| "bad seed")?),
+            "--braille" => options.glyphs = Glyphs::Braille,
+            "--palette" => {
+                let name = value()?;
+                options.palette =
+                    Palette::builtin(&name).ok_or(format!("unknown palette '{}'", name))?
+            }
+            "--panel" => options.panel = true,
+            "--hold-ms" => {
+                let ms = value()?.parse().map_err(|_| "bad hold time")?;
+                options.hold = Duration::from_millis(ms)
+            }
+            _ if !arg.starts_with("--") && rom.is_none() => rom = Some(arg),
+            _ => return Err(format!("unknown option {}", arg)),
+        }
+    }
+    options.rom = rom.ok_or("no ROM given")?;
+    Ok(options)
+}
+
+/// Raw mode and the alternate screen for as long as it lives, so the
+/// terminal is restored however the emulator exits
+struct Terminal {
+    // The terminal reports key releases itself
+    releases: bool,
+}
+
+impl Terminal {
+    fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        let releases = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        let mut stdout = io::stdout();
+        execute!(stdout, EnterAlternateScreen, Hide, Clear(ClearType::All))?;
+        if releases {
+            execute!(
+                stdout,
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+        Ok(Terminal { releases })
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+        if self.releases {
+            let _ = execute!(stdout, PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(stdout, Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn main() {
+    let options = parse_args().unwrap_or_else(|e| {
+        eprintln!("{}\n{}", e, USAGE);
+        std::process::exit(2);
+    });
+    let rom = std::fs::read(&options.rom).unwrap_or_else(|e| {
+        eprintln!("Failed to read {}: {}", options.rom, e);
+        std::process::exit(1);
+    });
+
+    let mut chip8 = Chip8::new();
+    chip8.set_platform(options.platform);
+    chip8.set_timing(options.timing);
+    chip8.set_seed(options.seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64)
+    }));
+    if let Err(e) = chip8.try_load_rom_bytes(&rom) {
+        eprintln!("Failed to load {}: {}", options.rom, e);
+        std::process::exit(1);
+    }
+
+    if let Err(e) = run(&mut chip8, &options) {
+        eprintln!("Terminal error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run(chip8: &mut Chip8, options: &Options) -> io::Result<()> {
+    let terminal = Terminal::enter()?;
+    let mut stdout = io::stdout();
+    let mut keys = HeldKeys::new(options.hold, REPEAT_HOLD);
+    let mut panel = options.panel;
+    let mut paused = false;
+    let mut redraw = true;
+    let mut beeping = false;
+
+    let mut next_frame = Instant::now();
+    loop {
+        // Handle input until the next frame is due
+        while event::poll(next_frame.saturating_duration_since(Instant::now()))? {
+            match event::read()? {
+                Event::Key(key) => {
+                    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+                    match (key.code, key.kind) {
+                        (KeyCode::Esc, _) => return Ok(()),
+                        (KeyCode::Char('c'), _) if ctrl => return Ok(()),
+                        (KeyCode::Tab, KeyEventKind::Press) => {
+                            panel = !panel;
+                            redraw = true;
+                        }
+                        (KeyCode::Char(' '), KeyEventKind::Press) => paused = !paused,
+                        (KeyCode::Char(c), kind) => {
+                            let Some(k) = keypad_key(c) else {
+                                continue;
+                            };
+                            if kind == KeyEventKind::Release {
+                                if keys.release(k) {
+                                    chip8.key_mut().release_key(k);
+                                }
+                            } else if keys.press(k, Instant::now()) {
+                                chip8.key_mut().press_key(k);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                Event::Resize(..) => redraw = true,
+                _ => {}
+            }
+        }
+        // Skip frames rather than rush to catch up after a stall
+        next_frame = (next_frame + FRAME).max(Instant::now());
+
+        if !terminal.releases {
+            for k in keys.expire(Instant::now()) {
+                chip8.key_mut().release_key(k);
+            }
+        }
+        if !paused {
+            chip8.run_frame();
+        }
+
+        // The terminal bell is the closest thing to a beeper
+        if chip8.sound_active() && !beeping {
+            queue!(stdout, Print('\x07'))?;
+        }
+        beeping = chip8.sound_active();
+
+        draw(&mut stdout, chip8, options, panel, redraw)?;
+        redraw = false;
+    }
+}
+
+/// Rewrite the rows of the screen that changed, and the panel if shown
+fn draw(
+    out: &mut impl Write,
+    chip8: &mut Chip8,
+    options: &Options,
+    panel: bool,
+    redraw: bool,
+) -> io::Result<()> {
+    let glyphs = options.glyphs;
+    let (columns, rows) = glyphs.text_size(chip8.display());
+    let dirty = chip8.display_mut().take_dirty_rects();
+    let rows: Vec<usize> = if redraw {
+        queue!(out, Clear(ClearType::All))?;
+        (0..rows).collect()
+    } else {
+        glyphs.dirty_rows(&dirty).into_iter().collect()
+    };
+    let colors = options.palette.colors();
+    for row in rows {
+        let line = glyphs.render_row(chip8.display(), row, colors);
+        queue!(out, MoveTo(0, row as u16), Print(line))?;
+    }
+    if panel {
+        for (row, line) in panel_lines(chip8).into_iter().enumerate() {
+            queue!(out, MoveTo(columns as u16 + 2, row as u16), Print(line))?;
+        }
+    }
+    out.flush()
+}
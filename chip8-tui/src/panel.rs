@@ -0,0 +1,63 @@
+use chip8_core::disasm::disassemble_range;
+use chip8_core::emu::Chip8;
+
+/// Columns taken by the panel, so shorter lines can be padded over old text
+pub const PANEL_WIDTH: usize = 28;
+
+// Instructions listed before and after the one at the PC
+const LISTING_BEFORE: usize = 3;
+const LISTING_AFTER: usize = 6;
+
+/// The registers and the code around the PC, one line of text each
+pub fn panel_lines(chip8: &Chip8) -> Vec<String> {
+    let mut lines = vec![
+        format!("PC {:04X}  I {:04X}", chip8.pc(), chip8.i()),
+        format!(
+            "DT {:02X}  ST {:02X}  SP {:X}",
+            chip8.delay_timer(),
+            chip8.sound_timer(),
+            chip8.stack().len()
+        ),
+    ];
+    for (n, values) in chip8.registers().chunks(4).enumerate() {
+        let line: Vec<String> = values
+            .iter()
+            .enumerate()
+            .map(|(m, v)| format!("V{:X} {:02X}", n * 4 + m, v))
+            .collect();
+        lines.push(line.join("  "));
+    }
+    lines.push(String::new());
+
+    let pc = chip8.pc() as usize;
+    let start = pc.saturating_sub(LISTING_BEFORE * 2);
+    let listing = disassemble_range(chip8.memory(), start, LISTING_BEFORE + 1 + LISTING_AFTER);
+    for (address, text) in listing {
+        let marker = if address == pc { '>' } else { ' ' };
+        lines.push(format!("{} {:03X}  {}", marker, address, text));
+    }
+    lines
+        .into_iter()
+        .map(|line| format!("{:width$.width$}", line, width = PANEL_WIDTH))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn panel_marks_the_pc() {
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&[0x60, 0x2A, 0x12, 0x00]);
+        chip8.emulate_cycle();
+        let lines = panel_lines(&chip8);
+        assert!(lines.iter().all(|line| line.chars().count() == PANEL_WIDTH));
+        assert!(lines[0].starts_with("PC 0202"));
+        assert!(lines[2].starts_with("V0 2A  V1 00"));
+        assert!(lines
+            .iter()
+            .any(|line| line.starts_with("  200  LD V0, 0x2A")));
+        assert!(lines.iter().any(|line| line.starts_with("> 202  JP 0x200")));
+    }
+}
@@ -0,0 +1,178 @@
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use chip8_core::display::{Display, Rect, Rgb};
+
+const RESET: &str = "\x1b[0m";
+
+/// How CHIP-8 pixels are packed into terminal character cells
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Glyphs {
+    /// `▀` with the top pixel as foreground and the bottom one as background:
+    /// 1x2 pixels per cell, each in its own colour
+    HalfBlock,
+    /// Braille patterns: 2x4 pixels per cell, but only one colour for the lit
+    /// ones
+    Braille,
+}
+
+impl Glyphs {
+    /// Pixels covered by one cell, as `(width, height)`
+    pub fn cell_size(self) -> (usize, usize) {
+        match self {
+            Glyphs::HalfBlock => (1, 2),
+            Glyphs::Braille => (2, 4),
+        }
+    }
+
+    /// Terminal `(columns, rows)` taken by `display`
+    pub fn text_size(self, display: &Display) -> (usize, usize) {
+        let (width, height) = self.cell_size();
+        (
+            display.width().div_ceil(width),
+            display.height().div_ceil(height),
+        )
+    }
+
+    /// One row of text, with ANSI colour escapes, showing the pixels under it
+    pub fn render_row(self, display: &Display, row: usize, palette: &[Rgb]) -> String {
+        let (columns, _) = self.text_size(display);
+        let mut line = String::new();
+        let mut colors = None;
+        for column in 0..columns {
+            let (glyph, fg, bg) = match self {
+                Glyphs::HalfBlock => {
+                    let top = pixel(display, column, row * 2);
+                    let bottom = pixel(display, column, row * 2 + 1);
+                    ('▀', color(palette, top), color(palette, bottom))
+                }
+                Glyphs::Braille => {
+                    let mut dots = [[0; 2]; 4];
+                    for (dy, line) in dots.iter_mut().enumerate() {
+                        for (dx, dot) in line.iter_mut().enumerate() {
+                            *dot = pixel(display, column * 2 + dx, row * 4 + dy);
+                        }
+                    }
+                    // Lit dots take the colour of the highest pixel value among them
+                    let lit = dots.iter().flatten().copied().max().unwrap_or(0).max(1);
+                    (braille(&dots), color(palette, lit), palette[0])
+                }
+            };
+            // Only switch colours where they change
+            if colors != Some((fg, bg)) {
+                let _ = write!(
+                    line,
+                    "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m",
+                    fg[0], fg[1], fg[2], bg[0], bg[1], bg[2]
+                );
+                colors = Some((fg, bg));
+            }
+            line.push(glyph);
+        }
+        line.push_str(RESET);
+        line
+    }
+
+    /// Text rows that need redrawing after the pixels in `rects` changed
+    pub fn dirty_rows(self, rects: &[Rect]) -> BTreeSet<usize> {
+        let (_, height) = self.cell_size();
+        rects
+            .iter()
+            .filter(|rect| rect.height > 0)
+            .flat_map(|rect| rect.y / height..=(rect.y + rect.height - 1) / height)
+            .collect()
+    }
+}
+
+/// The braille pattern with a dot for every lit pixel of a 2x4 block, given
+/// row by row
+fn braille(dots: &[[u8; 2]; 4]) -> char {
+    // Unicode numbers the dots down the left column first, with the bottom
+    // row added last
+    const BITS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+    let mut pattern = 0;
+    for (line, bits) in dots.iter().zip(BITS) {
+        for (&dot, bit) in line.iter().zip(bits) {
+            if dot != 0 {
+                pattern |= bit;
+            }
+        }
+    }
+    char::from_u32(0x2800 + pattern).unwrap()
+}
+
+// Pixels past the edge of a display that does not fill whole cells are dark
+fn pixel(display: &Display, x: usize, y: usize) -> u8 {
+    if x < display.width() && y < display.height() {
+        display.get_pixel(x, y)
+    } else {
+        0
+    }
+}
+
+fn color(palette: &[Rgb], pixel: u8) -> Rgb {
+    palette[(pixel as usize).min(palette.len() - 1)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PALETTE: [Rgb; 2] = [[0, 0, 0], [255, 255, 255]];
+
+    // The glyphs of a rendered row, without the colour escapes
+    fn glyphs(line: &str) -> String {
+        let mut text = String::new();
+        let mut chars = line.chars();
+        while let Some(c) = chars.next() {
+            if c == '\x1b' {
+                chars.by_ref().find(|&c| c == 'm');
+            } else {
+                text.push(c);
+            }
+        }
+        text
+    }
+
+    #[test]
+    fn braille_dots() {
+        assert_eq!(braille(&[[0; 2]; 4]), '⠀');
+        assert_eq!(braille(&[[1, 0], [0, 0], [0, 0], [0, 0]]), '⠁');
+        assert_eq!(braille(&[[0, 0], [0, 0], [0, 0], [0, 1]]), '⢀');
+        assert_eq!(braille(&[[1; 2]; 4]), '⣿');
+    }
+
+    #[test]
+    fn rows_show_their_pixels() {
+        let mut display = Display::new();
+        display.update_pixel(0, 0, 1);
+        display.update_pixel(3, 1, 1);
+
+        assert_eq!(Glyphs::HalfBlock.text_size(&display), (64, 16));
+        let row = Glyphs::HalfBlock.render_row(&display, 0, &PALETTE);
+        assert_eq!(glyphs(&row).chars().count(), 64);
+        // Lit over dark, then dark over dark: the colours switch once more
+        assert!(row.starts_with("\x1b[38;2;255;255;255m\x1b[48;2;0;0;0m▀\x1b[38;2;0;0;0m"));
+
+        assert_eq!(Glyphs::Braille.text_size(&display), (32, 8));
+        let row = glyphs(&Glyphs::Braille.render_row(&display, 0, &PALETTE));
+        assert!(row.starts_with("⠁⠐⠀"));
+    }
+
+    #[test]
+    fn dirty_rects_map_to_text_rows() {
+        let rect = Rect {
+            x: 0,
+            y: 3,
+            width: 8,
+            height: 6,
+        };
+        let rows: Vec<usize> = Glyphs::HalfBlock.dirty_rows(&[rect]).into_iter().collect();
+        assert_eq!(rows, [1, 2, 3, 4]);
+        let rows: Vec<usize> = Glyphs::Braille
+            .dirty_rows(&[rect, Rect::SCREEN])
+            .into_iter()
+            .collect();
+        assert_eq!(rows, (0..8).collect::<Vec<_>>());
+    }
+}
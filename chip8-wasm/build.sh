@@ -0,0 +1,6 @@
+#!/bin/sh
+# Builds the JavaScript package into www/pkg. Serve www/ with any static file
+# server and open index.html; browsers refuse to load wasm from file:// URLs.
+set -e
+cd "$(dirname "$0")"
+wasm-pack build --target web --out-dir www/pkg --no-typescript
@@ -0,0 +1,219 @@
+//! JavaScript API for embedding the emulator in a web page.
+//!
+//! `build.sh` turns this crate into an ES module under `www/pkg`, which
+//! `www/index.html` uses:
+//!
+//! ```js
+//! import init, { Emulator } from "./pkg/chip8_wasm.js";
+//! const { memory } = await init();
+//! const emulator = new Emulator(Date.now());
+//! emulator.load_rom(new Uint8Array(await rom.arrayBuffer()));
+//! emulator.run_frame();
+//! const pixels = new Uint8ClampedArray(
+//!     memory.buffer, emulator.framebuffer(), emulator.framebuffer_len());
+//! context.putImageData(new ImageData(pixels, emulator.width()), 0, 0);
+//! ```
+
+use wasm_bindgen::prelude::*;
+
+use chip8_core::emu::{Chip8, RomTooLarge};
+use chip8_core::palette::Palette;
+use chip8_core::platform::Platform;
+
+#[wasm_bindgen]
+pub struct Emulator {
+    chip8: Chip8,
+    platform: Platform,
+    seed: u64,
+    palette: Palette,
+    // RGBA of the last frame handed out, read by JavaScript straight from
+    // the module's memory
+    framebuffer: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl Emulator {
+    /// An emulator with no ROM loaded. JavaScript numbers cannot hold every
+    /// `u64`, so the seed is taken as a double and truncated.
+    #[wasm_bindgen(constructor)]
+    pub fn new(seed: f64) -> Emulator {
+        let mut emulator = Emulator {
+            chip8: Chip8::new(),
+            platform: Platform::default(),
+            seed: seed as u64,
+            palette: Palette::default(),
+            framebuffer: Vec::new(),
+        };
+        emulator.reset(&[]).unwrap();
+        emulator
+    }
+
+    /// Power on afresh with `rom` loaded. A ROM too long for memory is an
+    /// error and leaves the running machine alone.
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), JsError> {
+        self.reset(rom)?;
+        Ok(())
+    }
+
+    /// `vip` or `schip`; takes effect at the next `load_rom`
+    pub fn set_platform(&mut self, name: &str) -> Result<(), JsError> {
+        self.platform = name.parse().map_err(|e: String| JsError::new(&e))?;
+        Ok(())
+    }
+
+    /// One of the built-in palettes used for `framebuffer`
+    pub fn set_palette(&mut self, name: &str) -> Result<(), JsError> {
+        self.palette = Palette::builtin(name)
+            .ok_or_else(|| JsError::new(&format!("unknown palette '{}'", name)))?;
+        Ok(())
+    }
+
+    /// Run one 60 Hz frame
+    pub fn run_frame(&mut self) {
+        self.chip8.run_frame();
+    }
+
+    pub fn frame(&self) -> f64 {
+        self.chip8.frame() as f64
+    }
+
+    pub fn width(&self) -> usize {
+        self.chip8.display().width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.chip8.display().height()
+    }
+
+    /// Render the screen as RGBA and return where it starts in the module's
+    /// memory. The pointer is only good until the next call into the module.
+    pub fn framebuffer(&mut self) -> *const u8 {
+        self.framebuffer = self
+            .chip8
+            .display()
+            .to_rgba(self.palette.colors())
+            .expect("palettes have at least two colours");
+        self.framebuffer.as_ptr()
+    }
+
+    /// Size in bytes of the picture `framebuffer` points to
+    pub fn framebuffer_len(&self) -> usize {
+        self.width() * self.height() * 4
+    }
+
+    /// Press or release keypad key 0 to F
+    pub fn set_key(&mut self, key: u8, pressed: bool) -> Result<(), JsError> {
+        let key = keypad_key(key).map_err(|e| JsError::new(&e))?;
+        self.chip8.key_mut().set_key(key, pressed);
+        Ok(())
+    }
+
+    pub fn sound_active(&self) -> bool {
+        self.chip8.sound_active()
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        self.chip8.save_state()
+    }
+
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), JsError> {
+        self.chip8.load_state(state)?;
+        Ok(())
+    }
+}
+
+impl Emulator {
+    fn reset(&mut self, rom: &[u8]) -> Result<(), RomTooLarge> {
+        let mut chip8 = Chip8::new();
+        chip8.set_platform(self.platform);
+        chip8.set_seed(self.seed);
+        chip8.try_load_rom_bytes(rom)?;
+        self.chip8 = chip8;
+        Ok(())
+    }
+}
+
+fn keypad_key(key: u8) -> Result<usize, String> {
+    if key > 0xF {
+        return Err(format!("no keypad key {:#X}", key));
+    }
+    Ok(key as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Draws a row of four pixels at the top left, sounds the beeper, then loops
+    const ROM: &[u8] = include_bytes!("../../roms/test/draw-row.ch8");
+
+    fn pixel(emulator: &mut Emulator, x: usize, y: usize) -> [u8; 4] {
+        let len = emulator.framebuffer_len();
+        let ptr = emulator.framebuffer();
+        // As JavaScript would read it, straight from memory
+        let rgba = unsafe { std::slice::from_raw_parts(ptr, len) };
+        let n = (y * emulator.width() + x) * 4;
+        rgba[n..n + 4].try_into().unwrap()
+    }
+
+    #[test]
+    fn runs_a_rom_into_the_framebuffer() {
+        let mut emulator = Emulator::new(1.0);
+        emulator.load_rom(ROM).unwrap();
+        emulator.run_frame();
+        assert_eq!(emulator.frame(), 1.0);
+        assert_eq!(emulator.framebuffer_len(), 64 * 32 * 4);
+        let palette = Palette::default();
+        let [r, g, b] = palette.colors()[1];
+        assert_eq!(pixel(&mut emulator, 0, 0), [r, g, b, 0xFF]);
+        let [r, g, b] = palette.colors()[0];
+        assert_eq!(pixel(&mut emulator, 5, 0), [r, g, b, 0xFF]);
+
+        emulator.set_palette("amber").unwrap();
+        let [r, g, b] = Palette::builtin("amber").unwrap().colors()[1];
+        assert_eq!(pixel(&mut emulator, 0, 0), [r, g, b, 0xFF]);
+    }
+
+    #[test]
+    fn state_roundtrips() {
+        let mut emulator = Emulator::new(1.0);
+        emulator.load_rom(ROM).unwrap();
+        emulator.run_frame();
+        let state = emulator.save_state();
+
+        emulator.load_rom(&[]).unwrap();
+        assert_eq!(emulator.frame(), 0.0);
+        emulator.load_state(&state).unwrap();
+        assert_eq!(emulator.frame(), 1.0);
+        assert_eq!(emulator.save_state(), state);
+    }
+
+    #[test]
+    fn keys_and_sound() {
+        let mut emulator = Emulator::new(1.0);
+        // LD V0, 0x10; LD ST, V0; then loop
+        emulator
+            .load_rom(&[0x60, 0x10, 0xF0, 0x18, 0x12, 0x04])
+            .unwrap();
+        emulator.run_frame();
+        assert!(emulator.sound_active());
+
+        emulator.set_key(0xA, true).unwrap();
+        assert!(emulator.chip8.key_mut().key_is_pressed(0xA));
+        emulator.set_key(0xA, false).unwrap();
+        assert!(!emulator.chip8.key_mut().key_is_pressed(0xA));
+        // JsError only exists in the browser, so check what `set_key` rejects
+        assert_eq!(keypad_key(0xF), Ok(0xF));
+        assert!(keypad_key(0x1A).is_err());
+    }
+
+    #[test]
+    fn oversized_rom_keeps_the_running_machine() {
+        let mut emulator = Emulator::new(1.0);
+        emulator.load_rom(ROM).unwrap();
+        emulator.run_frame();
+        // JsError only exists in the browser, so go through `reset`
+        assert_eq!(emulator.reset(&[0; 0xE01]), Err(RomTooLarge { len: 0xE01 }));
+        assert_eq!(emulator.frame(), 1.0);
+    }
+}
@@ -0,0 +1,412 @@
+//! Frontend settings stored as TOML.
+//!
+//! ```toml
+//! # Gamepad preset used unless a ROM picks another (see `chip8_core::gamepad::PRESETS`)
+//! preset = "dpad-2468"
+//!
+//! # Starting palette: one of the built-in ones (default, green-phosphor,
+//! # amber, lcd, high-contrast, octo) or one defined under [palettes]
+//! palette = "amber"
+//!
+//! # How the screen fills the window: "integer", "fit" or "stretch"
+//! scale = "integer"
+//! fullscreen = false
+//!
+//! # Filter against flicker: "off", "persist", "blend" or "decay", and how
+//! # many frames "blend" averages (2 to 8)
+//! deflicker = "blend"
+//! blend_frames = 3
+//!
+//! # Extra palettes of 2 to 16 colours, indexed by pixel value
+//! [palettes]
+//! ice = ["#0B1C2C", "#CFEFFF"]
+//!
+//! # CHIP-8 key (hex digit) = host inputs: keys named as in piston's `Key`,
+//! # or "Button0", "Axis1+"/"Axis1-" and "HatUp" for gamepads
+//! [keys]
+//! 5 = ["W", "Up", "Button0"]
+//! 8 = ["S", "Down"]
+//!
+//! # Overrides for a single ROM, matched by file name
+//! [roms."ghosts.ch8"]
+//! preset = "wasd-5789"
+//! palette = "ice"
+//!
+//! [roms."ghosts.ch8".keys]
+//! 5 = ["Space"]
+//! ```
+//!
+//! Keys that are not listed keep the default QWERTY layout. Well known games
+//! get a gamepad preset even without a `[roms]` entry. The frontend's hotkeys
+//! (F1, F2, F3, F4, F9, F11, F12, Backspace) take precedence over any binding;
+//! F2 cycles through all palettes, F3 through the scale modes, F4 through the
+//! flicker filters and F11 toggles fullscreen.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use chip8_core::gamepad;
+use chip8_core::palette::{parse_color, Palette};
+
+use crate::deflicker::{FlickerMode, DEFAULT_BLEND_FRAMES, MAX_BLEND_FRAMES};
+use crate::keymap::{Input, KeyMap};
+use crate::layout::ScaleMode;
+
+pub const DEFAULT_CONFIG_PATH: &str = "chip8.toml";
+
+type Bindings = BTreeMap<String, Vec<Input>>;
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    preset: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    palette: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scale: Option<ScaleMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fullscreen: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    deflicker: Option<FlickerMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    blend_frames: Option<usize>,
+    keys: Bindings,
+    palettes: BTreeMap<String, Vec<String>>,
+    roms: BTreeMap<String, RomConfig>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+struct RomConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    preset: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    palette: Option<String>,
+    keys: Bindings,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "{}", e),
+            ConfigError::Parse(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> Self {
+        ConfigError::Io(e)
+    }
+}
+
+impl Config {
+    /// Read `path`, or fall back to the defaults if it does not exist
+    pub fn load_or_default(path: &Path) -> Result<Self, ConfigError> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => text.parse(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ConfigError> {
+        let text = toml::to_string(self).map_err(|e| ConfigError::Parse(e.to_string()))?;
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+
+    /// The bindings for `rom`. Each step overrides the ones before it: the
+    /// defaults, the global preset and keys, then the ROM's preset (configured
+    /// or built in) and keys.
+    pub fn key_map(&self, rom: &Path) -> KeyMap {
+        let mut map = KeyMap::default();
+        if let Some(preset) = self.preset.as_deref().and_then(gamepad::preset) {
+            map.apply_preset(preset);
+        }
+        apply_bindings(&mut map, &self.keys);
+
+        let rom_config = self.rom_config(rom);
+        let rom_preset = match rom_config.and_then(|rom| rom.preset.as_deref()) {
+            Some(name) => gamepad::preset(name),
+            None => gamepad::rom_preset(&rom_name(rom)),
+        };
+        if let Some(preset) = rom_preset {
+            map.apply_preset(preset);
+        }
+        if let Some(rom) = rom_config {
+            apply_bindings(&mut map, &rom.keys);
+        }
+        map
+    }
+
+    /// Store `map` as the bindings for `rom`
+    pub fn set_key_map(&mut self, rom: &Path, map: &KeyMap) {
+        let keys = (0..16)
+            .map(|key| (format!("{:X}", key), map.bindings(key).to_vec()))
+            .collect();
+        let name = rom_name(rom);
+        self.roms.entry(name).or_default().keys = keys;
+    }
+
+    /// The built-in palettes followed by the configured ones; a configured
+    /// palette with a built-in name takes its place
+    pub fn palettes(&self) -> Vec<Palette> {
+        let mut palettes = Palette::builtins();
+        for (name, colors) in &self.palettes {
+            // `validate` already checked every palette
+            let Ok(palette) = colors
+                .iter()
+                .map(|c| parse_color(c))
+                .collect::<Result<Vec<_>, _>>()
+                .and_then(|colors| Palette::new(name, colors))
+            else {
+                continue;
+            };
+            match palettes.iter_mut().find(|p| p.name() == name) {
+                Some(builtin) => *builtin = palette,
+                None => palettes.push(palette),
+            }
+        }
+        palettes
+    }
+
+    /// Name of the palette to start `rom` with, if one is configured
+    pub fn palette_name(&self, rom: &Path) -> Option<&str> {
+        self.rom_config(rom)
+            .and_then(|rom| rom.palette.as_deref())
+            .or(self.palette.as_deref())
+    }
+
+    pub fn scale_mode(&self) -> ScaleMode {
+        self.scale.unwrap_or_default()
+    }
+
+    pub fn fullscreen(&self) -> bool {
+        self.fullscreen.unwrap_or(false)
+    }
+
+    pub fn flicker_mode(&self) -> FlickerMode {
+        self.deflicker.unwrap_or_default()
+    }
+
+    pub fn blend_frames(&self) -> usize {
+        self.blend_frames.unwrap_or(DEFAULT_BLEND_FRAMES)
+    }
+
+    fn rom_config(&self, rom: &Path) -> Option<&RomConfig> {
+        self.roms.get(&rom_name(rom))
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        let mut presets = std::iter::once(&self.preset)
+            .chain(self.roms.values().map(|rom| &rom.preset))
+            .flatten();
+        if let Some(bad) = presets.find(|name| gamepad::preset(name).is_none()) {
+            return Err(ConfigError::Parse(format!(
+                "unknown gamepad preset '{}'",
+                bad
+            )));
+        }
+
+        if let Some(frames) = self.blend_frames {
+            if !(2..=MAX_BLEND_FRAMES).contains(&frames) {
+                return Err(ConfigError::Parse(format!(
+                    "blend_frames must be 2 to {}, not {}",
+                    MAX_BLEND_FRAMES, frames
+                )));
+            }
+        }
+
+        for (name, colors) in &self.palettes {
+            let colors = colors
+                .iter()
+                .map(|c| parse_color(c))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| ConfigError::Parse(format!("[palettes]: {}", e)))?;
+            Palette::new(name, colors).map_err(ConfigError::Parse)?;
+        }
+        let palettes = self.palettes();
+        let mut names = std::iter::once(&self.palette)
+            .chain(self.roms.values().map(|rom| &rom.palette))
+            .flatten();
+        if let Some(bad) = names.find(|name| !palettes.iter().any(|p| p.name() == *name)) {
+            return Err(ConfigError::Parse(format!("unknown palette '{}'", bad)));
+        }
+
+        let tables = std::iter::once(("keys".to_string(), &self.keys)).chain(
+            self.roms
+                .iter()
+                .map(|(name, rom)| (format!("roms.\"{}\".keys", name), &rom.keys)),
+        );
+        for (table, keys) in tables {
+            if let Some(bad) = keys.keys().find(|k| chip8_key(k).is_none()) {
+                return Err(ConfigError::Parse(format!(
+                    "[{}]: '{}' is not a CHIP-8 key (0 to F)",
+                    table, bad
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for Config {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let config: Config = toml::from_str(s).map_err(|e| ConfigError::Parse(e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+fn rom_name(rom: &Path) -> String {
+    rom.file_name()
+        .unwrap_or(rom.as_os_str())
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn chip8_key(name: &str) -> Option<usize> {
+    usize::from_str_radix(name, 16).ok().filter(|&k| k <= 0xF)
+}
+
+fn apply_bindings(map: &mut KeyMap, bindings: &Bindings) {
+    for (name, keys) in bindings {
+        if let Some(key) = chip8_key(name) {
+            map.set_bindings(key, keys.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::keymap::Direction;
+    use piston_window::Key;
+
+    const CONFIG: &str = r#"
+[keys]
+5 = ["W", "Up"]
+
+[roms."ghosts.ch8"]
+preset = "wasd-5789"
+
+[roms."ghosts.ch8".keys]
+5 = ["Space"]
+a = ["Return", "Button2"]
+"#;
+
+    #[test]
+    fn rom_overrides_apply_on_top_of_global_bindings() {
+        let config: Config = CONFIG.parse().unwrap();
+
+        let global = config.key_map(Path::new("roms/maze.ch8"));
+        assert_eq!(
+            global.bindings(0x5),
+            &[Input::Key(Key::W), Input::Key(Key::Up)]
+        );
+        assert_eq!(global.lookup(Input::Key(Key::Q)), Some(0x4));
+        assert_eq!(global.lookup(Input::Hat(Direction::Up)), Some(0x2));
+
+        let ghosts = config.key_map(Path::new("roms/ghosts.ch8"));
+        assert_eq!(ghosts.bindings(0x5), &[Input::Key(Key::Space)]);
+        assert_eq!(ghosts.lookup(Input::Key(Key::Return)), Some(0xA));
+        assert_eq!(ghosts.lookup(Input::Button(2)), Some(0xA));
+        assert_eq!(ghosts.lookup(Input::Hat(Direction::Left)), Some(0x7));
+        assert_eq!(ghosts.lookup(Input::Key(Key::Z)), None);
+
+        // No entry, but a built-in preset
+        let pong = config.key_map(Path::new("roms/pong.ch8"));
+        assert_eq!(pong.lookup(Input::Hat(Direction::Up)), Some(0x1));
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert!("[keys]\n5 = [\"NoSuchKey\"]".parse::<Config>().is_err());
+        assert!("[keys]\n10 = [\"W\"]".parse::<Config>().is_err());
+        assert!("preset = \"nope\"".parse::<Config>().is_err());
+    }
+
+    #[test]
+    fn palettes_from_config() {
+        let config: Config = r##"
+palette = "amber"
+
+[palettes]
+ice = ["#0B1C2C", "#CFEFFF"]
+lcd = ["#000000", "#FFFFFF"]
+
+[roms."ghosts.ch8"]
+palette = "ice"
+"##
+        .parse()
+        .unwrap();
+        let palettes = config.palettes();
+        let names: Vec<&str> = palettes.iter().map(Palette::name).collect();
+        assert_eq!(names.last(), Some(&"ice"));
+        assert_eq!(names.len(), Palette::builtins().len() + 1);
+        let lcd = palettes.iter().find(|p| p.name() == "lcd").unwrap();
+        assert_eq!(lcd.colors(), &[[0, 0, 0], [255, 255, 255]]);
+
+        assert_eq!(config.palette_name(Path::new("ghosts.ch8")), Some("ice"));
+        assert_eq!(config.palette_name(Path::new("maze.ch8")), Some("amber"));
+
+        assert!("palette = \"nope\"".parse::<Config>().is_err());
+        assert!("scale = \"zoom\"".parse::<Config>().is_err());
+        assert!("[palettes]\nbad = [\"#FFF\", \"#000\"]"
+            .parse::<Config>()
+            .is_err());
+        assert!("[palettes]\nsolo = [\"#FFFFFF\"]"
+            .parse::<Config>()
+            .is_err());
+    }
+
+    #[test]
+    fn flicker_filter_from_config() {
+        let config: Config = "deflicker = \"decay\"".parse().unwrap();
+        assert_eq!(config.flicker_mode(), FlickerMode::Decay);
+        assert_eq!(config.blend_frames(), DEFAULT_BLEND_FRAMES);
+        assert_eq!(Config::default().flicker_mode(), FlickerMode::Off);
+        assert!("deflicker = \"smear\"".parse::<Config>().is_err());
+        assert!("blend_frames = 1".parse::<Config>().is_err());
+        assert!("blend_frames = 9".parse::<Config>().is_err());
+    }
+
+    #[test]
+    fn saved_key_map_reloads_identically() {
+        let rom = Path::new("ghosts.ch8");
+        let mut map = KeyMap::default();
+        map.set_bindings(
+            0x5,
+            vec![
+                Input::Key(Key::Up),
+                Input::Axis {
+                    axis: 3,
+                    positive: false,
+                },
+            ],
+        );
+        let mut config = Config::default();
+        config.set_key_map(rom, &map);
+
+        let text = toml::to_string(&config).unwrap();
+        let reloaded: Config = text.parse().unwrap();
+        assert_eq!(reloaded.key_map(rom), map);
+    }
+}
@@ -0,0 +1,207 @@
+//! Post-processing against the flicker of XOR-drawn games, which erase and
+//! redraw their sprites every frame.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use chip8_core::display::{Display, Rgb};
+
+pub const DEFAULT_BLEND_FRAMES: usize = 3;
+pub const MAX_BLEND_FRAMES: usize = 8;
+
+// Share of its brightness a phosphor keeps from one frame to the next
+const PHOSPHOR_DECAY: f32 = 0.55;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FlickerMode {
+    /// Show each frame as it is
+    #[default]
+    Off,
+    /// A pixel is lit if it was lit in either of the last two frames
+    Persist,
+    /// Average the colours of the last few frames
+    Blend,
+    /// Lit pixels light up at once and fade out over a few frames, like the
+    /// phosphor of a CRT
+    Decay,
+}
+
+impl FlickerMode {
+    pub const ALL: [FlickerMode; 4] = [
+        FlickerMode::Off,
+        FlickerMode::Persist,
+        FlickerMode::Blend,
+        FlickerMode::Decay,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FlickerMode::Off => "off",
+            FlickerMode::Persist => "persist",
+            FlickerMode::Blend => "blend",
+            FlickerMode::Decay => "decay",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        let n = FlickerMode::ALL.iter().position(|&m| m == self).unwrap();
+        FlickerMode::ALL[(n + 1) % FlickerMode::ALL.len()]
+    }
+}
+
+impl fmt::Display for FlickerMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for FlickerMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        FlickerMode::ALL
+            .into_iter()
+            .find(|m| m.name() == s)
+            .ok_or_else(|| format!("unknown flicker filter '{}'", s))
+    }
+}
+
+/// Keeps snapshots of the last few frames and turns them into the picture to show
+pub struct Deflicker {
+    mode: FlickerMode,
+    blend_frames: usize,
+    history: VecDeque<Display>,
+    // Current colour of every phosphor, row by row
+    phosphor: Vec<[f32; 3]>,
+}
+
+impl Deflicker {
+    pub fn new(mode: FlickerMode, blend_frames: usize) -> Self {
+        Deflicker {
+            mode,
+            blend_frames: blend_frames.clamp(2, MAX_BLEND_FRAMES),
+            history: VecDeque::new(),
+            phosphor: Vec::new(),
+        }
+    }
+
+    pub fn mode(&self) -> FlickerMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: FlickerMode) {
+        self.mode = mode;
+    }
+
+    /// Take a snapshot of a frame the emulator just finished
+    pub fn push(&mut self, display: &Display, palette: &[Rgb]) {
+        let size = display.width() * display.height();
+        if self.phosphor.len() != size {
+            self.phosphor = vec![[0.0; 3]; size];
+            self.history.clear();
+        }
+        for (n, phosphor) in self.phosphor.iter_mut().enumerate() {
+            let pixel = display.get_pixel(n % display.width(), n / display.width());
+            let target = color(palette, pixel).map(|c| c as f32);
+            let keep = if pixel != 0 { 0.0 } else { PHOSPHOR_DECAY };
+            for (channel, target) in phosphor.iter_mut().zip(target) {
+                *channel = *channel * keep + target * (1.0 - keep);
+            }
+        }
+        self.history.push_back(display.clone());
+        while self.history.len() > MAX_BLEND_FRAMES {
+            self.history.pop_front();
+        }
+    }
+
+    /// The filtered picture as packed RGBA, or `None` before the first snapshot
+    pub fn render(&self, palette: &[Rgb]) -> Option<Vec<u8>> {
+        let latest = self.history.back()?;
+        let (width, height) = (latest.width(), latest.height());
+        let mut rgba = Vec::with_capacity(width * height * 4);
+        for y in 0..height {
+            for x in 0..width {
+                let rgb = match self.mode {
+                    FlickerMode::Off => color(palette, latest.get_pixel(x, y)),
+                    FlickerMode::Persist => {
+                        let recent = self.history.iter().rev().take(2);
+                        color(palette, recent.map(|d| d.get_pixel(x, y)).max().unwrap())
+                    }
+                    FlickerMode::Blend => {
+                        let recent = self.history.iter().rev().take(self.blend_frames);
+                        let count = recent.len() as u32;
+                        let mut sum = [0u32; 3];
+                        for display in recent {
+                            let rgb = color(palette, display.get_pixel(x, y));
+                            for (total, c) in sum.iter_mut().zip(rgb) {
+                                *total += c as u32;
+                            }
+                        }
+                        sum.map(|total| (total / count) as u8)
+                    }
+                    FlickerMode::Decay => self.phosphor[y * width + x].map(|c| c.round() as u8),
+                };
+                rgba.extend_from_slice(&rgb);
+                rgba.push(0xFF);
+            }
+        }
+        Some(rgba)
+    }
+}
+
+fn color(palette: &[Rgb], pixel: u8) -> Rgb {
+    palette[(pixel as usize).min(palette.len() - 1)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PALETTE: [Rgb; 2] = [[0, 0, 0], [200, 100, 50]];
+
+    // Pixel (0, 0) lit in odd frames only, as a flickering sprite would be
+    fn frames(deflicker: &mut Deflicker, count: usize) {
+        let mut display = Display::new();
+        for n in 0..count {
+            display.update_pixel(0, 0, (n % 2) as u8);
+            deflicker.push(&display, &PALETTE);
+        }
+    }
+
+    fn first_pixel(deflicker: &Deflicker) -> [u8; 3] {
+        let rgba = deflicker.render(&PALETTE).unwrap();
+        [rgba[0], rgba[1], rgba[2]]
+    }
+
+    #[test]
+    fn filters_smooth_a_flickering_pixel() {
+        let mut deflicker = Deflicker::new(FlickerMode::Off, 2);
+        assert!(deflicker.render(&PALETTE).is_none());
+        // The last frame pushed has the pixel off
+        frames(&mut deflicker, 5);
+        assert_eq!(first_pixel(&deflicker), [0, 0, 0]);
+
+        deflicker.set_mode(FlickerMode::Persist);
+        assert_eq!(first_pixel(&deflicker), [200, 100, 50]);
+
+        deflicker.set_mode(FlickerMode::Blend);
+        assert_eq!(first_pixel(&deflicker), [100, 50, 25]);
+
+        // Faded somewhat, but still well lit a frame after going dark
+        deflicker.set_mode(FlickerMode::Decay);
+        let [r, _, _] = first_pixel(&deflicker);
+        assert!((100..200).contains(&r));
+    }
+
+    #[test]
+    fn modes_cycle_and_parse() {
+        assert_eq!(FlickerMode::Decay.next(), FlickerMode::Off);
+        for mode in FlickerMode::ALL {
+            assert_eq!(mode.name().parse(), Ok(mode));
+        }
+    }
+}
@@ -0,0 +1,355 @@
+//! Gamepad input: controller events read through gilrs, turned into presses
+//! and releases of bindable `Input`s, and the inputs the shared presets in
+//! `chip8_core::gamepad` bind.
+//!
+//! gilrs reports controllers on its own, independently of the window
+//! backend. Everything past `PadEvent::from_gilrs` is backend independent,
+//! and tests drive it with `MockGamepad` as well as gilrs's own button and
+//! axis values.
+
+use std::collections::HashSet;
+#[cfg(test)]
+use std::collections::VecDeque;
+
+#[cfg(feature = "gamepad")]
+use gilrs::{Axis, Button, EventType};
+
+use chip8_core::gamepad::PadControl;
+
+use crate::keymap::{Direction, Input};
+
+/// How far an axis has to be pushed before it counts as pressed
+pub const AXIS_DEAD_ZONE: f32 = 0.5;
+
+/// A raw event from controller `pad`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PadEvent {
+    Button {
+        pad: usize,
+        button: u8,
+        pressed: bool,
+    },
+    /// A stick or trigger axis; vertical axes are positive downwards
+    Axis { pad: usize, axis: u8, position: f32 },
+    /// A d-pad reported as one button per direction
+    Hat {
+        pad: usize,
+        direction: Direction,
+        pressed: bool,
+    },
+    /// A d-pad reported as a pair of axes, positive right and down
+    HatAxis {
+        pad: usize,
+        vertical: bool,
+        position: f32,
+    },
+}
+
+#[cfg(feature = "gamepad")]
+impl PadEvent {
+    pub fn from_gilrs(pad: usize, event: &EventType) -> Option<Self> {
+        match *event {
+            EventType::ButtonPressed(button, _) => Self::from_button(pad, button, true),
+            EventType::ButtonReleased(button, _) => Self::from_button(pad, button, false),
+            EventType::AxisChanged(axis, position, _) => Self::from_axis(pad, axis, position),
+            _ => None,
+        }
+    }
+
+    fn from_button(pad: usize, button: Button, pressed: bool) -> Option<Self> {
+        let direction = match button {
+            Button::DPadUp => Direction::Up,
+            Button::DPadDown => Direction::Down,
+            Button::DPadLeft => Direction::Left,
+            Button::DPadRight => Direction::Right,
+            _ => {
+                // Numbered like SDL's game controller buttons, so bindings
+                // written as "Button0" keep meaning the bottom face button
+                let button = match button {
+                    Button::South => 0,
+                    Button::East => 1,
+                    Button::West => 2,
+                    Button::North => 3,
+                    Button::Select => 4,
+                    Button::Mode => 5,
+                    Button::Start => 6,
+                    Button::LeftThumb => 7,
+                    Button::RightThumb => 8,
+                    Button::LeftTrigger => 9,
+                    Button::RightTrigger => 10,
+                    Button::LeftTrigger2 => 11,
+                    Button::RightTrigger2 => 12,
+                    Button::C => 13,
+                    Button::Z => 14,
+                    _ => return None,
+                };
+                return Some(PadEvent::Button {
+                    pad,
+                    button,
+                    pressed,
+                });
+            }
+        };
+        Some(PadEvent::Hat {
+            pad,
+            direction,
+            pressed,
+        })
+    }
+
+    fn from_axis(pad: usize, axis: Axis, position: f32) -> Option<Self> {
+        // gilrs has up positive, bindings have it the other way around
+        let (axis, position) = match axis {
+            Axis::LeftStickX => (0, position),
+            Axis::LeftStickY => (1, -position),
+            Axis::RightStickX => (2, position),
+            Axis::RightStickY => (3, -position),
+            Axis::LeftZ => (4, position),
+            Axis::RightZ => (5, position),
+            Axis::DPadX | Axis::DPadY => {
+                let vertical = axis == Axis::DPadY;
+                return Some(PadEvent::HatAxis {
+                    pad,
+                    vertical,
+                    position: if vertical { -position } else { position },
+                });
+            }
+            _ => return None,
+        };
+        Some(PadEvent::Axis {
+            pad,
+            axis,
+            position,
+        })
+    }
+}
+
+/// Tracks what is held on every pad, so analog axes and d-pads turn into
+/// clean press and release transitions
+#[derive(Debug, Default)]
+pub struct Gamepad {
+    held: HashSet<(usize, Input)>,
+}
+
+impl Gamepad {
+    /// The inputs `event` presses (`true`) and releases (`false`)
+    pub fn handle(&mut self, event: PadEvent) -> Vec<(Input, bool)> {
+        match event {
+            PadEvent::Button {
+                pad,
+                button,
+                pressed,
+            } => self.update(pad, &[(Input::Button(button), pressed)]),
+            PadEvent::Axis {
+                pad,
+                axis: n,
+                position,
+            } => self.update(
+                pad,
+                &[
+                    (axis(n, true), position > AXIS_DEAD_ZONE),
+                    (axis(n, false), position < -AXIS_DEAD_ZONE),
+                ],
+            ),
+            PadEvent::Hat {
+                pad,
+                direction,
+                pressed,
+            } => self.update(pad, &[(Input::Hat(direction), pressed)]),
+            PadEvent::HatAxis {
+                pad,
+                vertical,
+                position,
+            } => {
+                let (positive, negative) = if vertical {
+                    (Direction::Down, Direction::Up)
+                } else {
+                    (Direction::Right, Direction::Left)
+                };
+                self.update(
+                    pad,
+                    &[
+                        (Input::Hat(positive), position > AXIS_DEAD_ZONE),
+                        (Input::Hat(negative), position < -AXIS_DEAD_ZONE),
+                    ],
+                )
+            }
+        }
+    }
+
+    fn update(&mut self, pad: usize, inputs: &[(Input, bool)]) -> Vec<(Input, bool)> {
+        let mut changes = Vec::new();
+        for &(input, down) in inputs {
+            let changed = if down {
+                self.held.insert((pad, input))
+            } else {
+                self.held.remove(&(pad, input))
+            };
+            if changed {
+                changes.push((input, down));
+            }
+        }
+        changes
+    }
+}
+
+/// Scripted stand-in for a controller
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct MockGamepad {
+    events: VecDeque<PadEvent>,
+}
+
+#[cfg(test)]
+impl MockGamepad {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn button(mut self, button: u8, pressed: bool) -> Self {
+        self.events.push_back(PadEvent::Button {
+            pad: 0,
+            button,
+            pressed,
+        });
+        self
+    }
+
+    pub fn axis(mut self, axis: u8, position: f32) -> Self {
+        self.events.push_back(PadEvent::Axis {
+            pad: 0,
+            axis,
+            position,
+        });
+        self
+    }
+
+    pub fn hat(mut self, direction: Direction, pressed: bool) -> Self {
+        self.events.push_back(PadEvent::Hat {
+            pad: 0,
+            direction,
+            pressed,
+        });
+        self
+    }
+}
+
+#[cfg(test)]
+impl Iterator for MockGamepad {
+    type Item = PadEvent;
+
+    fn next(&mut self) -> Option<PadEvent> {
+        self.events.pop_front()
+    }
+}
+
+const fn axis(axis: u8, positive: bool) -> Input {
+    Input::Axis { axis, positive }
+}
+
+// The usual layout: axis 0 is the left stick's horizontal, axis 1 its vertical
+// (positive is down), button 0 the bottom face button and button 1 the right one
+const LEFT: [Input; 2] = [Input::Hat(Direction::Left), axis(0, false)];
+const RIGHT: [Input; 2] = [Input::Hat(Direction::Right), axis(0, true)];
+const UP: [Input; 2] = [Input::Hat(Direction::Up), axis(1, false)];
+const DOWN: [Input; 2] = [Input::Hat(Direction::Down), axis(1, true)];
+
+/// The inputs a preset's `control` binds
+pub fn control_inputs(control: PadControl) -> &'static [Input] {
+    match control {
+        PadControl::Up => &UP,
+        PadControl::Down => &DOWN,
+        PadControl::Left => &LEFT,
+        PadControl::Right => &RIGHT,
+        PadControl::Action => &[Input::Button(0)],
+        PadControl::Secondary => &[Input::Button(1)],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keymap::KeyMap;
+    use chip8_core::emu::Chip8;
+
+    #[test]
+    fn axes_and_hats_press_and_release_once() {
+        let mut pad = Gamepad::default();
+        let events: Vec<_> = MockGamepad::new()
+            .axis(0, 0.2) // inside the dead zone
+            .axis(0, 0.8)
+            .axis(0, 0.9) // still held
+            .axis(0, -0.7) // straight across
+            .hat(Direction::Up, true)
+            .hat(Direction::Up, true) // repeated
+            .hat(Direction::Up, false)
+            .flat_map(|event| pad.handle(event))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                (axis(0, true), true),
+                (axis(0, true), false),
+                (axis(0, false), true),
+                (Input::Hat(Direction::Up), true),
+                (Input::Hat(Direction::Up), false),
+            ]
+        );
+    }
+
+    #[cfg(feature = "gamepad")]
+    #[test]
+    fn gilrs_events_become_bindable_inputs() {
+        let mut pad = Gamepad::default();
+        let events = [
+            PadEvent::from_button(3, Button::South, true),
+            PadEvent::from_button(3, Button::DPadLeft, true),
+            // Up on the stick is down the bindings' vertical axis
+            PadEvent::from_axis(3, Axis::LeftStickY, 0.9),
+            PadEvent::from_axis(3, Axis::DPadY, -1.0),
+            PadEvent::from_axis(3, Axis::DPadY, 0.0),
+            PadEvent::from_button(3, Button::South, false),
+            PadEvent::from_button(3, Button::Unknown, true),
+        ];
+        let inputs: Vec<_> = events
+            .into_iter()
+            .flatten()
+            .flat_map(|event| pad.handle(event))
+            .collect();
+        assert_eq!(
+            inputs,
+            [
+                (Input::Button(0), true),
+                (Input::Hat(Direction::Left), true),
+                (axis(1, false), true),
+                (Input::Hat(Direction::Down), true),
+                (Input::Hat(Direction::Down), false),
+                (Input::Button(0), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn mock_gamepad_drives_chip8_keys() {
+        let map = KeyMap::default();
+        let mut pad = Gamepad::default();
+        let mut chip8 = Chip8::new();
+        let source = MockGamepad::new()
+            .hat(Direction::Down, true)
+            .button(0, true)
+            .axis(0, -1.0);
+        for (input, pressed) in source.flat_map(|event| pad.handle(event)) {
+            if let Some(key) = map.lookup(input) {
+                if pressed {
+                    chip8.key_mut().press_key(key);
+                } else {
+                    chip8.key_mut().release_key(key);
+                }
+            }
+        }
+        let held: Vec<u8> = (0..16)
+            .filter(|&k| chip8.key_mut().key_is_pressed(k))
+            .collect();
+        assert_eq!(held, [0x4, 0x5, 0x8]);
+    }
+}
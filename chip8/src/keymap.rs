@@ -0,0 +1,285 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+use piston_window::Key;
+use serde::de::IntoDeserializer;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use chip8_core::gamepad::{Preset, DEFAULT_PRESET};
+
+use crate::gamepad::control_inputs;
+
+/// CHIP-8 keys in the order they sit on the COSMAC VIP hex keypad
+pub const KEYPAD_LAYOUT: [usize; 16] = [
+    0x1, 0x2, 0x3, 0xC, //
+    0x4, 0x5, 0x6, 0xD, //
+    0x7, 0x8, 0x9, 0xE, //
+    0xA, 0x0, 0xB, 0xF,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 4] = [
+        Direction::Up,
+        Direction::Down,
+        Direction::Left,
+        Direction::Right,
+    ];
+}
+
+/// A host input that can be bound to a CHIP-8 key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Input {
+    Key(Key),
+    /// A gamepad button
+    Button(u8),
+    /// A gamepad axis pushed past its dead zone in one direction
+    Axis {
+        axis: u8,
+        positive: bool,
+    },
+    /// A direction on a gamepad's hat (d-pad)
+    Hat(Direction),
+}
+
+impl Input {
+    pub fn is_key(self) -> bool {
+        matches!(self, Input::Key(_))
+    }
+}
+
+// Keys use piston's names ("W", "D1", "Up"); gamepad inputs are written as
+// "Button0", "Axis1+", "Axis1-" and "HatUp"
+impl fmt::Display for Input {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Input::Key(key) => write!(f, "{:?}", key),
+            Input::Button(button) => write!(f, "Button{}", button),
+            Input::Axis { axis, positive } => {
+                write!(f, "Axis{}{}", axis, if *positive { '+' } else { '-' })
+            }
+            Input::Hat(direction) => write!(f, "Hat{:?}", direction),
+        }
+    }
+}
+
+impl FromStr for Input {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = || format!("unknown input '{}'", s);
+        if let Some(button) = s.strip_prefix("Button") {
+            return button.parse().map(Input::Button).map_err(|_| bad());
+        }
+        if let Some(axis) = s.strip_prefix("Axis") {
+            let positive = match axis.chars().last() {
+                Some('+') => true,
+                Some('-') => false,
+                _ => return Err(bad()),
+            };
+            let axis = axis[..axis.len() - 1].parse().map_err(|_| bad())?;
+            return Ok(Input::Axis { axis, positive });
+        }
+        if let Some(direction) = s.strip_prefix("Hat") {
+            return Direction::ALL
+                .into_iter()
+                .find(|d| format!("{:?}", d) == direction)
+                .map(Input::Hat)
+                .ok_or_else(bad);
+        }
+        let key: Result<Key, serde::de::value::Error> = Key::deserialize(s.into_deserializer());
+        key.map(Input::Key).map_err(|_| bad())
+    }
+}
+
+impl Serialize for Input {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Input {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Host inputs bound to each of the 16 CHIP-8 keys
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyMap {
+    bindings: [Vec<Input>; 16],
+}
+
+impl Default for KeyMap {
+    /// The keypad laid over the left of a QWERTY keyboard, plus the default
+    /// gamepad preset:
+    ///
+    /// ```text
+    /// 1 2 3 4      1 2 3 C
+    /// Q W E R  ->  4 5 6 D
+    /// A S D F      7 8 9 E
+    /// Z X C V      A 0 B F
+    /// ```
+    fn default() -> Self {
+        #[rustfmt::skip]
+        let host = [
+            Key::D1, Key::D2, Key::D3, Key::D4,
+            Key::Q,  Key::W,  Key::E,  Key::R,
+            Key::A,  Key::S,  Key::D,  Key::F,
+            Key::Z,  Key::X,  Key::C,  Key::V,
+        ];
+        let mut map = KeyMap::empty();
+        for (&chip8_key, host_key) in KEYPAD_LAYOUT.iter().zip(host) {
+            map.bindings[chip8_key].push(Input::Key(host_key));
+        }
+        map.apply_preset(&DEFAULT_PRESET);
+        map
+    }
+}
+
+impl KeyMap {
+    fn empty() -> Self {
+        KeyMap {
+            bindings: Default::default(),
+        }
+    }
+
+    /// The CHIP-8 key `input` is bound to, if any
+    pub fn lookup(&self, input: Input) -> Option<usize> {
+        self.bindings
+            .iter()
+            .position(|inputs| inputs.contains(&input))
+    }
+
+    pub fn bindings(&self, chip8_key: usize) -> &[Input] {
+        &self.bindings[chip8_key]
+    }
+
+    /// Replace every binding of `chip8_key`, taking the inputs away from any
+    /// other CHIP-8 key they were bound to
+    pub fn set_bindings(&mut self, chip8_key: usize, inputs: Vec<Input>) {
+        for input in &inputs {
+            self.unbind(*input);
+        }
+        self.bindings[chip8_key] = inputs;
+    }
+
+    /// Swap all gamepad bindings for the ones in `preset`, leaving the keyboard alone
+    pub fn apply_preset(&mut self, preset: &Preset) {
+        for inputs in self.bindings.iter_mut() {
+            inputs.retain(|input| input.is_key());
+        }
+        for &(control, chip8_key) in preset.bindings {
+            for &input in control_inputs(control) {
+                self.unbind(input);
+                self.bindings[chip8_key].push(input);
+            }
+        }
+    }
+
+    fn unbind(&mut self, input: Input) {
+        for inputs in self.bindings.iter_mut() {
+            inputs.retain(|&i| i != input);
+        }
+    }
+}
+
+/// The host inputs holding each CHIP-8 key down, so a key bound to several
+/// inputs is only released once the last of them is
+#[derive(Debug, Default)]
+pub struct HeldKeys {
+    held: [HashSet<Input>; 16],
+}
+
+impl HeldKeys {
+    /// Note `input` holding `chip8_key`; true if the key was up until now
+    pub fn press(&mut self, chip8_key: usize, input: Input) -> bool {
+        let was_up = self.held[chip8_key].is_empty();
+        self.held[chip8_key].insert(input);
+        was_up
+    }
+
+    /// Let go of `input`, returning the CHIP-8 key it held if nothing else
+    /// holds that key any more. The key is the one `input` was bound to when
+    /// pressed, even if it has been rebound since.
+    pub fn release(&mut self, input: Input) -> Option<usize> {
+        let chip8_key = self
+            .held
+            .iter()
+            .position(|inputs| inputs.contains(&input))?;
+        self.held[chip8_key].remove(&input);
+        self.held[chip8_key].is_empty().then_some(chip8_key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_layout_matches_the_keypad() {
+        let map = KeyMap::default();
+        assert_eq!(map.lookup(Input::Key(Key::D4)), Some(0xC));
+        assert_eq!(map.lookup(Input::Key(Key::X)), Some(0x0));
+        assert_eq!(map.lookup(Input::Key(Key::V)), Some(0xF));
+        assert_eq!(map.lookup(Input::Key(Key::Space)), None);
+        assert_eq!(map.lookup(Input::Hat(Direction::Up)), Some(0x2));
+    }
+
+    #[test]
+    fn inputs_move_between_chip8_keys() {
+        let mut map = KeyMap::default();
+        map.set_bindings(0x5, vec![Input::Key(Key::W), Input::Key(Key::Up)]);
+        assert_eq!(map.lookup(Input::Key(Key::Up)), Some(0x5));
+
+        // W now belongs to 8 only, 5 keeps its arrow key
+        map.set_bindings(0x8, vec![Input::Key(Key::W), Input::Key(Key::Down)]);
+        assert_eq!(map.lookup(Input::Key(Key::W)), Some(0x8));
+        assert_eq!(map.bindings(0x5), &[Input::Key(Key::Up)]);
+    }
+
+    #[test]
+    fn input_names_roundtrip() {
+        for name in [
+            "W", "D1", "NumPad8", "Button3", "Axis1+", "Axis0-", "HatLeft",
+        ] {
+            let input: Input = name.parse().unwrap();
+            assert_eq!(input.to_string(), name);
+        }
+        assert!("Axis1".parse::<Input>().is_err());
+        assert!("HatSideways".parse::<Input>().is_err());
+        assert!("NoSuchKey".parse::<Input>().is_err());
+    }
+
+    #[test]
+    fn presets_replace_only_gamepad_bindings() {
+        let mut map = KeyMap::default();
+        map.apply_preset(chip8_core::gamepad::preset("paddle-14").unwrap());
+        assert_eq!(map.lookup(Input::Hat(Direction::Up)), Some(0x1));
+        // The default preset's buttons are gone, the keyboard is untouched
+        assert_eq!(map.lookup(Input::Button(0)), None);
+        assert_eq!(map.lookup(Input::Key(Key::W)), Some(0x5));
+    }
+
+    #[test]
+    fn keys_held_by_two_inputs_wait_for_both() {
+        let mut held = HeldKeys::default();
+        let (w, up) = (Input::Key(Key::W), Input::Key(Key::Up));
+        assert!(held.press(0x5, w));
+        assert!(!held.press(0x5, up));
+        assert_eq!(held.release(w), None);
+        assert_eq!(held.release(up), Some(0x5));
+        // Nothing held, nothing to release
+        assert_eq!(held.release(up), None);
+    }
+}
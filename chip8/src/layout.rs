@@ -0,0 +1,109 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// How the emulated screen is fitted into the window
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScaleMode {
+    /// The largest whole number of window pixels per CHIP-8 pixel, letterboxed
+    Integer,
+    /// As large as fits while keeping the aspect ratio, letterboxed
+    #[default]
+    Fit,
+    /// Fill the whole window, distorting the aspect ratio
+    Stretch,
+}
+
+impl ScaleMode {
+    pub const ALL: [ScaleMode; 3] = [ScaleMode::Integer, ScaleMode::Fit, ScaleMode::Stretch];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ScaleMode::Integer => "integer",
+            ScaleMode::Fit => "fit",
+            ScaleMode::Stretch => "stretch",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        let n = ScaleMode::ALL.iter().position(|&m| m == self).unwrap();
+        ScaleMode::ALL[(n + 1) % ScaleMode::ALL.len()]
+    }
+}
+
+impl fmt::Display for ScaleMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for ScaleMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ScaleMode::ALL
+            .into_iter()
+            .find(|m| m.name() == s)
+            .ok_or_else(|| format!("unknown scale mode '{}'", s))
+    }
+}
+
+/// The `[x, y, width, height]` of the window area showing a `screen` of
+/// emulated pixels, centred in a `window` of the given size. Computed afresh
+/// for every frame, so it follows both window resizes and changes in the
+/// emulated resolution.
+pub fn viewport(window: [f64; 2], screen: [usize; 2], mode: ScaleMode) -> [f64; 4] {
+    let [width, height] = window;
+    let (columns, rows) = (screen[0] as f64, screen[1] as f64);
+    let scale = (width / columns).min(height / rows);
+    let (w, h) = match mode {
+        // Windows smaller than the screen still show it, just not pixel perfect
+        ScaleMode::Integer if scale >= 1.0 => (scale.floor() * columns, scale.floor() * rows),
+        ScaleMode::Integer | ScaleMode::Fit => (scale * columns, scale * rows),
+        ScaleMode::Stretch => (width, height),
+    };
+    [(width - w) / 2.0, (height - h) / 2.0, w, h]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn viewport_follows_the_window() {
+        let lores = [64, 32];
+        assert_eq!(
+            viewport([640.0, 320.0], lores, ScaleMode::Integer),
+            [0.0, 0.0, 640.0, 320.0]
+        );
+        // Letterboxed top and bottom
+        assert_eq!(
+            viewport([700.0, 500.0], lores, ScaleMode::Integer),
+            [30.0, 90.0, 640.0, 320.0]
+        );
+        assert_eq!(
+            viewport([700.0, 500.0], lores, ScaleMode::Fit),
+            [0.0, 75.0, 700.0, 350.0]
+        );
+        assert_eq!(
+            viewport([700.0, 500.0], lores, ScaleMode::Stretch),
+            [0.0, 0.0, 700.0, 500.0]
+        );
+        // A hires screen in the same window gets half the scale
+        assert_eq!(
+            viewport([700.0, 500.0], [128, 64], ScaleMode::Integer),
+            [30.0, 90.0, 640.0, 320.0]
+        );
+    }
+
+    #[test]
+    fn modes_cycle_and_parse() {
+        assert_eq!(ScaleMode::Stretch.next(), ScaleMode::Integer);
+        for mode in ScaleMode::ALL {
+            assert_eq!(mode.name().parse(), Ok(mode));
+        }
+        assert!("zoom".parse::<ScaleMode>().is_err());
+    }
+}
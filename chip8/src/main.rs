@@ -1,45 +1,471 @@
 extern crate piston_window;
 use piston_window::*;
 
-use chip8_core::emu::Chip8;
+mod config;
+mod deflicker;
+// Without the gamepad feature nothing feeds controller events in
+#[cfg_attr(not(feature = "gamepad"), allow(dead_code))]
+mod gamepad;
+mod keymap;
+mod layout;
+mod rebind;
+mod render;
+
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chip8_core::capture::Capture;
+use chip8_core::display::Rgb;
+use chip8_core::emu::{Chip8, RomTooLarge, MAX_ROM_SIZE};
+use chip8_core::machine::Machine;
+use chip8_core::platform::Platform;
+use chip8_core::replay::{InputRecorder, InputRecording};
+use chip8_core::rewind::RewindBuffer;
+use chip8_core::timing::Timing;
+use chip8_core::vip::Vip;
+
+use config::{Config, DEFAULT_CONFIG_PATH};
+use deflicker::{Deflicker, FlickerMode};
+use gamepad::Gamepad;
+#[cfg(feature = "gamepad")]
+use gamepad::PadEvent;
+use keymap::{HeldKeys, Input, KeyMap};
+use layout::viewport;
+use rebind::Rebinder;
+use render::ScreenRenderer;
+
+const TITLE: &str = "CHIP-8 Emulator";
+
+// Hold this key to play the game backwards frame by frame
+const REWIND_KEY: Key = Key::Backspace;
+const REWIND_SECONDS: usize = 30;
+
+// Press to save the screen as a PNG in the working directory
+const SCREENSHOT_KEY: Key = Key::F12;
+const SCREENSHOT_SCALE: usize = 10;
+
+// Press to start or stop recording a GIF, a Y4M video and a WAV of the beeper
+const CAPTURE_KEY: Key = Key::F9;
+const CAPTURE_SCALE: usize = 4;
+
+// Press to rebind the keypad for the current ROM, saved to the config file
+const REBIND_KEY: Key = Key::F1;
+
+// Press to switch to the next palette
+const PALETTE_KEY: Key = Key::F2;
+
+// Press to switch between integer, fractional and stretched scaling
+const SCALE_KEY: Key = Key::F3;
+
+// Press to switch between the filters against flicker
+const FLICKER_KEY: Key = Key::F4;
+
+const FULLSCREEN_KEY: Key = Key::F11;
+
+const USAGE: &str = "usage: chip8 [ROM] [--platform vip|schip] [--timing fixed|vip] [--seed N] \
+[--record FILE | --replay FILE] [--capture BASE] [--config FILE] \
+[--vip-interpreter FILE [--vip-monitor FILE]]";
+
+struct Options {
+    rom: String,
+    platform: Platform,
+    timing: Timing,
+    seed: Option<u64>,
+    record: Option<String>,
+    replay: Option<String>,
+    capture: Option<String>,
+    config: PathBuf,
+    vip_interpreter: Option<String>,
+    vip_monitor: Option<String>,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options {
+        rom: "roms/6-keypad.ch8".to_string(),
+        platform: Platform::default(),
+        timing: Timing::default(),
+        seed: None,
+        record: None,
+        replay: None,
+        capture: None,
+        config: PathBuf::from(DEFAULT_CONFIG_PATH),
+        vip_interpreter: None,
+        vip_monitor: None,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--platform" => options.platform = value()?.parse()?,
+            "--timing" => options.timing = value()?.parse()?,
+            "--seed" => options.seed = Some(value()?.parse().map_err(|_| "bad seed")?),
+            "--record" => options.record = Some(value()?),
+            "--replay" => options.replay = Some(value()?),
+            "--capture" => options.capture = Some(value()?),
+            "--config" => options.config = PathBuf::from(value()?),
+            "--vip-interpreter" => options.vip_interpreter = Some(value()?),
+            "--vip-monitor" => options.vip_monitor = Some(value()?),
+            _ if !arg.starts_with("--") => options.rom = arg,
+            _ => return Err(format!("unknown option {}", arg)),
+        }
+    }
+    if options.record.is_some() && options.replay.is_some() {
+        return Err("--record and --replay are mutually exclusive".to_string());
+    }
+    if options.vip_interpreter.is_some() {
+        if options.record.is_some() || options.replay.is_some() {
+            return Err("--record and --replay only work with the CHIP-8 interpreter".to_string());
+        }
+    } else if options.vip_monitor.is_some() {
+        return Err("--vip-monitor needs --vip-interpreter".to_string());
+    }
+    Ok(options)
+}
 
 fn main() {
-    let mut chip8 = Chip8::new();
+    let options = parse_args().unwrap_or_else(|e| {
+        eprintln!("{}\n{}", e, USAGE);
+        std::process::exit(2);
+    });
 
-    // chip8.load_rom("roms/IBM_Logo.ch8");
-    // chip8.load_rom("roms/1-chip8-logo.ch8");
-    // chip8.load_rom("roms/3-corax+.ch8");
-    // chip8.load_rom("roms/4-flags.ch8");
-    // chip8.load_rom("roms/5-quirks.ch8");
-    chip8.load_rom("roms/6-keypad.ch8");
-    // chip8.load_rom("roms/ghosts.ch8");
+    // Roms bundled in roms/:
+    // IBM_Logo.ch8, 1-chip8-logo.ch8, 3-corax+.ch8, 4-flags.ch8,
+    // 5-quirks.ch8, 6-keypad.ch8, ghosts.ch8
+    let rom = std::fs::read(&options.rom).unwrap_or_else(|e| {
+        eprintln!("Failed to read {}: {}", options.rom, e);
+        std::process::exit(2);
+    });
+    if rom.len() > MAX_ROM_SIZE {
+        eprintln!(
+            "Failed to load {}: {}",
+            options.rom,
+            RomTooLarge { len: rom.len() }
+        );
+        std::process::exit(2);
+    }
+
+    let mut config = Config::load_or_default(&options.config).unwrap_or_else(|e| {
+        eprintln!("Failed to load {}: {}", options.config.display(), e);
+        std::process::exit(2);
+    });
+    let rom_path = Path::new(&options.rom);
+    let mut key_map = config.key_map(rom_path);
+    let mut held = HeldKeys::default();
+    let mut rebinder: Option<Rebinder> = None;
+    #[cfg_attr(not(feature = "gamepad"), allow(unused_mut, unused_variables))]
+    let mut gamepad = Gamepad::default();
+    #[cfg(feature = "gamepad")]
+    let mut gilrs = gilrs::Gilrs::new()
+        .map_err(|e| eprintln!("Gamepads unavailable: {}", e))
+        .ok();
+    let palettes = config.palettes();
+    let mut palette = config
+        .palette_name(rom_path)
+        .and_then(|name| palettes.iter().position(|p| p.name() == name))
+        .unwrap_or(0);
+    let mut scale_mode = config.scale_mode();
+    let mut fullscreen = config.fullscreen();
+    let mut deflicker = Deflicker::new(config.flicker_mode(), config.blend_frames());
+
+    let replay = options.replay.as_ref().map(|path| {
+        InputRecording::load(path).unwrap_or_else(|e| {
+            eprintln!("Failed to load {}: {}", path, e);
+            std::process::exit(2);
+        })
+    });
+    let mut chip8 = match &replay {
+        Some(recording) => recording.boot(&rom).unwrap_or_else(|e| {
+            eprintln!("{}: {}", options.replay.as_deref().unwrap_or_default(), e);
+            std::process::exit(2);
+        }),
+        None => {
+            let mut chip8 = Chip8::new();
+            chip8.set_platform(options.platform);
+            chip8.set_timing(options.timing);
+            chip8.set_seed(options.seed.unwrap_or_else(|| {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |d| d.as_nanos() as u64)
+            }));
+            chip8.load_rom_bytes(&rom);
+            chip8
+        }
+    };
+    let mut player = replay.as_ref().map(InputRecording::player);
+    // Emulating the VIP hardware runs the ROM through a real interpreter instead
+    let mut vip = options.vip_interpreter.as_ref().map(|interpreter| {
+        boot_vip(interpreter, options.vip_monitor.as_deref(), &rom).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        })
+    });
+    let mut recorder = options
+        .record
+        .as_ref()
+        .map(|_| InputRecorder::new(&chip8, &rom));
 
-    let mut window: PistonWindow = WindowSettings::new("CHIP-8 Emulator", [640, 320])
+    let mut window: PistonWindow = WindowSettings::new(TITLE, [640, 320])
         .exit_on_esc(true)
+        .fullscreen(fullscreen)
         .build()
         .unwrap();
+    let mut renderer = ScreenRenderer::new(&mut window);
 
-    let mut events = Events::new(EventSettings::new().ups(500)); // Update at 500 Hz
+    // Rewinding would rewrite history under a recording or replay, so it is only
+    // available during normal play
+    let mut rewind = RewindBuffer::new(REWIND_SECONDS, 1);
+    let can_rewind = recorder.is_none() && replay.is_none() && vip.is_none();
+    let mut rewinding = false;
+
+    let mut capture = options
+        .capture
+        .as_ref()
+        .and_then(|base| start_capture(Path::new(base), palettes[palette].colors()));
+
+    let mut events = Events::new(EventSettings::new().ups(60)); // One update per 60 Hz frame
     while let Some(e) = events.next(&mut window) {
-        if let Some(args) = e.press_args() {
-            if let Button::Keyboard(key) = args {
-                chip8.key_mut().handle_key_press(key);
+        // Host inputs that may be bound to CHIP-8 keys, after hotkeys are handled
+        let mut inputs = Vec::new();
+        if let Some(Button::Keyboard(key)) = e.press_args() {
+            if key == REBIND_KEY {
+                // Starting or cancelling a rebind; the emulator is paused meanwhile
+                rebinder = match rebinder {
+                    Some(_) => None,
+                    None => Some(Rebinder::new(key_map.clone())),
+                };
+                set_title(&mut window, rebinder.as_ref());
+            } else if rebinder.is_some() {
+                inputs.push((Input::Key(key), true));
+            } else if key == REWIND_KEY {
+                rewinding = can_rewind;
+            } else if key == SCREENSHOT_KEY {
+                save_screenshot(
+                    active_machine(&mut chip8, &mut vip),
+                    palettes[palette].colors(),
+                );
+            } else if key == PALETTE_KEY {
+                palette = (palette + 1) % palettes.len();
+                println!("Palette: {}", palettes[palette]);
+            } else if key == SCALE_KEY {
+                scale_mode = scale_mode.next();
+                println!("Scaling: {}", scale_mode);
+            } else if key == FLICKER_KEY {
+                deflicker.set_mode(deflicker.mode().next());
+                println!("Flicker filter: {}", deflicker.mode());
+            } else if key == FULLSCREEN_KEY {
+                fullscreen = !fullscreen;
+                set_fullscreen(&mut window, fullscreen);
+            } else if key == CAPTURE_KEY {
+                match capture.take() {
+                    Some(capture) => finish_capture(capture),
+                    None => {
+                        let frame = active_machine(&mut chip8, &mut vip).frame();
+                        let base = PathBuf::from(format!("capture-{:06}", frame));
+                        capture = start_capture(&base, palettes[palette].colors());
+                    }
+                }
+            } else {
+                inputs.push((Input::Key(key), true));
+            }
+        }
+        if let Some(Button::Keyboard(key)) = e.release_args() {
+            if key == REWIND_KEY {
+                rewinding = false;
+            } else {
+                inputs.push((Input::Key(key), false));
             }
         }
-        
-        if let Some(_) = e.update_args() {
-            chip8.emulate_cycle(); // Execute one cycle of the emulator
-            chip8.delay_timer_tick();
+        #[cfg(feature = "gamepad")]
+        while let Some(gilrs::Event { id, event, .. }) = gilrs.as_mut().and_then(|g| g.next_event())
+        {
+            if let Some(event) = PadEvent::from_gilrs(id.into(), &event) {
+                inputs.extend(gamepad.handle(event));
+            }
         }
 
-        if let Some(args) = e.release_args() {
-            if let Button::Keyboard(key) = args {
-                chip8.key_mut().handle_key_release(key);
+        for (input, pressed) in inputs {
+            match rebinder.as_mut() {
+                Some(active) if pressed => {
+                    active.press(input);
+                    set_title(&mut window, Some(active));
+                    if active.is_done() {
+                        key_map = rebinder.take().unwrap().into_map();
+                        config.set_key_map(rom_path, &key_map);
+                        match config.save(&options.config) {
+                            Ok(()) => {
+                                println!("Key bindings saved to {}", options.config.display())
+                            }
+                            Err(e) => {
+                                eprintln!("Failed to save {}: {}", options.config.display(), e)
+                            }
+                        }
+                        set_title(&mut window, None);
+                    }
+                }
+                _ if replay.is_none() => {
+                    let Some(key) = bound_key(&mut held, &key_map, input, pressed) else {
+                        continue;
+                    };
+                    let machine = active_machine(&mut chip8, &mut vip);
+                    match recorder.as_mut() {
+                        Some(recorder) => recorder
+                            .key_event(&mut chip8, key, pressed)
+                            .expect("key maps only hold keys 0x0 to 0xF"),
+                        None if pressed => machine.key_mut().press_key(key as usize),
+                        None => machine.key_mut().release_key(key as usize),
+                    }
+                }
+                _ => {}
             }
         }
 
-        if let Some(_) = e.render_args() {
-            chip8.display_mut().draw_graphics(&mut window, &e); // Draw the current state of the display
+        if e.update_args().is_some() {
+            let mut ran = false;
+            if rebinder.is_some() {
+                // Paused
+            } else if let Some(vip) = vip.as_mut() {
+                vip.run_frame();
+                ran = true;
+            } else if rewinding {
+                rewind.rewind(&mut chip8);
+                ran = true;
+            } else if let (Some(recording), Some(player)) = (&replay, player.as_mut()) {
+                if chip8.frame() < recording.frames {
+                    player.apply_events(&mut chip8);
+                    chip8.run_frame();
+                    ran = true;
+                }
+            } else {
+                chip8.run_frame(); // Execute one frame worth of cycles
+                ran = true;
+                if can_rewind {
+                    rewind.push_frame(&chip8);
+                }
+            }
+
+            if ran {
+                let display = active_machine(&mut chip8, &mut vip).display();
+                deflicker.push(display, palettes[palette].colors());
+            }
+
+            // Only emulated frames are captured, so the clip keeps the game's
+            // own timing even when the host stutters
+            if let Some(recording) = capture.as_mut().filter(|_| ran && !rewinding) {
+                if let Err(e) = recording.frame(active_machine(&mut chip8, &mut vip)) {
+                    eprintln!("Capture failed: {}", e);
+                    capture = None;
+                }
+            }
+        }
+
+        if e.render_args().is_some() {
+            match &rebinder {
+                Some(rebinder) => rebinder.draw(&mut window, &e),
+                None => {
+                    // Draw the current state of the display
+                    let display = active_machine(&mut chip8, &mut vip).display_mut();
+                    let colors = palettes[palette].colors();
+                    match deflicker.render(colors) {
+                        Some(rgba) if deflicker.mode() != FlickerMode::Off => {
+                            // The filter redraws the whole picture every frame anyway
+                            display.take_dirty_rects();
+                            renderer.upload([display.width(), display.height()], &rgba);
+                        }
+                        _ => renderer.update(display, colors),
+                    }
+                    let size = window.size();
+                    let area = viewport([size.width, size.height], renderer.size(), scale_mode);
+                    renderer.draw(&mut window, &e, area);
+                }
+            }
         }
     }
+
+    if let Some(capture) = capture {
+        finish_capture(capture);
+    }
+
+    if let (Some(recorder), Some(path)) = (recorder, &options.record) {
+        match recorder.finish(&chip8).save(path) {
+            Ok(()) => println!("Input recorded to {}", path),
+            Err(e) => eprintln!("Failed to save recording to {}: {}", path, e),
+        }
+    }
+}
+
+/// The machine the window shows: the VIP hardware if it is emulated, the
+/// CHIP-8 interpreter otherwise
+fn active_machine<'a>(chip8: &'a mut Chip8, vip: &'a mut Option<Vip>) -> &'a mut dyn Machine {
+    match vip {
+        Some(vip) => vip,
+        None => chip8,
+    }
+}
+
+fn save_screenshot(machine: &dyn Machine, palette: &[Rgb]) {
+    let path = PathBuf::from(format!("screenshot-{:06}.png", machine.frame()));
+    match machine
+        .display()
+        .save_screenshot(&path, SCREENSHOT_SCALE, palette)
+    {
+        Ok(()) => println!("Saved {}", path.display()),
+        Err(e) => eprintln!("Failed to save {}: {}", path.display(), e),
+    }
+}
+
+fn start_capture(base: &Path, palette: &[Rgb]) -> Option<Capture> {
+    match Capture::start(base, CAPTURE_SCALE, palette) {
+        Ok(capture) => {
+            println!("Capturing to {}.{{gif,y4m,wav}}", base.display());
+            Some(capture)
+        }
+        Err(e) => {
+            eprintln!("Failed to start capture at {}: {}", base.display(), e);
+            None
+        }
+    }
+}
+
+fn finish_capture(capture: Capture) {
+    let frames = capture.frames();
+    match capture.finish() {
+        Ok(()) => println!("Captured {} frames", frames),
+        Err(e) => eprintln!("Failed to finish capture: {}", e),
+    }
+}
+
+fn set_fullscreen(window: &mut PistonWindow, fullscreen: bool) {
+    let window = window.window.ctx.window();
+    let mode = fullscreen.then(|| glutin::window::Fullscreen::Borderless(window.current_monitor()));
+    window.set_fullscreen(mode);
+}
+
+fn set_title(window: &mut PistonWindow, rebinder: Option<&Rebinder>) {
+    window.set_title(rebinder.map_or(TITLE.to_string(), Rebinder::title));
+}
+
+// The CHIP-8 key `input` presses or releases, if the event should reach the
+// machine: only the first input to hold a key presses it, and only the last
+// to let go releases it
+fn bound_key(held: &mut HeldKeys, key_map: &KeyMap, input: Input, pressed: bool) -> Option<u8> {
+    let key = if pressed {
+        let key = key_map.lookup(input)?;
+        held.press(key, input).then_some(key)?
+    } else {
+        held.release(input)?
+    };
+    Some(key as u8)
+}
+
+fn boot_vip(interpreter: &str, monitor: Option<&str>, rom: &[u8]) -> Result<Vip, String> {
+    let read =
+        |path: &str| std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path, e));
+    let mut vip = Vip::new();
+    if let Some(monitor) = monitor {
+        vip.load_monitor(&read(monitor)?);
+    }
+    vip.try_load_interpreter(&read(interpreter)?)
+        .map_err(|e| format!("Failed to load {}: {}", interpreter, e))?;
+    vip.try_load_rom_bytes(rom).map_err(|e| e.to_string())?;
+    Ok(vip)
 }
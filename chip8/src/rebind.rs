@@ -0,0 +1,139 @@
+use piston_window::*;
+
+use crate::keymap::{Input, KeyMap, KEYPAD_LAYOUT};
+
+const BACKGROUND: [f32; 4] = [0.1, 0.1, 0.1, 1.0];
+const UNBOUND: [f32; 4] = [0.35, 0.2, 0.2, 1.0];
+const BOUND: [f32; 4] = [0.45, 0.45, 0.45, 1.0];
+const CURRENT: [f32; 4] = [1.0, 0.85, 0.95, 1.0];
+
+/// Walks through the keypad one CHIP-8 key at a time, collecting host inputs.
+///
+/// Pressing keyboard keys replaces the CHIP-8 key's keyboard bindings and
+/// pressing gamepad inputs its gamepad bindings. Return moves on, keeping
+/// whatever was not replaced.
+pub struct Rebinder {
+    map: KeyMap,
+    step: usize,
+    pressed: Vec<Input>,
+}
+
+impl Rebinder {
+    pub fn new(map: KeyMap) -> Self {
+        Rebinder {
+            map,
+            step: 0,
+            pressed: Vec::new(),
+        }
+    }
+
+    /// The CHIP-8 key being rebound, `None` once every key has been visited
+    pub fn current(&self) -> Option<usize> {
+        KEYPAD_LAYOUT.get(self.step).copied()
+    }
+
+    pub fn press(&mut self, input: Input) {
+        let Some(chip8_key) = self.current() else {
+            return;
+        };
+        if input == Input::Key(Key::Return) {
+            let pressed = std::mem::take(&mut self.pressed);
+            let mut inputs: Vec<Input> = self
+                .map
+                .bindings(chip8_key)
+                .iter()
+                .copied()
+                .filter(|old| !pressed.iter().any(|new| new.is_key() == old.is_key()))
+                .collect();
+            inputs.extend(pressed);
+            self.map.set_bindings(chip8_key, inputs);
+            self.step += 1;
+        } else if !self.pressed.contains(&input) {
+            self.pressed.push(input);
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.current().is_none()
+    }
+
+    pub fn into_map(self) -> KeyMap {
+        self.map
+    }
+
+    /// What to show in the window title while rebinding
+    pub fn title(&self) -> String {
+        let Some(chip8_key) = self.current() else {
+            return "Rebinding done".to_string();
+        };
+        let inputs = if self.pressed.is_empty() {
+            self.map.bindings(chip8_key)
+        } else {
+            &self.pressed
+        };
+        let names: Vec<String> = inputs.iter().map(Input::to_string).collect();
+        format!(
+            "Rebind CHIP-8 key {:X}: [{}] - press keys, Return for next, F1 to cancel",
+            chip8_key,
+            names.join(", ")
+        )
+    }
+
+    /// Draw the keypad, highlighting the key being rebound
+    pub fn draw(&self, window: &mut PistonWindow, e: &Event) {
+        let size = window.size();
+        let cell = (size.width / 4.0).min(size.height / 4.0);
+        window.draw_2d(e, |c, g, _| {
+            clear(BACKGROUND, g);
+            for (n, &chip8_key) in KEYPAD_LAYOUT.iter().enumerate() {
+                let color = if Some(chip8_key) == self.current() {
+                    CURRENT
+                } else if self.map.bindings(chip8_key).is_empty() {
+                    UNBOUND
+                } else {
+                    BOUND
+                };
+                let (col, row) = ((n % 4) as f64, (n / 4) as f64);
+                rectangle(
+                    color,
+                    [col * cell + 4.0, row * cell + 4.0, cell - 8.0, cell - 8.0],
+                    c.transform,
+                    g,
+                );
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn walks_the_keypad_in_layout_order() {
+        let mut rebinder = Rebinder::new(KeyMap::default());
+        assert_eq!(rebinder.current(), Some(0x1));
+
+        // Two keys for 1, a button for 2 on top of its key, keep 3 as it was
+        rebinder.press(Input::Key(Key::Up));
+        rebinder.press(Input::Key(Key::NumPad8));
+        rebinder.press(Input::Key(Key::Return));
+        rebinder.press(Input::Button(7));
+        rebinder.press(Input::Key(Key::Return));
+        rebinder.press(Input::Key(Key::Return));
+        assert_eq!(rebinder.current(), Some(0xC));
+
+        for _ in 3..16 {
+            assert!(!rebinder.is_done());
+            rebinder.press(Input::Key(Key::Return));
+        }
+        assert!(rebinder.is_done());
+        let map = rebinder.into_map();
+        assert_eq!(
+            map.bindings(0x1),
+            &[Input::Key(Key::Up), Input::Key(Key::NumPad8)]
+        );
+        assert_eq!(map.bindings(0x2), &[Input::Key(Key::D2), Input::Button(7)]);
+        assert_eq!(map.bindings(0x3), &[Input::Key(Key::D3)]);
+    }
+}
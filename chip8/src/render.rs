@@ -0,0 +1,115 @@
+use piston_window::texture::{CreateTexture, Format, UpdateTexture};
+use piston_window::*;
+
+use chip8_core::display::{Display, Rgb};
+
+const LETTERBOX: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
+/// Draws the framebuffer as a single texture, one texel per emulated pixel.
+/// Only the regions of the display that changed are uploaded again.
+pub struct ScreenRenderer {
+    context: G2dTextureContext,
+    texture: Option<G2dTexture>,
+    // Resolution and colours of what the texture holds
+    size: [usize; 2],
+    palette: Vec<Rgb>,
+}
+
+impl ScreenRenderer {
+    pub fn new(window: &mut PistonWindow) -> Self {
+        ScreenRenderer {
+            context: window.create_texture_context(),
+            texture: None,
+            size: [0, 0],
+            palette: Vec::new(),
+        }
+    }
+
+    /// Resolution of the screen being shown
+    pub fn size(&self) -> [usize; 2] {
+        self.size
+    }
+
+    /// Upload the parts of `display` that changed since the last upload
+    pub fn update(&mut self, display: &mut Display, palette: &[Rgb]) {
+        let size = [display.width(), display.height()];
+        let dirty = display.take_dirty_rects();
+        match &mut self.texture {
+            Some(texture) if size == self.size && palette == self.palette => {
+                for rect in dirty {
+                    let rgba = display
+                        .rect_to_rgba(rect, palette)
+                        .expect("palettes have at least two colours");
+                    let offset = [rect.x as u32, rect.y as u32];
+                    let extent = [rect.width as u32, rect.height as u32];
+                    // The inherent `update` wants an `image` buffer, the trait takes raw bytes
+                    UpdateTexture::update(
+                        texture,
+                        &mut self.context,
+                        Format::Rgba8,
+                        &rgba,
+                        offset,
+                        extent,
+                    )
+                    .expect("Failed to update the screen texture");
+                }
+            }
+            _ => {
+                // New resolution or colours: start over with the whole picture
+                let rgba = display
+                    .to_rgba(palette)
+                    .expect("palettes have at least two colours");
+                self.create(size, &rgba);
+                self.palette = palette.to_vec();
+            }
+        }
+    }
+
+    /// Upload a whole picture of packed RGBA made elsewhere, such as by a filter
+    pub fn upload(&mut self, size: [usize; 2], rgba: &[u8]) {
+        match &mut self.texture {
+            Some(texture) if size == self.size => {
+                let extent = [size[0] as u32, size[1] as u32];
+                UpdateTexture::update(
+                    texture,
+                    &mut self.context,
+                    Format::Rgba8,
+                    rgba,
+                    [0, 0],
+                    extent,
+                )
+                .expect("Failed to update the screen texture");
+            }
+            _ => self.create(size, rgba),
+        }
+        // The texture no longer shows the display as is, so the next `update`
+        // must redraw all of it
+        self.palette.clear();
+    }
+
+    fn create(&mut self, size: [usize; 2], rgba: &[u8]) {
+        let extent = [size[0] as u32, size[1] as u32];
+        let settings = TextureSettings::new().filter(Filter::Nearest);
+        let texture = Texture::create(&mut self.context, Format::Rgba8, rgba, extent, &settings)
+            .expect("Failed to create the screen texture");
+        self.texture = Some(texture);
+        self.size = size;
+    }
+
+    /// Draw the texture stretched over `area` (`[x, y, width, height]`),
+    /// leaving the rest of the window black
+    pub fn draw(&mut self, window: &mut PistonWindow, e: &Event, area: [f64; 4]) {
+        let Some(texture) = &self.texture else {
+            return;
+        };
+        let context = &mut self.context;
+        let [x, y, width, height] = area;
+        let scale = (width / self.size[0] as f64, height / self.size[1] as f64);
+        window.draw_2d(e, |c, g, device| {
+            // Send the pending texture upload before drawing with it
+            context.encoder.flush(device);
+            clear(LETTERBOX, g);
+            image(texture, c.transform.trans(x, y).scale(scale.0, scale.1), g);
+        });
+    }
+}